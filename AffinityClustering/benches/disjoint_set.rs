@@ -0,0 +1,135 @@
+use AffinityClustering::disjoint_set::DisjointSet;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use AffinityClustering::affinity_clustering::ClusteringError;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+
+//DisjointSet之前的ArrayUnion，原样照抄，包括每次union时clone整个items。
+//因此每次union都是O(n)，只在较小的规模下运行。
+#[allow(dead_code)]
+pub struct ArrayUnion<T> {
+    group: HashMap::<T, T>,
+    size: HashMap::<T, usize>,
+    items: HashMap::<T, Vec::<T>>,
+    order: Vec::<T>,//记录初始点的顺序，保证get_items的输出顺序固定
+}
+
+#[allow(dead_code)]
+impl<T:Debug + Display + Copy + Hash + Eq> ArrayUnion<T> {
+    fn new_and_init(v: Vec::<T>) -> Self {
+        let mut arr_uni = ArrayUnion {
+            group: HashMap::new(),
+            size: HashMap::new(),
+            items: HashMap::new(),
+            order: Vec::new(),
+        };
+        for v in v {
+            arr_uni.group.insert(v, v);
+            arr_uni.size.insert(v, 1);
+            arr_uni.items.insert(v, vec!(v));
+            arr_uni.order.push(v);
+        }
+        arr_uni
+    }
+
+    fn find(&self, target: T) -> Result<T, ClusteringError> {//返回包含target的group id
+        let mut gp = *self.group.get(&target).ok_or_else(|| ClusteringError::UnknownVertex(target.to_string()))?;
+        //union只更新了根的group，非根成员需要沿group链找到当前的根
+        while self.group[&gp] != gp {
+            gp = self.group[&gp];
+        }
+        Ok(gp)
+    }
+
+    fn union(&mut self, a: T, b: T) -> Result<(), ClusteringError> {
+        //a, b可以是任意成员，先找到各自的根
+        let mut a = self.find(a)?;
+        let mut b = self.find(b)?;
+        if a == b {
+            return Ok(());
+        }
+
+        if self.size[&a] > self.size[&b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+
+        if let Some(s) = self.items.clone().get_mut(&a) {
+            if let Some(x) = self.group.get_mut(&a) {
+                *x = b
+            } else {
+                panic!("Failed to get a from items a");
+            }
+
+            if let Some(x) = self.items.get_mut(&b) {
+                (*x).append(s);
+            } else {
+                panic!("Failed to get a from items b");
+            }
+        }
+
+        *self.size.get_mut(&b).unwrap() += self.size[&a];
+        self.size.remove(&a);
+        self.items.remove(&a);
+        Ok(())
+    }
+
+    fn get_items(&self) -> Vec::<T> {
+        //按初始点的顺序输出，而不是HashMap的随机顺序
+        self.order.iter().filter(|x| self.items.contains_key(x)).copied().collect()
+    }
+}
+
+const ARRAY_UNION_LIMIT: usize = 10_000;
+
+fn random_edges(n: usize, m: usize, seed: u64) -> Vec<(usize, usize, usize)> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut edges: Vec<(usize, usize, usize)> = (0..m)
+        .map(|_| (rng.gen_range(0, n), rng.gen_range(0, n), rng.gen_range(1, 1000)))
+        .collect();
+    edges.sort_by_key(|e| e.2);
+    edges
+}
+
+fn kruskal(c: &mut Criterion) {
+    let mut group = c.benchmark_group("kruskal");
+    group.sample_size(10);
+    for &n in &[1_000usize, 10_000, 1_000_000] {
+        let edges = random_edges(n, 4 * n, 1);
+        group.bench_with_input(BenchmarkId::new("disjoint_set", n), &edges, |b, edges| {
+            b.iter(|| {
+                let mut ds = DisjointSet::new(n);
+                let mut mst = 0usize;
+                for &(u, v, w) in edges {
+                    if ds.union(u, v).is_some() {
+                        mst += w;
+                    }
+                }
+                black_box(mst)
+            })
+        });
+        if n > ARRAY_UNION_LIMIT {
+            continue;
+        }
+        group.bench_with_input(BenchmarkId::new("array_union", n), &edges, |b, edges| {
+            b.iter(|| {
+                let mut uf = ArrayUnion::new_and_init((0..n).collect());
+                let mut mst = 0usize;
+                for &(u, v, w) in edges {
+                    //原来的union在两点已经连通时也返回Ok，需要先比较根
+                    if uf.find(u).unwrap() != uf.find(v).unwrap() {
+                        uf.union(u, v).unwrap();
+                        mst += w;
+                    }
+                }
+                black_box(mst)
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, kruskal);
+criterion_main!(benches);
@@ -0,0 +1,156 @@
+//聚类和划分流水线各阶段的基准：new_and_init、每一轮clustering、每一轮的edges_update、
+//common neighbor图的构造、RankSwap和DynamicProgram，图族为二维torus、Barabási–Albert和
+//均匀随机图，规模为10^3到10^6个点。
+//
+//结果由criterion保存在target/criterion下，在两个commit之间比较：
+//  cargo bench --bench pipeline -- --save-baseline before
+//  （切换到另一个commit）
+//  cargo bench --bench pipeline -- --baseline before
+//BENCH_SIZES可以只跑部分规模，例如BENCH_SIZES=1000,10000；criterion的过滤参数可以只跑部分阶段，
+//例如cargo bench --bench pipeline -- dynamic_program。
+use AffinityClustering::affinity_clustering::{Affinity, ClusteringConfig, Edge};
+use AffinityClustering::generators::{barabasi_albert, torus, Topology, WeightNoise, Weights};
+use AffinityClustering::graph::Graph;
+use AffinityClustering::linkage::Min;
+use AffinityClustering::neighborhood::{common_neighbors, NeighborhoodOptions};
+use AffinityClustering::partition::{dynamic_program, even_bounds, partition, rank_swap, PartitionOptions, RankSwapOptions};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::time::{Duration, Instant};
+
+const ROUNDS: u32 = 3;//只测前几轮，之后边数很少
+const PARTS: usize = 16;
+const MAX_SHARED: usize = 64;//common neighbor图对hub的上限
+const UNCAPPED_LIMIT: usize = 100_000;//不限制hub时，更大的图产生的点对放不进内存
+
+struct Family {
+    name: &'static str,
+    graph: Graph<usize>,
+    edges: Vec<Edge<usize>>,
+}
+
+fn sizes() -> Vec<usize> {
+    match std::env::var("BENCH_SIZES") {
+        Ok(list) => list.split(',').map(|s| s.trim().parse().expect("BENCH_SIZES is a comma-separated list of vertex counts")).collect(),
+        Err(_) => vec![1_000, 10_000, 100_000, 1_000_000],
+    }
+}
+
+//每个点平均连出4条无向边
+fn uniform<R: Rng>(n: usize, weights: &Weights, rng: &mut R) -> Topology {
+    let vertex_weights = (0..n).map(|_| weights.node.sample(rng)).collect();
+    let mut edges = Vec::with_capacity(8 * n);
+    for _ in 0..4 * n {
+        let (a, b) = (rng.gen_range(0, n), rng.gen_range(0, n));
+        let weight = weights.edge.sample(rng);
+        edges.push(Edge { start: a, end: b, weight });
+        edges.push(Edge { start: b, end: a, weight });
+    }
+    Topology { edges, vertex_weights, communities: None }
+}
+
+fn families(n: usize) -> Vec<Family> {
+    let weights = Weights { node: WeightNoise::constant(1), edge: WeightNoise { low: 1, high: 100, noise_weight: 1, noise_rate: 0 } };
+    let side = (n as f64).sqrt().round() as usize;
+    let mut rng = StdRng::seed_from_u64(2021);
+    let topologies = vec![
+        ("torus", torus(&[side, side], &weights, &mut rng)),
+        ("barabasi_albert", barabasi_albert(n, 3, &weights, &mut rng)),
+        ("uniform", uniform(n, &weights, &mut rng)),
+    ];
+    topologies.into_iter().map(|(name, t)| Family { name, graph: t.graph(), edges: t.edges }).collect()
+}
+
+//从new_and_init开始聚类到第round轮，返回该轮的用时和其中edges_update的用时；没有这一轮时返回None
+fn round_times(graph: &Graph<usize>, round: u32) -> Option<(Duration, Duration)> {
+    let mut af = Affinity::new_and_init(graph, 1).unwrap();
+    let config = ClusteringConfig { max_rounds: round, ..Default::default() };
+    let mut stats = Vec::new();
+    af.clustering_with_config(&config, &Min, |s| stats.push(*s)).unwrap();
+    let last = stats.get(round as usize - 1)?;
+    let previous = if round == 1 { Duration::ZERO } else { stats[round as usize - 2].elapsed };
+    Some((last.elapsed - previous, last.contraction))
+}
+
+fn clustering(c: &mut Criterion) {
+    for n in sizes() {
+        for family in families(n) {
+            let mut group = c.benchmark_group(format!("clustering/{}", family.name));
+            group.sample_size(10);
+            group.throughput(Throughput::Elements(family.edges.len() as u64));
+            group.bench_with_input(BenchmarkId::new("new_and_init", n), &family.graph, |b, graph| {
+                b.iter(|| black_box(Affinity::new_and_init(graph, 1).unwrap()))
+            });
+            for round in 1..=ROUNDS {
+                if round_times(&family.graph, round).is_none() {
+                    break;
+                }
+                //clustering_with_config从头开始计时，用iter_custom只累加第round轮本身
+                group.bench_with_input(BenchmarkId::new(format!("round_{}", round), n), &family.graph, |b, graph| {
+                    b.iter_custom(|iters| (0..iters).map(|_| round_times(graph, round).unwrap().0).sum())
+                });
+                group.bench_with_input(BenchmarkId::new(format!("edges_update_{}", round), n), &family.graph, |b, graph| {
+                    b.iter_custom(|iters| (0..iters).map(|_| round_times(graph, round).unwrap().1).sum())
+                });
+            }
+            group.finish();
+        }
+    }
+}
+
+fn neighbors(c: &mut Criterion) {
+    let capped = NeighborhoodOptions { max_shared: Some(MAX_SHARED), ..Default::default() };
+    for n in sizes() {
+        for family in families(n) {
+            let mut group = c.benchmark_group(format!("common_neighbors/{}", family.name));
+            group.sample_size(10);
+            group.throughput(Throughput::Elements(family.edges.len() as u64));
+            if n <= UNCAPPED_LIMIT {
+                group.bench_with_input(BenchmarkId::new("streamed", n), &family.edges, |b, edges| {
+                    b.iter(|| black_box(common_neighbors(edges, &NeighborhoodOptions::default())))
+                });
+            }
+            group.bench_with_input(BenchmarkId::new(format!("streamed_capped_{}", MAX_SHARED), n), &family.edges, |b, edges| {
+                b.iter(|| black_box(common_neighbors(edges, &capped)))
+            });
+            group.finish();
+        }
+    }
+}
+
+fn partitioning(c: &mut Criterion) {
+    for n in sizes() {
+        for family in families(n) {
+            //不做RankSwap时order就是embedding得到的line
+            let options = PartitionOptions { common_neighbors: false, cluster_threshold: PARTS, ..Default::default() };
+            let start = Instant::now();
+            let line = partition(&family.graph, PARTS, &options).unwrap().order().to_vec();
+            eprintln!("{}/{}: embedding took {:?}", family.name, n, start.elapsed());
+            let bounds = even_bounds(line.len(), PARTS);
+            let intervals = ((line.len() / PARTS) as f64).sqrt() as usize;
+            let node_weight = |v: &usize| family.graph.id(v).map_or(0, |id| family.graph.vertex_weight(id));
+
+            let mut group = c.benchmark_group(format!("partition/{}", family.name));
+            group.sample_size(10);
+            group.throughput(Throughput::Elements(line.len() as u64));
+            let modes = [
+                ("near", RankSwapOptions::Near { intervals }),
+                ("rank", RankSwapOptions::Rank { intervals }),
+                ("greedy", RankSwapOptions::Greedy { intervals, partners: 2 }),
+            ];
+            for (name, mode) in &modes {
+                group.bench_with_input(BenchmarkId::new(format!("rank_swap_{}", name), n), &line, |b, line| {
+                    b.iter_batched(|| StdRng::seed_from_u64(1), |mut rng| black_box(rank_swap(line, &bounds, mode, node_weight, &mut rng)), BatchSize::SmallInput)
+                });
+            }
+            group.bench_with_input(BenchmarkId::new("dynamic_program", n), &line, |b, line| {
+                b.iter(|| black_box(dynamic_program(&family.graph, line, PARTS).unwrap()))
+            });
+            group.finish();
+        }
+    }
+}
+
+criterion_group!(benches, clustering, neighbors, partitioning);
+criterion_main!(benches);
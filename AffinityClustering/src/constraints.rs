@@ -0,0 +1,164 @@
+//! Must-link, cannot-link and capacity constraints on clustering merges.
+//!
+//! `Affinity::set_constraints` merges every must-link pair up front and from
+//! then on every merge, in clustering, fragment processing, the exact-k pass
+//! and incremental updates, asks the constraints first. A merge is refused
+//! when it would put a cannot-link pair into one cluster or push the cluster
+//! weight above the capacity. Must-links that cannot be honoured for the same
+//! reasons are left apart and show up in the `ConstraintReport`.
+
+use crate::disjoint_set::DisjointSet;
+use crate::weight::Weight;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Constraints<T, W = usize> {
+    pub must_link: Vec<(T, T)>,
+    pub cannot_link: Vec<(T, T)>,
+    pub max_cluster_weight: Option<W>,
+}
+
+impl<T, W> Default for Constraints<T, W> {
+    fn default() -> Self {
+        Constraints { must_link: Vec::new(), cannot_link: Vec::new(), max_cluster_weight: None }
+    }
+}
+
+/// Constraints the current clustering does not satisfy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ConstraintReport<T, W = usize> {
+    pub split_must_links: Vec<(T, T)>,//没能放进同一个cluster的must-link
+    pub joined_cannot_links: Vec<(T, T)>,//在同一个cluster中的cannot-link
+    pub oversized: Vec<(T, W)>,//点权和超过上限的cluster（名字, 点权和），例如单个点就超过上限
+}
+
+impl<T, W> ConstraintReport<T, W> {
+    pub fn is_satisfied(&self) -> bool {
+        self.split_must_links.is_empty() && self.joined_cannot_links.is_empty() && self.oversized.is_empty()
+    }
+}
+
+//以稠密编号表示的约束，随Affinity一起保存
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub(crate) struct ConstraintSet<W> {
+    pub(crate) must_link: Vec<(usize, usize)>,
+    pub(crate) cannot_link: Vec<(usize, usize)>,
+    cannot: Vec<Vec<usize>>,//cannot[v]为不能与v在同一个cluster的点，没有约束时为空
+    constrained: Vec<Vec<usize>>,//以根为下标，cluster中带有cannot-link的点
+    pub(crate) max_cluster_weight: Option<W>,
+}
+
+impl<W> Default for ConstraintSet<W> {
+    fn default() -> Self {
+        ConstraintSet { must_link: Vec::new(), cannot_link: Vec::new(), cannot: Vec::new(), constrained: Vec::new(), max_cluster_weight: None }
+    }
+}
+
+impl<W: Weight> ConstraintSet<W> {
+    pub(crate) fn new(n: usize, must_link: Vec<(usize, usize)>, cannot_link: Vec<(usize, usize)>, max_cluster_weight: Option<W>) -> Self {
+        let mut cannot = vec![Vec::new(); n];
+        for &(a, b) in &cannot_link {
+            cannot[a].push(b);
+            cannot[b].push(a);
+        }
+        let mut set = ConstraintSet { must_link, cannot_link, cannot, constrained: Vec::new(), max_cluster_weight };
+        set.reset(n);
+        set
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.must_link.is_empty() && self.cannot_link.is_empty() && self.max_cluster_weight.is_none()
+    }
+
+    //并查集重建为单点时调用
+    pub(crate) fn reset(&mut self, n: usize) {
+        self.constrained = if self.cannot_link.is_empty() {
+            Vec::new()
+        } else {
+            (0..n).map(|v| if self.cannot[v].is_empty() { Vec::new() } else { vec![v] }).collect()
+        };
+    }
+
+    //根a、b所在的cluster合并后是否仍满足cannot-link和容量
+    pub(crate) fn allows(&self, uf: &DisjointSet, cluster_weight: &[W], a: usize, b: usize) -> bool {
+        if self.max_cluster_weight.is_some_and(|cap| cluster_weight[a] + cluster_weight[b] > cap) {
+            return false;
+        }
+        if self.constrained.is_empty() {
+            return true;
+        }
+        let (small, other) = if self.constrained[a].len() <= self.constrained[b].len() { (a, b) } else { (b, a) };
+        !self.constrained[small].iter().any(|&x| self.cannot[x].iter().any(|&y| uf.root(y) == other))
+    }
+
+    //根a、b合并为root之后调用
+    pub(crate) fn merged(&mut self, root: usize, a: usize, b: usize) {
+        if self.constrained.is_empty() {
+            return;
+        }
+        let other = if root == a { b } else { a };
+        let moved = std::mem::take(&mut self.constrained[other]);
+        self.constrained[root].extend(moved);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::affinity_clustering::{Affinity, ClusteringConfig, Edge, EdgeChange};
+    use crate::constraints::Constraints;
+    use crate::graph::Graph;
+    use crate::linkage::Min;
+
+    fn edge(start: usize, end: usize, weight: usize) -> Edge<usize> {
+        Edge { start, end, weight }
+    }
+
+    //路径0-1-...-9，边(i, i+1)的权重为i+1，不加约束时合并为一个cluster
+    fn path() -> Graph<usize> {
+        let edges: Vec<Edge<usize>> = (0..9).flat_map(|i| vec![edge(i, i + 1, i + 1), edge(i + 1, i, i + 1)]).collect();
+        Graph::from_edges(&edges).unwrap()
+    }
+
+    #[test]
+    fn cannot_link_and_capacity_are_never_violated() {
+        let mut af = Affinity::new_and_init(&path(), 1).unwrap();
+        let constraints = Constraints { must_link: vec![(0, 9)], cannot_link: vec![(2, 7)], max_cluster_weight: Some(6) };
+        let report = af.set_constraints(&constraints).unwrap();
+        assert!(report.is_satisfied());
+        let config = ClusteringConfig { max_rounds: 20, exact_k: true, fragment_process: true, ..Default::default() };
+        af.clustering_with_config(&config, &Min, |_| {}).unwrap();
+        assert_eq!(af.find(0), af.find(9));
+        assert_ne!(af.find(2), af.find(7));
+        assert!(af.clusters().iter().all(|c| c.len() <= 6));
+        assert!(af.constraint_report().is_satisfied());
+    }
+
+    #[test]
+    fn conflicting_constraints_are_reported() {
+        let mut af = Affinity::new_and_init(&path().with_vertex_weights(|&v| if v == 5 { 9 } else { 1 }).unwrap(), 1).unwrap();
+        let constraints = Constraints { must_link: vec![(0, 1), (1, 2), (0, 2), (3, 4)], cannot_link: vec![(0, 2)], max_cluster_weight: Some(4) };
+        let report = af.set_constraints(&constraints).unwrap();
+        assert_eq!(report.split_must_links, vec![(1, 2), (0, 2)]);
+        assert!(report.joined_cannot_links.is_empty());
+        assert_eq!(report.oversized, vec![(5, 9)]);
+        assert_eq!(af.find(0), af.find(1));
+        assert_eq!(af.find(3), af.find(4));
+        assert_eq!(af.set_constraints(&Constraints { must_link: vec![(0, 11)], ..Default::default() }).err().map(|e| e.to_string()), Some("unknown vertex 11".to_string()));
+    }
+
+    #[test]
+    fn must_links_survive_incremental_changes() {
+        let mut af = Affinity::new_and_init(&path(), 2).unwrap();
+        af.set_constraints(&Constraints { must_link: vec![(4, 5)], cannot_link: vec![(0, 9)], max_cluster_weight: None }).unwrap();
+        af.clustering_with_config(&ClusteringConfig { exact_k: true, ..Default::default() }, &Min, |_| {}).unwrap();
+        let changes = vec![EdgeChange::Delete { start: 4, end: 5 }, EdgeChange::Delete { start: 5, end: 4 }];
+        af.apply_changes(&changes, false).unwrap();
+        assert_eq!(af.find(4), af.find(5));
+        assert_ne!(af.find(0), af.find(9));
+        assert!(af.constraint_report().is_satisfied());
+    }
+}
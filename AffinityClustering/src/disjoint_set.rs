@@ -0,0 +1,155 @@
+//! Disjoint-set forest over dense `usize` ids.
+//!
+//! Union by size, path halving in `find`, and a circular `next` list per set
+//! so the members of a set can be walked without keeping a `Vec` per cluster.
+//! Ids are `0..len()`; passing an id outside that range panics like slice
+//! indexing does.
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DisjointSet {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+    next: Vec<usize>,//每个集合的成员组成一个环形链表，union时O(1)拼接
+    sets: usize,
+}
+
+impl DisjointSet {
+    pub fn new(n: usize) -> Self {
+        DisjointSet {
+            parent: (0..n).collect(),
+            size: vec![1; n],
+            next: (0..n).collect(),
+            sets: n,
+        }
+    }
+
+    /// Number of elements.
+    pub fn len(&self) -> usize {
+        self.parent.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parent.is_empty()
+    }
+
+    /// Number of disjoint sets.
+    pub fn set_count(&self) -> usize {
+        self.sets
+    }
+
+    /// Root of `x`, compressing the path on the way.
+    pub fn find(&mut self, mut x: usize) -> usize {
+        while self.parent[x] != x {
+            let grand_parent = self.parent[self.parent[x]];
+            self.parent[x] = grand_parent;
+            x = grand_parent;
+        }
+        x
+    }
+
+    /// Root of `x` without modifying the forest.
+    pub fn root(&self, mut x: usize) -> usize {
+        while self.parent[x] != x {
+            x = self.parent[x];
+        }
+        x
+    }
+
+    pub fn is_root(&self, x: usize) -> bool {
+        self.parent[x] == x
+    }
+
+    /// Merges the sets of `a` and `b`, returning the new root, or `None` if
+    /// they were already in the same set. On equal sizes `a`'s root is kept.
+    pub fn union(&mut self, a: usize, b: usize) -> Option<usize> {
+        let mut a = self.find(a);
+        let mut b = self.find(b);
+        if a == b {
+            return None;
+        }
+        if self.size[a] < self.size[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parent[b] = a;
+        self.size[a] += self.size[b];
+        self.next.swap(a, b);
+        self.sets -= 1;
+        Some(a)
+    }
+
+    pub fn same_set(&mut self, a: usize, b: usize) -> bool {
+        self.find(a) == self.find(b)
+    }
+
+    /// Size of the set containing `x`.
+    pub fn size_of(&mut self, x: usize) -> usize {
+        let r = self.find(x);
+        self.size[r]
+    }
+
+    /// Current roots in increasing id order.
+    pub fn roots(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.parent.len()).filter(move |&x| self.parent[x] == x)
+    }
+
+    /// Members of the set containing `x`, starting with `x`.
+    pub fn members(&self, x: usize) -> Members<'_> {
+        Members { set: self, start: x, cur: Some(x) }
+    }
+}
+
+pub struct Members<'a> {
+    set: &'a DisjointSet,
+    start: usize,
+    cur: Option<usize>,
+}
+
+impl Iterator for Members<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let x = self.cur?;
+        let nx = self.set.next[x];
+        self.cur = if nx == self.start { None } else { Some(nx) };
+        Some(x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DisjointSet;
+
+    #[test]
+    fn union_and_members() {
+        let mut ds = DisjointSet::new(6);
+        assert_eq!(ds.union(0, 1), Some(0));
+        assert_eq!(ds.union(2, 3), Some(2));
+        assert_eq!(ds.union(3, 1), Some(2));
+        assert_eq!(ds.union(0, 2), None);
+        assert_eq!(ds.set_count(), 3);
+        assert_eq!(ds.size_of(3), 4);
+        assert!(ds.same_set(1, 3));
+        assert!(!ds.same_set(1, 4));
+
+        let mut members: Vec<usize> = ds.members(1).collect();
+        members.sort_unstable();
+        assert_eq!(members, vec![0, 1, 2, 3]);
+        assert_eq!(ds.members(5).collect::<Vec<_>>(), vec![5]);
+        assert_eq!(ds.roots().collect::<Vec<_>>(), vec![2, 4, 5]);
+    }
+
+    #[test]
+    fn find_compresses_paths() {
+        let mut ds = DisjointSet::new(4);
+        ds.union(1, 0);
+        ds.union(2, 3);
+        ds.union(1, 2);
+        let root = ds.find(3);
+        assert_eq!(ds.root(3), root);
+        assert!(ds.is_root(root));
+    }
+}
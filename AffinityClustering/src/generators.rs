@@ -0,0 +1,378 @@
+//! Seeded generators for datacenter topologies and random graph families.
+//!
+//! Every generator numbers its vertices `0..n`, lists each undirected link in
+//! both directions, and draws all vertex weights (in id order) before any
+//! edge weight, so the same rng seed always gives the same graph. Weights
+//! come from `WeightNoise`: uniform in a range, except for a given percentage
+//! of outliers with a fixed weight.
+//!
+//! Generators with an obvious community structure (fat-tree pods, leaf-spine
+//! racks, dragonfly groups, SBM blocks) also return it as the planted
+//! communities, to compare clusterings against with `metrics::compare`.
+
+use crate::affinity_clustering::Edge;
+use crate::graph::Graph;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+/// Weights uniform in `[low, high)`, except that `noise_rate` percent of
+/// them are `noise_weight`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WeightNoise {
+    pub low: usize,
+    pub high: usize,
+    pub noise_weight: usize,
+    pub noise_rate: u32,
+}
+
+impl WeightNoise {
+    /// Every weight is `w`; sampling does not touch the rng.
+    pub fn constant(w: usize) -> Self {
+        WeightNoise { low: w, high: w + 1, noise_weight: w, noise_rate: 0 }
+    }
+
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> usize {
+        if self.noise_rate == 0 && self.high <= self.low + 1 {
+            self.low
+        } else if rng.gen_ratio(self.noise_rate.min(100), 100) {
+            self.noise_weight
+        } else if self.high > self.low + 1 {
+            rng.gen_range(self.low, self.high)
+        } else {
+            self.low
+        }
+    }
+}
+
+impl Default for WeightNoise {
+    fn default() -> Self {
+        WeightNoise::constant(1)
+    }
+}
+
+/// Weight distributions for the vertices and the links of a generated graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Weights {
+    pub node: WeightNoise,
+    pub edge: WeightNoise,
+}
+
+#[derive(Clone)]
+pub struct Topology {
+    pub edges: Vec<Edge<usize>>,
+    pub vertex_weights: Vec<usize>,//vertex_weights[v]为点v的权重
+    pub communities: Option<Vec<usize>>,//communities[v]为点v所在的planted community
+}
+
+impl Topology {
+    pub fn vertex_count(&self) -> usize {
+        self.vertex_weights.len()
+    }
+
+    /// The graph with the generated vertex weights. Isolated vertices, which
+    /// only the random families produce, have no edge and are left out.
+    pub fn graph(&self) -> Graph<usize> {
+        let graph = Graph::from_edges(&self.edges).expect("usize weights are never negative");
+        graph.with_vertex_weights(|&v| self.vertex_weights[v]).expect("usize weights are never negative")
+    }
+
+    /// The planted communities as lists of vertices, ordered by community.
+    pub fn planted(&self) -> Option<Vec<Vec<usize>>> {
+        let communities = self.communities.as_ref()?;
+        let mut parts = vec![Vec::new(); communities.iter().map(|&c| c + 1).max().unwrap_or(0)];
+        for (v, &c) in communities.iter().enumerate() {
+            parts[c].push(v);
+        }
+        Some(parts)
+    }
+}
+
+//先按编号抽取所有点权，之后每条无向边抽取一次边权，两个方向使用相同的权重
+struct Builder<'a, R: ?Sized> {
+    edges: Vec<Edge<usize>>,
+    vertex_weights: Vec<usize>,
+    edge_weight: WeightNoise,
+    rng: &'a mut R,
+}
+
+impl<'a, R: Rng + ?Sized> Builder<'a, R> {
+    fn new(n: usize, weights: &Weights, rng: &'a mut R) -> Self {
+        let vertex_weights = (0..n).map(|_| weights.node.sample(rng)).collect();
+        Builder { edges: Vec::new(), vertex_weights, edge_weight: weights.edge, rng }
+    }
+
+    fn link(&mut self, a: usize, b: usize) {
+        let weight = self.edge_weight.sample(self.rng);
+        self.edges.push(Edge { start: a, end: b, weight });
+        self.edges.push(Edge { start: b, end: a, weight });
+    }
+
+    fn finish(self, communities: Option<Vec<usize>>) -> Topology {
+        Topology { edges: self.edges, vertex_weights: self.vertex_weights, communities }
+    }
+}
+
+/// k-ary fat-tree: `k²/4` core switches, `k` pods of `k/2` aggregation and
+/// `k/2` access switches, and `k/2` servers under every access switch.
+/// Vertices are numbered core first, then each pod's aggregation and access
+/// switches, then the servers pod by pod. The planted communities are the
+/// pods with their servers; the core switches are spread evenly over them.
+pub fn fat_tree<R: Rng + ?Sized>(k: usize, weights: &Weights, rng: &mut R) -> Topology {
+    let half = k / 2;
+    let core = k * k / 4;
+    let pod_base = |pod: usize| core + pod * 2 * half;
+    let server_base = core + k * 2 * half;
+    let mut b = Builder::new(server_base + k * half * half, weights, rng);
+    for i in 0..core {
+        for pod in 0..k {
+            b.link(i, pod_base(pod) + i * 2 / k);
+        }
+    }
+    for pod in 0..k {
+        for aggregation in 0..half {
+            for access in 0..half {
+                b.link(pod_base(pod) + aggregation, pod_base(pod) + half + access);
+            }
+        }
+    }
+    for pod in 0..k {
+        for access in 0..half {
+            for server in 0..half {
+                b.link(pod_base(pod) + half + access, server_base + (pod * half + access) * half + server);
+            }
+        }
+    }
+    let pods = (0..core)
+        .map(|i| i * k / core)
+        .chain((0..k).flat_map(|pod| std::iter::repeat_n(pod, 2 * half)))
+        .chain((0..k).flat_map(|pod| std::iter::repeat_n(pod, half * half)))
+        .collect();
+    b.finish(Some(pods))
+}
+
+/// Two-tier folded Clos: every leaf links to every spine and `hosts` hosts
+/// hang off each leaf. Vertices are spines, then leaves, then hosts. The
+/// planted communities are the racks, a leaf with its hosts; the spines are
+/// spread evenly over them.
+pub fn leaf_spine<R: Rng + ?Sized>(spines: usize, leaves: usize, hosts: usize, weights: &Weights, rng: &mut R) -> Topology {
+    let mut b = Builder::new(spines + leaves * (1 + hosts), weights, rng);
+    for leaf in 0..leaves {
+        for spine in 0..spines {
+            b.link(spine, spines + leaf);
+        }
+    }
+    for leaf in 0..leaves {
+        for host in 0..hosts {
+            b.link(spines + leaf, spines + leaves + leaf * hosts + host);
+        }
+    }
+    let racks = (0..spines)
+        .map(|s| s * leaves / spines)
+        .chain(0..leaves)
+        .chain((0..leaves).flat_map(|leaf| std::iter::repeat_n(leaf, hosts)))
+        .collect();
+    b.finish(Some(racks))
+}
+
+/// Torus with the given side lengths (two for 2D, three for 3D, any number
+/// works). Vertex ids are row-major coordinates. A side of length 2 gets a
+/// single link instead of a doubled one, and a side of length 1 none.
+pub fn torus<R: Rng + ?Sized>(dims: &[usize], weights: &Weights, rng: &mut R) -> Topology {
+    let n = dims.iter().product();
+    let mut b = Builder::new(n, weights, rng);
+    for v in 0..n {
+        let mut stride = 1;
+        for &side in dims.iter().rev() {
+            let x = v / stride % side;
+            //每个点只连向每一维的下一个坐标，边长为2时下一个与上一个相同，只连一次
+            if side > 2 || (side == 2 && x == 0) {
+                b.link(v, v - x * stride + (x + 1) % side * stride);
+            }
+            stride *= side;
+        }
+    }
+    b.finish(None)
+}
+
+/// Dragonfly: `groups` groups of `routers` fully connected routers with
+/// `hosts` hosts each, and one global link between every pair of groups.
+/// Each group spreads its global links over its routers round-robin.
+/// Vertices are routers group by group, then hosts router by router. The
+/// planted communities are the groups with their hosts.
+pub fn dragonfly<R: Rng + ?Sized>(groups: usize, routers: usize, hosts: usize, weights: &Weights, rng: &mut R) -> Topology {
+    let total_routers = groups * routers;
+    let mut b = Builder::new(total_routers * (1 + hosts), weights, rng);
+    for g in 0..groups {
+        for r1 in 0..routers {
+            for r2 in r1 + 1..routers {
+                b.link(g * routers + r1, g * routers + r2);
+            }
+        }
+    }
+    let mut port = vec![0; groups];//每个group已经用掉的全局链路数
+    for g1 in 0..groups {
+        for g2 in g1 + 1..groups {
+            b.link(g1 * routers + port[g1] % routers, g2 * routers + port[g2] % routers);
+            port[g1] += 1;
+            port[g2] += 1;
+        }
+    }
+    for r in 0..total_routers {
+        for host in 0..hosts {
+            b.link(r, total_routers + r * hosts + host);
+        }
+    }
+    let group_of = (0..total_routers).map(|r| r / routers).chain((0..total_routers * hosts).map(|h| h / hosts / routers)).collect();
+    b.finish(Some(group_of))
+}
+
+/// Erdős–Rényi G(n, p): every pair of vertices is linked with probability `p`.
+pub fn erdos_renyi<R: Rng + ?Sized>(n: usize, p: f64, weights: &Weights, rng: &mut R) -> Topology {
+    let mut b = Builder::new(n, weights, rng);
+    for i in 0..n {
+        for j in i + 1..n {
+            if b.rng.gen_bool(p.clamp(0.0, 1.0)) {
+                b.link(i, j);
+            }
+        }
+    }
+    b.finish(None)
+}
+
+/// Barabási–Albert preferential attachment: starts from a clique on `m + 1`
+/// vertices, then every new vertex links to `m` distinct earlier vertices
+/// picked with probability proportional to their degree.
+pub fn barabasi_albert<R: Rng + ?Sized>(n: usize, m: usize, weights: &Weights, rng: &mut R) -> Topology {
+    let m = m.max(1);
+    let mut b = Builder::new(n, weights, rng);
+    let mut ends = Vec::<usize>::new();//每条边的两个端点各出现一次，均匀抽取即按度数抽取
+    for i in 0..n.min(m + 1) {
+        for j in 0..i {
+            b.link(j, i);
+            ends.push(i);
+            ends.push(j);
+        }
+    }
+    for v in m + 1..n {
+        let mut targets = Vec::with_capacity(m);
+        while targets.len() < m {
+            let t = *ends.choose(b.rng).unwrap();
+            if !targets.contains(&t) {
+                targets.push(t);
+            }
+        }
+        for t in targets {
+            b.link(t, v);
+            ends.push(v);
+            ends.push(t);
+        }
+    }
+    b.finish(None)
+}
+
+/// Stochastic block model: blocks of the given sizes, numbered consecutively;
+/// two vertices are linked with probability `p_in` inside a block and
+/// `p_out` across blocks. The planted communities are the blocks.
+pub fn stochastic_block_model<R: Rng + ?Sized>(sizes: &[usize], p_in: f64, p_out: f64, weights: &Weights, rng: &mut R) -> Topology {
+    let block: Vec<usize> = sizes.iter().enumerate().flat_map(|(i, &s)| std::iter::repeat_n(i, s)).collect();
+    let n = block.len();
+    let mut b = Builder::new(n, weights, rng);
+    for i in 0..n {
+        for j in i + 1..n {
+            let p = if block[i] == block[j] { p_in } else { p_out };
+            if b.rng.gen_bool(p.clamp(0.0, 1.0)) {
+                b.link(i, j);
+            }
+        }
+    }
+    b.finish(Some(block))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::collections::HashSet;
+
+    fn degrees(t: &Topology) -> Vec<usize> {
+        let mut degree = vec![0; t.vertex_count()];
+        for e in &t.edges {
+            degree[e.start] += 1;
+        }
+        degree
+    }
+
+    //每条边两个方向都在，权重相同，没有自环和重边
+    fn assert_simple_undirected(t: &Topology) {
+        let arcs: HashSet<(usize, usize, usize)> = t.edges.iter().map(|e| (e.start, e.end, e.weight)).collect();
+        assert_eq!(arcs.len(), t.edges.len());
+        assert!(t.edges.iter().all(|e| e.start != e.end && arcs.contains(&(e.end, e.start, e.weight))));
+    }
+
+    #[test]
+    fn datacenter_shapes_have_the_expected_size() {
+        let w = Weights::default();
+        let mut rng = StdRng::seed_from_u64(0);
+        let ft = fat_tree(4, &w, &mut rng);
+        assert_eq!((ft.vertex_count(), ft.edges.len()), (36, 96));
+        assert_eq!(degrees(&ft)[..4], [4, 4, 4, 4]);
+        let ls = leaf_spine(2, 4, 3, &w, &mut rng);
+        assert_eq!((ls.vertex_count(), ls.edges.len()), (18, 2 * (8 + 12)));
+        let t = torus(&[4, 3, 2], &w, &mut rng);
+        assert_eq!(t.vertex_count(), 24);
+        assert!(degrees(&t).iter().all(|&d| d == 2 + 2 + 1));
+        let df = dragonfly(5, 4, 2, &w, &mut rng);
+        assert_eq!(df.vertex_count(), 20 + 40);
+        assert_eq!(df.edges.len(), 2 * (5 * 6 + 10 + 40));
+        for t in &[&ft, &ls, &t, &df] {
+            assert_simple_undirected(t);
+        }
+
+        //每个pod有4个交换机、4台服务器，4个核心交换机每个pod分到一个
+        let pods = ft.planted().unwrap();
+        assert_eq!(pods.len(), 4);
+        assert!(pods.iter().all(|p| p.len() == 9));
+        assert_eq!(pods[1][..2], [1, 8]);
+        assert_eq!(ls.planted().unwrap()[3], vec![5, 15, 16, 17]);
+        assert!(t.communities.is_none());
+        assert_eq!(df.planted().unwrap()[4], vec![16, 17, 18, 19, 52, 53, 54, 55, 56, 57, 58, 59]);
+    }
+
+    #[test]
+    fn random_families() {
+        let w = Weights::default();
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(erdos_renyi(10, 1.0, &w, &mut rng).edges.len(), 90);
+        assert!(erdos_renyi(10, 0.0, &w, &mut rng).edges.is_empty());
+        let ba = barabasi_albert(50, 3, &w, &mut rng);
+        assert_eq!(ba.edges.len(), 2 * (6 + 3 * 46));
+        assert!(degrees(&ba).iter().all(|&d| d >= 3));
+        let sbm = stochastic_block_model(&[5, 7], 1.0, 0.0, &w, &mut rng);
+        assert_eq!(sbm.edges.len(), 2 * (10 + 21));
+        assert!(sbm.edges.iter().all(|e| (e.start < 5) == (e.end < 5)));
+        assert_eq!(sbm.planted(), Some(vec![(0..5).collect(), (5..12).collect()]));
+        for t in &[ba, sbm] {
+            assert_simple_undirected(t);
+        }
+    }
+
+    #[test]
+    fn same_seed_same_topology_and_noise() {
+        let w = Weights {
+            node: WeightNoise { low: 3, high: 6, noise_weight: 100, noise_rate: 10 },
+            edge: WeightNoise { low: 1, high: 10, noise_weight: 50, noise_rate: 5 },
+        };
+        let a = dragonfly(4, 3, 2, &w, &mut StdRng::seed_from_u64(9));
+        let b = dragonfly(4, 3, 2, &w, &mut StdRng::seed_from_u64(9));
+        assert_eq!(a.vertex_weights, b.vertex_weights);
+        let arcs = |t: &Topology| t.edges.iter().map(|e| (e.start, e.end, e.weight)).collect::<Vec<_>>();
+        assert_eq!(arcs(&a), arcs(&b));
+        assert!(a.vertex_weights.iter().all(|&x| (3..6).contains(&x) || x == 100));
+        assert!(a.edges.iter().all(|e| (1..10).contains(&e.weight) || e.weight == 50));
+
+        let all_noise = WeightNoise { noise_rate: 100, ..w.node };
+        assert!((0..20).all(|_| all_noise.sample(&mut StdRng::seed_from_u64(2)) == 100));
+        assert_eq!(a.graph().vertex_weight(0), a.vertex_weights[a.graph().vertex(0)]);
+    }
+}
@@ -0,0 +1,245 @@
+//! Compressed-sparse-row graph with vertex interning.
+//!
+//! `Graph<T, W>` maps arbitrary vertex names to dense ids `0..vertex_count()`
+//! in order of first appearance and stores the out-edges of every vertex
+//! contiguously. Edges keep their input order inside a vertex's row. Every
+//! vertex also carries a weight of type `W`, `W::one()` unless set.
+//!
+//! Clustering, the DP partitioner and refinement all assume weights are
+//! non-negative, so a negative edge or vertex weight is rejected here.
+
+use crate::affinity_clustering::{ClusteringError, Edge};
+use crate::weight::Weight;
+use ndarray::Array2;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+//只有稠密编号的CSR，Graph和Affinity每一轮收缩后的图共用
+#[derive(Debug, Clone, Default)]
+pub struct Csr<W = usize> {
+    offsets: Vec<usize>,
+    targets: Vec<usize>,
+    weights: Vec<W>,
+}
+
+impl<W: Copy + Default> Csr<W> {
+    /// Builds the rows of `n` vertices from `(start, end, weight)` triples.
+    pub fn from_triples<I: IntoIterator<Item = (usize, usize, W)>>(n: usize, triples: I) -> Self {
+        let triples: Vec<(usize, usize, W)> = triples.into_iter().collect();
+        //按起点计数排序，同一起点的边保持输入顺序
+        let mut offsets = vec![0usize; n + 1];
+        for &(start, _, _) in &triples {
+            offsets[start + 1] += 1;
+        }
+        for i in 0..n {
+            offsets[i + 1] += offsets[i];
+        }
+        let mut fill = offsets.clone();
+        let mut targets = vec![0usize; triples.len()];
+        let mut weights = vec![W::default(); triples.len()];
+        for &(start, end, weight) in &triples {
+            targets[fill[start]] = end;
+            weights[fill[start]] = weight;
+            fill[start] += 1;
+        }
+        Csr { offsets, targets, weights }
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.offsets.len().saturating_sub(1)
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.targets.len()
+    }
+
+    pub fn degree(&self, v: usize) -> usize {
+        self.offsets[v + 1] - self.offsets[v]
+    }
+
+    pub fn targets(&self, v: usize) -> &[usize] {
+        &self.targets[self.offsets[v]..self.offsets[v + 1]]
+    }
+
+    pub fn weights(&self, v: usize) -> &[W] {
+        &self.weights[self.offsets[v]..self.offsets[v + 1]]
+    }
+
+    /// `(end, weight)` pairs of `v`'s out-edges.
+    pub fn neighbors(&self, v: usize) -> impl Iterator<Item = (usize, W)> + '_ {
+        self.targets(v).iter().copied().zip(self.weights(v).iter().copied())
+    }
+
+    /// All edges as `(start, end, weight)`, row by row.
+    pub fn triples(&self) -> impl Iterator<Item = (usize, usize, W)> + '_ {
+        (0..self.vertex_count()).flat_map(move |v| self.neighbors(v).map(move |(t, w)| (v, t, w)))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Graph<T, W = usize> {
+    vertices: Vec<T>,
+    index: HashMap<T, usize>,
+    vertex_weights: Vec<W>,
+    csr: Csr<W>,
+}
+
+pub(crate) fn check_weight<W: Weight>(weight: W) -> Result<W, ClusteringError> {
+    if weight < W::zero() {
+        return Err(ClusteringError::NegativeWeight(weight.to_string()));
+    }
+    Ok(weight)
+}
+
+impl<T: Copy + Hash + Eq, W: Weight> Graph<T, W> {
+    pub fn from_edges(edges: &[Edge<T, W>]) -> Result<Self, ClusteringError> {
+        for e in edges {
+            check_weight(e.weight)?;
+        }
+        Ok(Graph::intern(edges))
+    }
+
+    //不检查权重，调用方保证非负
+    fn intern(edges: &[Edge<T, W>]) -> Self {
+        let mut vertices = Vec::new();
+        let mut index = HashMap::new();
+        let mut intern = |v: T| {
+            *index.entry(v).or_insert_with(|| {
+                vertices.push(v);
+                vertices.len() - 1
+            })
+        };
+        let triples: Vec<(usize, usize, W)> = edges.iter().map(|e| (intern(e.start), intern(e.end), e.weight)).collect();
+        let csr = Csr::from_triples(vertices.len(), triples);
+        let vertex_weights = vec![W::one(); vertices.len()];
+        Graph { vertices, index, vertex_weights, csr }
+    }
+
+    /// Sets every vertex weight from `weight`.
+    pub fn with_vertex_weights<F: Fn(&T) -> W>(mut self, weight: F) -> Result<Self, ClusteringError> {
+        self.vertex_weights = self.vertices.iter().map(|v| check_weight(weight(v))).collect::<Result<_, _>>()?;
+        Ok(self)
+    }
+
+    pub fn set_vertex_weight(&mut self, id: usize, weight: W) -> Result<(), ClusteringError> {
+        self.vertex_weights[id] = check_weight(weight)?;
+        Ok(())
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len()
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.csr.edge_count()
+    }
+
+    /// Vertex names indexed by dense id.
+    pub fn vertices(&self) -> &[T] {
+        &self.vertices
+    }
+
+    pub fn vertex(&self, id: usize) -> T {
+        self.vertices[id]
+    }
+
+    /// Vertex weights indexed by dense id.
+    pub fn vertex_weights(&self) -> &[W] {
+        &self.vertex_weights
+    }
+
+    pub fn vertex_weight(&self, id: usize) -> W {
+        self.vertex_weights[id]
+    }
+
+    pub fn id(&self, v: &T) -> Option<usize> {
+        self.index.get(v).copied()
+    }
+
+    pub fn csr(&self) -> &Csr<W> {
+        &self.csr
+    }
+
+    pub fn degree(&self, id: usize) -> usize {
+        self.csr.degree(id)
+    }
+
+    pub fn neighbors(&self, id: usize) -> impl Iterator<Item = (usize, W)> + '_ {
+        self.csr.neighbors(id)
+    }
+
+    pub fn edges(&self) -> impl Iterator<Item = Edge<T, W>> + '_ {
+        self.csr.triples().map(move |(s, t, weight)| Edge {
+            start: self.vertices[s],
+            end: self.vertices[t],
+            weight,
+        })
+    }
+
+    pub fn to_edges(&self) -> Vec<Edge<T, W>> {
+        self.edges().collect()
+    }
+}
+
+impl Graph<usize> {
+    /// Reads a square weight matrix. Diagonal entries and entries equal to
+    /// `absent` are not edges; `make_random_graph_matrix` uses 10000 for absent.
+    pub fn from_adjacency_matrix(matrix: &Array2<usize>, absent: usize) -> Self {
+        let mut edges = Vec::new();
+        for ((i, j), &weight) in matrix.indexed_iter() {
+            if i != j && weight != absent {
+                edges.push(Edge { start: i, end: j, weight });
+            }
+        }
+        Graph::intern(&edges)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Graph;
+    use crate::affinity_clustering::{make_random_graph_matrix, Edge};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn triples(edges: &[Edge<usize>]) -> Vec<(usize, usize, usize)> {
+        let mut t: Vec<_> = edges.iter().map(|e| (e.start, e.end, e.weight)).collect();
+        t.sort_unstable();
+        t
+    }
+
+    #[test]
+    fn interning_and_rows() {
+        let edges = vec![
+            Edge { start: 'b', end: 'a', weight: 3 },
+            Edge { start: 'a', end: 'c', weight: 1 },
+            Edge { start: 'b', end: 'c', weight: 2 },
+        ];
+        let g = Graph::from_edges(&edges).unwrap();
+        assert_eq!(g.vertices(), &['b', 'a', 'c']);
+        assert_eq!(g.edge_count(), 3);
+        let b = g.id(&'b').unwrap();
+        assert_eq!(g.neighbors(b).collect::<Vec<_>>(), vec![(1, 3), (2, 2)]);
+        assert_eq!(g.degree(g.id(&'c').unwrap()), 0);
+        assert_eq!(g.id(&'z'), None);
+        let back: Vec<(char, char, usize)> = g.edges().map(|e| (e.start, e.end, e.weight)).collect();
+        assert_eq!(back, vec![('b', 'a', 3), ('b', 'c', 2), ('a', 'c', 1)]);
+    }
+
+    #[test]
+    fn adjacency_matrix_matches_edge_list() {
+        let (matrix, edges) = make_random_graph_matrix(40, &mut StdRng::seed_from_u64(4));
+        let g = Graph::from_adjacency_matrix(&matrix, 10000);
+        assert_eq!(triples(&g.to_edges()), triples(&edges));
+    }
+
+    #[test]
+    fn negative_weights_are_rejected() {
+        use crate::affinity_clustering::ClusteringError;
+        let edges = vec![Edge { start: 0, end: 1, weight: 2i32 }, Edge { start: 1, end: 2, weight: -1 }];
+        assert_eq!(Graph::from_edges(&edges).err(), Some(ClusteringError::NegativeWeight("-1".to_string())));
+        let graph = Graph::from_edges(&edges[..1]).unwrap();
+        assert_eq!(graph.clone().with_vertex_weights(|&v| v - 1).err(), Some(ClusteringError::NegativeWeight("-1".to_string())));
+        assert!(graph.with_vertex_weights(|_| 0).is_ok());
+    }
+}
@@ -0,0 +1,94 @@
+//! Dendrogram recorded by `Affinity::clustering`.
+//!
+//! Clusters are named by the dense id of their root vertex at the level they
+//! exist on, so `vertices()[id]` is a representative member. `levels()[r]`
+//! holds the merges done in round `r + 1`; level 0 is all singletons.
+
+use crate::disjoint_set::DisjointSet;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ClusterMerge<W = usize> {
+    pub parent: usize,//合并后的cluster编号
+    pub children: Vec<usize>,//本轮开始时被合并的cluster编号，按编号升序
+    pub weight: W,//连接这些children的边中最弱的一条的权重
+    /// False when exact-k merged clusters with no edge between them to reach
+    /// k on a disconnected graph; `weight` is then zero and means nothing.
+    #[cfg_attr(feature = "serde", serde(default = "linked_default"))]
+    pub linked: bool,
+}
+
+#[cfg(feature = "serde")]
+fn linked_default() -> bool {
+    true
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Hierarchy<T, W = usize> {
+    vertices: Vec<T>,
+    levels: Vec<Vec<ClusterMerge<W>>>,
+}
+
+impl<T: Copy, W> Hierarchy<T, W> {
+    pub fn new(vertices: Vec<T>) -> Self {
+        Hierarchy { vertices, levels: Vec::new() }
+    }
+
+    pub fn vertices(&self) -> &[T] {
+        &self.vertices
+    }
+
+    pub fn levels(&self) -> &[Vec<ClusterMerge<W>>] {
+        &self.levels
+    }
+
+    /// Number of recorded rounds; `cut_at_level(depth())` is the final clustering.
+    pub fn depth(&self) -> usize {
+        self.levels.len()
+    }
+
+    //merges需按合并强度从强到弱排好，cut_at_clusters按这个顺序部分回放一层
+    pub(crate) fn push_level(&mut self, merges: Vec<ClusterMerge<W>>) {
+        self.levels.push(merges);
+    }
+
+    /// Clusters after `level` rounds (clamped to `depth()`).
+    pub fn cut_at_level(&self, level: usize) -> Vec<Vec<T>> {
+        let mut ds = DisjointSet::new(self.vertices.len());
+        for merges in self.levels.iter().take(level) {
+            for m in merges {
+                for &c in &m.children {
+                    ds.union(m.parent, c);
+                }
+            }
+        }
+        self.clusters_of(&ds)
+    }
+
+    /// Clusters when `k` remain, replaying whole levels and then the strongest
+    /// merges of the next level one child at a time. If the hierarchy never
+    /// gets down to `k`, the final clustering is returned.
+    pub fn cut_at_clusters(&self, k: usize) -> Vec<Vec<T>> {
+        let mut ds = DisjointSet::new(self.vertices.len());
+        'levels: for merges in &self.levels {
+            for m in merges {
+                for &c in &m.children {
+                    if ds.set_count() <= k.max(1) {
+                        break 'levels;
+                    }
+                    ds.union(m.parent, c);
+                }
+            }
+        }
+        self.clusters_of(&ds)
+    }
+
+    fn clusters_of(&self, ds: &DisjointSet) -> Vec<Vec<T>> {
+        ds.roots()
+            .map(|r| ds.members(r).map(|x| self.vertices[x]).collect())
+            .collect()
+    }
+}
@@ -0,0 +1,502 @@
+//! Reading and writing `Edge<usize>` lists in common graph file formats.
+//!
+//! Vertex ids are 0-based in memory. METIS, DIMACS and Matrix Market files
+//! are 1-based and are shifted on the way in and out. Parse errors carry the
+//! 1-based line number of the offending line.
+//!
+//! Readers also return the vertex count the file declares and writers take
+//! it, so isolated vertices after the last one with an edge survive a round
+//! trip. Edge lists cannot declare it; there it is one more than the largest id.
+
+use crate::affinity_clustering::Edge;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum GraphIoError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+    Format(String),//写出时图不满足格式要求，例如METIS要求无向图
+}
+
+impl Display for GraphIoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphIoError::Io(e) => write!(f, "{}", e),
+            GraphIoError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            GraphIoError::Format(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for GraphIoError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GraphIoError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for GraphIoError {
+    fn from(e: io::Error) -> Self {
+        GraphIoError::Io(e)
+    }
+}
+
+/// Edges read from a file, with the number of vertices it declares.
+#[derive(Clone)]
+pub struct GraphFile {
+    pub vertices: usize,
+    pub edges: Vec<Edge<usize>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    /// `start<delim>end<delim>weight` per line; the weight column may be omitted.
+    EdgeList(char),
+    Metis,
+    Dimacs,
+    MatrixMarket,
+}
+
+impl GraphFormat {
+    /// Guesses the format from a file extension.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let ext = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "csv" => Some(GraphFormat::EdgeList(',')),
+            "tsv" => Some(GraphFormat::EdgeList('\t')),
+            "txt" | "edges" | "el" => Some(GraphFormat::EdgeList(' ')),
+            "graph" | "metis" => Some(GraphFormat::Metis),
+            "gr" | "dimacs" => Some(GraphFormat::Dimacs),
+            "mtx" => Some(GraphFormat::MatrixMarket),
+            _ => None,
+        }
+    }
+}
+
+pub fn read_graph<R: BufRead>(reader: R, format: GraphFormat) -> Result<GraphFile, GraphIoError> {
+    match format {
+        GraphFormat::EdgeList(_) => read_edge_list(reader),
+        GraphFormat::Metis => read_metis(reader),
+        GraphFormat::Dimacs => read_dimacs(reader),
+        GraphFormat::MatrixMarket => read_matrix_market(reader),
+    }
+}
+
+/// Writes `edges` on `vertices` vertices; the edge list format has no
+/// place for the vertex count and ignores it.
+pub fn write_graph<W: Write>(writer: W, vertices: usize, edges: &[Edge<usize>], format: GraphFormat) -> Result<(), GraphIoError> {
+    match format {
+        GraphFormat::EdgeList(delim) => write_edge_list(writer, edges, delim),
+        GraphFormat::Metis => write_metis(writer, vertices, edges),
+        GraphFormat::Dimacs => write_dimacs(writer, vertices, edges),
+        GraphFormat::MatrixMarket => write_matrix_market(writer, vertices, edges),
+    }
+}
+
+pub fn read_graph_file<P: AsRef<Path>>(path: P, format: GraphFormat) -> Result<GraphFile, GraphIoError> {
+    read_graph(BufReader::new(File::open(path)?), format)
+}
+
+pub fn write_graph_file<P: AsRef<Path>>(path: P, vertices: usize, edges: &[Edge<usize>], format: GraphFormat) -> Result<(), GraphIoError> {
+    write_graph(BufWriter::new(File::create(path)?), vertices, edges, format)
+}
+
+//逐行读取，跳过空行和以comment中任一字符开头的行，返回(行号, 内容)
+fn content_lines<R: BufRead>(reader: R, comment: &'static [char]) -> impl Iterator<Item = Result<(usize, String), GraphIoError>> {
+    reader
+        .lines()
+        .enumerate()
+        .filter_map(move |(i, line)| match line {
+            Err(e) => Some(Err(GraphIoError::Io(e))),
+            Ok(line) => {
+                let trimmed = line.trim();
+                if trimmed.is_empty() || trimmed.starts_with(comment) {
+                    None
+                } else {
+                    Some(Ok((i + 1, trimmed.to_string())))
+                }
+            }
+        })
+}
+
+fn parse_field(line: usize, field: Option<&str>, what: &str) -> Result<usize, GraphIoError> {
+    let field = field.ok_or_else(|| GraphIoError::Parse { line, message: format!("missing {}", what) })?;
+    field.parse().map_err(|_| GraphIoError::Parse { line, message: format!("invalid {} {:?}", what, field) })
+}
+
+//1起始的编号转为0起始，同时检查不超过声明的点数
+fn parse_vertex(line: usize, field: Option<&str>, n: usize) -> Result<usize, GraphIoError> {
+    let v = parse_field(line, field, "vertex")?;
+    if v == 0 || v > n {
+        return Err(GraphIoError::Parse { line, message: format!("vertex {} out of range 1..={}", v, n) });
+    }
+    Ok(v - 1)
+}
+
+fn vertex_count(edges: &[Edge<usize>]) -> usize {
+    edges.iter().map(|e| e.start.max(e.end) + 1).max().unwrap_or(0)
+}
+
+//写出前检查所有端点都小于声明的点数
+fn check_vertices(vertices: usize, edges: &[Edge<usize>]) -> Result<(), GraphIoError> {
+    match edges.iter().find(|e| e.start.max(e.end) >= vertices) {
+        Some(e) => Err(GraphIoError::Format(format!("edge {} -> {} is outside the {} vertices", e.start, e.end, vertices))),
+        None => Ok(()),
+    }
+}
+
+/// Reads a weighted edge list separated by commas, tabs or spaces. Lines
+/// starting with `#` or `%` are comments; a missing weight column means 1.
+pub fn read_edge_list<R: BufRead>(reader: R) -> Result<GraphFile, GraphIoError> {
+    let mut edges = Vec::new();
+    for line in content_lines(reader, &['#', '%']) {
+        let (no, text) = line?;
+        let mut fields = text.split(|c: char| c == ',' || c.is_whitespace()).filter(|f| !f.is_empty());
+        let start = parse_field(no, fields.next(), "start vertex")?;
+        let end = parse_field(no, fields.next(), "end vertex")?;
+        let weight = match fields.next() {
+            Some(w) => parse_field(no, Some(w), "weight")?,
+            None => 1,
+        };
+        if let Some(extra) = fields.next() {
+            return Err(GraphIoError::Parse { line: no, message: format!("unexpected field {:?}", extra) });
+        }
+        edges.push(Edge { start, end, weight });
+    }
+    Ok(GraphFile { vertices: vertex_count(&edges), edges })
+}
+
+pub fn write_edge_list<W: Write>(mut writer: W, edges: &[Edge<usize>], delim: char) -> Result<(), GraphIoError> {
+    for e in edges {
+        writeln!(writer, "{}{}{}{}{}", e.start, delim, e.end, delim, e.weight)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads a METIS `.graph` file. Every adjacency entry becomes one directed
+/// edge, so each undirected edge shows up in both directions. Vertex weights
+/// are skipped; without edge weights every edge weighs 1.
+pub fn read_metis<R: BufRead>(reader: R) -> Result<GraphFile, GraphIoError> {
+    let mut lines = reader.lines().enumerate().map(|(i, l)| l.map(|l| (i + 1, l)));
+    //头部之前允许有%注释；头部之后空行表示孤立点，不能跳过
+    let (header_no, header) = loop {
+        match lines.next() {
+            None => return Err(GraphIoError::Parse { line: 1, message: "missing header".to_string() }),
+            Some(line) => {
+                let (no, text) = line?;
+                let text = text.trim().to_string();
+                if !text.is_empty() && !text.starts_with('%') {
+                    break (no, text);
+                }
+            }
+        }
+    };
+    let mut fields = header.split_whitespace();
+    let n = parse_field(header_no, fields.next(), "vertex count")?;
+    let m = parse_field(header_no, fields.next(), "edge count")?;
+    let fmt = fields.next().unwrap_or("0");
+    if fmt.len() > 3 || !fmt.chars().all(|c| c == '0' || c == '1') {
+        return Err(GraphIoError::Parse { line: header_no, message: format!("invalid fmt {:?}", fmt) });
+    }
+    let fmt = format!("{:0>3}", fmt);
+    let vertex_sizes = &fmt[0..1] == "1";
+    let vertex_weights = &fmt[1..2] == "1";
+    let edge_weights = &fmt[2..3] == "1";
+    let ncon = match fields.next() {
+        _ if !vertex_weights => 0,
+        Some(c) => parse_field(header_no, Some(c), "ncon")?,
+        None => 1,
+    };
+
+    let mut edges = Vec::new();
+    let mut v = 0;
+    let mut last_no = header_no;
+    for line in lines {
+        let (no, text) = line?;
+        last_no = no;
+        if text.trim_start().starts_with('%') {
+            continue;
+        }
+        if v == n {
+            if text.trim().is_empty() {
+                continue;
+            }
+            return Err(GraphIoError::Parse { line: no, message: format!("more than {} vertex lines", n) });
+        }
+        let mut fields = text.split_whitespace();
+        if vertex_sizes {
+            parse_field(no, fields.next(), "vertex size")?;
+        }
+        for _ in 0..ncon {
+            parse_field(no, fields.next(), "vertex weight")?;
+        }
+        while let Some(f) = fields.next() {
+            let end = parse_vertex(no, Some(f), n)?;
+            let weight = if edge_weights { parse_field(no, fields.next(), "weight")? } else { 1 };
+            edges.push(Edge { start: v, end, weight });
+        }
+        v += 1;
+    }
+    if v < n {
+        return Err(GraphIoError::Parse { line: last_no, message: format!("expected {} vertex lines, found {}", n, v) });
+    }
+    if edges.len() != 2 * m {
+        return Err(GraphIoError::Parse {
+            line: header_no,
+            message: format!("header declares {} edges but adjacency lists hold {} entries", m, edges.len()),
+        });
+    }
+    Ok(GraphFile { vertices: n, edges })
+}
+
+/// Writes a METIS `.graph` file with edge weights. METIS graphs are
+/// undirected, so every edge must appear in both directions with the same weight.
+pub fn write_metis<W: Write>(mut writer: W, vertices: usize, edges: &[Edge<usize>]) -> Result<(), GraphIoError> {
+    check_vertices(vertices, edges)?;
+    let arcs: HashSet<(usize, usize, usize)> = edges.iter().map(|e| (e.start, e.end, e.weight)).collect();
+    if let Some(e) = edges.iter().find(|e| e.start == e.end || !arcs.contains(&(e.end, e.start, e.weight))) {
+        return Err(GraphIoError::Format(format!(
+            "METIS needs an undirected graph without self-loops, edge {} -> {} does not fit",
+            e.start, e.end
+        )));
+    }
+    let n = vertices;
+    let mut rows = vec![Vec::new(); n];
+    for e in edges {
+        rows[e.start].push((e.end, e.weight));
+    }
+    writeln!(writer, "{} {} 001", n, edges.len() / 2)?;
+    for row in rows {
+        let line: Vec<String> = row.iter().map(|(end, weight)| format!("{} {}", end + 1, weight)).collect();
+        writeln!(writer, "{}", line.join(" "))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads a DIMACS file: `a u v w` arcs of the shortest-path format, or
+/// `e u v` edges of the clique format with weight 1.
+pub fn read_dimacs<R: BufRead>(reader: R) -> Result<GraphFile, GraphIoError> {
+    let mut edges = Vec::new();
+    let mut declared: Option<(usize, usize, usize)> = None;//(点数, 边数, 所在行)
+    let mut last_no = 0;
+    for line in content_lines(reader, &['c']) {
+        let (no, text) = line?;
+        last_no = no;
+        let mut fields = text.split_whitespace();
+        match fields.next() {
+            Some("p") => {
+                if declared.is_some() {
+                    return Err(GraphIoError::Parse { line: no, message: "duplicate problem line".to_string() });
+                }
+                fields.next();//问题类型，sp或edge
+                let n = parse_field(no, fields.next(), "vertex count")?;
+                let m = parse_field(no, fields.next(), "edge count")?;
+                declared = Some((n, m, no));
+            }
+            Some(kind @ "a") | Some(kind @ "e") => {
+                let (n, _, _) = declared
+                    .ok_or_else(|| GraphIoError::Parse { line: no, message: "edge before problem line".to_string() })?;
+                let start = parse_vertex(no, fields.next(), n)?;
+                let end = parse_vertex(no, fields.next(), n)?;
+                let weight = if kind == "a" { parse_field(no, fields.next(), "weight")? } else { 1 };
+                edges.push(Edge { start, end, weight });
+            }
+            Some(other) => {
+                return Err(GraphIoError::Parse { line: no, message: format!("unknown line type {:?}", other) });
+            }
+            None => unreachable!(),
+        }
+    }
+    match declared {
+        None => Err(GraphIoError::Parse { line: last_no.max(1), message: "missing problem line".to_string() }),
+        Some((_, m, no)) if m != edges.len() => Err(GraphIoError::Parse {
+            line: no,
+            message: format!("problem line declares {} edges, found {}", m, edges.len()),
+        }),
+        Some((n, _, _)) => Ok(GraphFile { vertices: n, edges }),
+    }
+}
+
+pub fn write_dimacs<W: Write>(mut writer: W, vertices: usize, edges: &[Edge<usize>]) -> Result<(), GraphIoError> {
+    check_vertices(vertices, edges)?;
+    writeln!(writer, "p sp {} {}", vertices, edges.len())?;
+    for e in edges {
+        writeln!(writer, "a {} {} {}", e.start + 1, e.end + 1, e.weight)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads a Matrix Market coordinate file. `pattern` entries weigh 1, and
+/// `symmetric` files get the mirrored entry of every off-diagonal one.
+pub fn read_matrix_market<R: BufRead>(reader: R) -> Result<GraphFile, GraphIoError> {
+    let mut lines = reader.lines().enumerate().map(|(i, l)| l.map(|l| (i + 1, l)));
+    let banner = match lines.next() {
+        Some(line) => line?.1,
+        None => return Err(GraphIoError::Parse { line: 1, message: "missing %%MatrixMarket banner".to_string() }),
+    };
+    let words: Vec<String> = banner.split_whitespace().map(|w| w.to_ascii_lowercase()).collect();
+    if words.len() != 5 || words[0] != "%%matrixmarket" || words[1] != "matrix" || words[2] != "coordinate" {
+        return Err(GraphIoError::Parse { line: 1, message: "expected a %%MatrixMarket matrix coordinate banner".to_string() });
+    }
+    let pattern = match words[3].as_str() {
+        "integer" => false,
+        "pattern" => true,
+        other => return Err(GraphIoError::Parse { line: 1, message: format!("unsupported field {:?}", other) }),
+    };
+    let symmetric = match words[4].as_str() {
+        "general" => false,
+        "symmetric" => true,
+        other => return Err(GraphIoError::Parse { line: 1, message: format!("unsupported symmetry {:?}", other) }),
+    };
+
+    let mut size: Option<(usize, usize, usize)> = None;//(点数, 非零元个数, 所在行)
+    let mut entries = 0;
+    let mut edges = Vec::new();
+    let mut last_no = 1;
+    for line in lines {
+        let (no, text) = line?;
+        last_no = no;
+        let text = text.trim();
+        if text.is_empty() || text.starts_with('%') {
+            continue;
+        }
+        let mut fields = text.split_whitespace();
+        let (n, _, _) = match size {
+            Some(s) => s,
+            None => {
+                let rows = parse_field(no, fields.next(), "row count")?;
+                let cols = parse_field(no, fields.next(), "column count")?;
+                let nnz = parse_field(no, fields.next(), "entry count")?;
+                if rows != cols {
+                    return Err(GraphIoError::Parse { line: no, message: format!("matrix is {}x{}, not square", rows, cols) });
+                }
+                size = Some((rows, nnz, no));
+                continue;
+            }
+        };
+        let start = parse_vertex(no, fields.next(), n)?;
+        let end = parse_vertex(no, fields.next(), n)?;
+        let weight = if pattern { 1 } else { parse_field(no, fields.next(), "weight")? };
+        edges.push(Edge { start, end, weight });
+        if symmetric && start != end {
+            edges.push(Edge { start: end, end: start, weight });
+        }
+        entries += 1;
+    }
+    match size {
+        None => Err(GraphIoError::Parse { line: last_no, message: "missing size line".to_string() }),
+        Some((_, nnz, no)) if nnz != entries => Err(GraphIoError::Parse {
+            line: no,
+            message: format!("size line declares {} entries, found {}", nnz, entries),
+        }),
+        Some((n, _, _)) => Ok(GraphFile { vertices: n, edges }),
+    }
+}
+
+pub fn write_matrix_market<W: Write>(mut writer: W, vertices: usize, edges: &[Edge<usize>]) -> Result<(), GraphIoError> {
+    check_vertices(vertices, edges)?;
+    let n = vertices;
+    writeln!(writer, "%%MatrixMarket matrix coordinate integer general")?;
+    writeln!(writer, "{} {} {}", n, n, edges.len())?;
+    for e in edges {
+        writeln!(writer, "{} {} {}", e.start + 1, e.end + 1, e.weight)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::affinity_clustering::make_random_graph_matrix;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn triples(edges: &[Edge<usize>]) -> Vec<(usize, usize, usize)> {
+        edges.iter().map(|e| (e.start, e.end, e.weight)).collect()
+    }
+
+    fn sorted(mut t: Vec<(usize, usize, usize)>) -> Vec<(usize, usize, usize)> {
+        t.sort_unstable();
+        t
+    }
+
+    #[test]
+    fn every_format_round_trips() {
+        let (_, edges) = make_random_graph_matrix(30, &mut StdRng::seed_from_u64(8));
+        let formats = [
+            GraphFormat::EdgeList(','),
+            GraphFormat::EdgeList('\t'),
+            GraphFormat::Metis,
+            GraphFormat::Dimacs,
+            GraphFormat::MatrixMarket,
+        ];
+        for &format in &formats {
+            let mut buf = Vec::new();
+            write_graph(&mut buf, 30, &edges, format).unwrap();
+            let back = read_graph(&buf[..], format).unwrap().edges;
+            if format == GraphFormat::Metis {
+                //METIS按起点分行，只保留边集合
+                assert_eq!(sorted(triples(&back)), sorted(triples(&edges)), "{:?}", format);
+            } else {
+                assert_eq!(triples(&back), triples(&edges), "{:?}", format);
+            }
+        }
+    }
+
+    #[test]
+    fn metis_and_matrix_market_variants() {
+        let metis = "% triangle with vertex weights\n3 3 011\n5 2 4 3 1\n6 1 4 3 2\n7 1 1 2 2\n";
+        let edges = read_metis(metis.as_bytes()).unwrap().edges;
+        assert_eq!(triples(&edges), vec![(0, 1, 4), (0, 2, 1), (1, 0, 4), (1, 2, 2), (2, 0, 1), (2, 1, 2)]);
+
+        let mtx = "%%MatrixMarket matrix coordinate pattern symmetric\n% comment\n3 3 2\n2 1\n3 3\n";
+        let edges = read_matrix_market(mtx.as_bytes()).unwrap().edges;
+        assert_eq!(triples(&edges), vec![(1, 0, 1), (0, 1, 1), (2, 2, 1)]);
+    }
+
+    #[test]
+    fn parse_errors_report_line_numbers() {
+        let line_of = |r: Result<GraphFile, GraphIoError>| match r {
+            Err(GraphIoError::Parse { line, .. }) => line,
+            other => panic!("expected a parse error, got {:?}", other.map(|g| g.edges.len())),
+        };
+        assert_eq!(line_of(read_edge_list("# header\n0,1,2\n\n1,x,3\n".as_bytes())), 4);
+        assert_eq!(line_of(read_metis("2 1\n2\n3\n".as_bytes())), 3);
+        assert_eq!(line_of(read_dimacs("c x\np sp 2 1\na 1 2\n".as_bytes())), 3);
+        assert_eq!(line_of(read_matrix_market("%%MatrixMarket matrix coordinate integer general\n2 2 2\n1 2 5\n".as_bytes())), 2);
+
+        let err = read_edge_list("0 1 2\n0 1 2 3\n".as_bytes()).err().unwrap();
+        assert_eq!(err.to_string(), "line 2: unexpected field \"3\"");
+    }
+
+    #[test]
+    fn metis_rejects_directed_graphs() {
+        let edges = vec![Edge { start: 0, end: 1, weight: 1 }];
+        assert!(matches!(write_metis(Vec::new(), 2, &edges), Err(GraphIoError::Format(_))));
+    }
+
+    #[test]
+    fn isolated_trailing_vertices_round_trip() {
+        //点3和点4没有边
+        let edges = vec![Edge { start: 0, end: 1, weight: 2 }, Edge { start: 1, end: 0, weight: 2 }, Edge { start: 1, end: 2, weight: 3 }, Edge { start: 2, end: 1, weight: 3 }];
+        for &format in &[GraphFormat::Metis, GraphFormat::Dimacs, GraphFormat::MatrixMarket] {
+            let mut buf = Vec::new();
+            write_graph(&mut buf, 5, &edges, format).unwrap();
+            let back = read_graph(&buf[..], format).unwrap();
+            assert_eq!(back.vertices, 5, "{:?}", format);
+            assert_eq!(sorted(triples(&back.edges)), sorted(triples(&edges)), "{:?}", format);
+            assert!(matches!(write_graph(Vec::new(), 2, &edges, format), Err(GraphIoError::Format(_))), "{:?}", format);
+        }
+    }
+}
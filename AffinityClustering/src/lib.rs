@@ -1,261 +1,808 @@
+#![allow(non_snake_case)]
+
+//打开parallel特性时用rayon并行迭代，否则退化为普通迭代器；两条路径按相同顺序收集结果，输出一致
+macro_rules! maybe_par_iter {
+    ($e:expr) => {{
+        #[cfg(feature = "parallel")]
+        {
+            rayon::iter::IntoParallelIterator::into_par_iter($e)
+        }
+        #[cfg(not(feature = "parallel"))]
+        {
+            IntoIterator::into_iter($e)
+        }
+    }};
+}
+
+pub mod constraints;
+pub mod disjoint_set;
+pub mod generators;
+pub mod graph;
+pub mod hierarchy;
+pub mod io;
+pub mod linkage;
+pub mod metrics;
+pub mod neighborhood;
+pub mod partition;
+pub mod refine;
+pub mod weight;
+
 pub mod affinity_clustering {
-    use rand::{Rng, thread_rng};
+    use rand::Rng;
     use std::collections::{HashMap, HashSet};
-    use ndarray::{Array2, ArrayView};
+    use ndarray::Array2;
     use std::fmt::{Display, Debug};
     use std::clone::Clone;
     use std::marker::Copy;
     use std::hash::Hash;
     use std::cmp::Eq;
+    use std::error::Error;
+    use std::time::{Duration, Instant};
+    use crate::constraints::{ConstraintReport, ConstraintSet, Constraints};
+    use crate::disjoint_set::DisjointSet;
+    use crate::hierarchy::{ClusterMerge, Hierarchy};
+    use crate::graph::{check_weight, Csr, Graph};
+    use crate::linkage::{Linkage, LinkView, Max, Min};
+    use crate::weight::Weight;
+    #[cfg(feature = "parallel")]
+    use rayon::prelude::*;
+    #[cfg(feature = "serde")]
+    use serde::{Deserialize, Serialize};
+    use tracing::{debug, debug_span, info, info_span};
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum ClusteringError {
+        UnknownVertex(String),//图中不存在该点
+        EmptyGraph,
+        KExceedsVertexCount { k: usize, vertices: usize },
+        NotConverged { rounds: u32, clusters: usize, k: usize },//某一轮没有发生合并，无法继续减少cluster数量
+        DuplicateVertex(String),//同一个点出现在多个partition中
+        UnknownEdge { start: String, end: String },//要删除或修改的边不存在
+        NegativeWeight(String),//边权或点权为负
+        PartLengthMismatch { len: usize, vertices: usize },//划分的长度与点数不同
+        PartOutOfRange { vertex: usize, part: usize, k: usize },//某个点的part编号不小于k
+    }
+
+    impl Display for ClusteringError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                ClusteringError::UnknownVertex(v) => write!(f, "unknown vertex {}", v),
+                ClusteringError::EmptyGraph => write!(f, "the graph has no edges"),
+                ClusteringError::KExceedsVertexCount { k, vertices } =>
+                    write!(f, "k = {} is larger than the number of vertices ({})", k, vertices),
+                ClusteringError::NotConverged { rounds, clusters, k } =>
+                    write!(f, "clustering stalled after {} rounds with {} clusters (k = {})", rounds, clusters, k),
+                ClusteringError::DuplicateVertex(v) => write!(f, "vertex {} is in more than one part", v),
+                ClusteringError::UnknownEdge { start, end } => write!(f, "no edge from {} to {}", start, end),
+                ClusteringError::NegativeWeight(w) => write!(f, "negative weight {}", w),
+                ClusteringError::PartLengthMismatch { len, vertices } =>
+                    write!(f, "the partition assigns {} vertices but the graph has {}", len, vertices),
+                ClusteringError::PartOutOfRange { vertex, part, k } =>
+                    write!(f, "vertex {} is in part {} but there are only {} parts", vertex, part, k),
+            }
+        }
+    }
+
+    impl Error for ClusteringError {}
 
-    pub struct ArrayUnion<T> {
-        group: HashMap::<T, T>,
-        size: HashMap::<T, usize>,
-        items: HashMap::<T, Vec::<T>>,
+    //打开serde特性时可以整体保存为检查点，反序列化后继续clustering或apply_changes
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    #[cfg_attr(
+        feature = "serde",
+        serde(from = "SavedAffinity<T, W>", bound(deserialize = "T: Deserialize<'de> + Copy + Hash + Eq, W: Deserialize<'de>"))
+    )]
+    pub struct Affinity<T, W = usize> {
+        k: usize,
+        V: Vec::<T>,//V[i]为编号i对应的点
+        #[cfg_attr(feature = "serde", serde(skip_serializing))]
+        index: HashMap::<T, usize>,//点到并查集中稠密编号的映射，反序列化时由V重建
+        E: Vec::<Edge<usize, W>>,//当前cluster之间的边，两端为cluster根的编号
+        vertex_edges: Vec::<Edge<usize, W>>,//点之间的边，增量更新时据此重建cluster之间的边
+        uf: DisjointSet,
+        vertex_weight: Vec::<W>,
+        cluster_weight: Vec::<W>,//以根为下标的cluster点权和
+        sparsify_rounds: Vec::<SparsifyRound>,
+        hierarchy: Hierarchy<T, W>,//记录每一轮的合并
+        constraints: ConstraintSet<W>,//所有合并都要先经过约束检查
+        #[cfg_attr(feature = "serde", serde(skip_serializing))]
+        contraction: Duration,//上次report_round之后edges_update累计用时
     }
-    
-    pub struct Affinity<T> {
+
+    //Affinity序列化的内容，即除index以外的所有字段
+    #[cfg(feature = "serde")]
+    #[derive(Deserialize)]
+    struct SavedAffinity<T, W> {
         k: usize,
-        pub E: Vec::<Edge<T>>,
-        pub V: Vec::<T>,//目前所有节点均为已知，故不需要集合类型
-        uf: ArrayUnion::<T>,
-        clost_neighbors: HashMap::<T, T>,
-        merged: HashMap::<T, Option::<T>>//使用没有value的hashmap作为集合类型
-    }
-    
-    impl<T:Debug + Display + Copy + Hash + Eq> Affinity<T> {
-        pub fn new_and_init(edges: &Vec<Edge<T>>, k: usize) -> Self {
-            let mut v_set = HashMap::<T, Option<T>>::new();
-            for e in edges.iter() {
-                v_set.insert(e.start, None);
-                v_set.insert(e.end, None);
-            }
-            let v: Vec::<T> = v_set.keys().into_iter().map(|&x| x).collect();
-            //println!("number of vertexs is:{}", v.len());
+        V: Vec::<T>,
+        E: Vec::<Edge<usize, W>>,
+        vertex_edges: Vec::<Edge<usize, W>>,
+        uf: DisjointSet,
+        vertex_weight: Vec::<W>,
+        cluster_weight: Vec::<W>,
+        sparsify_rounds: Vec::<SparsifyRound>,
+        hierarchy: Hierarchy<T, W>,
+        #[serde(default = "ConstraintSet::default")]
+        constraints: ConstraintSet<W>,//没有约束的检查点中不含该字段
+    }
+
+    #[cfg(feature = "serde")]
+    impl<T:Copy + Hash + Eq, W> From<SavedAffinity<T, W>> for Affinity<T, W> {
+        fn from(saved: SavedAffinity<T, W>) -> Self {
             Affinity {
-                k: k,
+                k: saved.k,
+                index: saved.V.iter().enumerate().map(|(i, &x)| (x, i)).collect(),
+                V: saved.V,
+                E: saved.E,
+                vertex_edges: saved.vertex_edges,
+                uf: saved.uf,
+                vertex_weight: saved.vertex_weight,
+                cluster_weight: saved.cluster_weight,
+                sparsify_rounds: saved.sparsify_rounds,
+                hierarchy: saved.hierarchy,
+                constraints: saved.constraints,
+                contraction: Duration::ZERO,
+            }
+        }
+    }
+
+    impl<T:Debug + Display + Copy + Hash + Eq + Send + Sync, W: Weight> Affinity<T, W> {
+        pub fn new_and_init(graph: &Graph<T, W>, k: usize) -> Result<Self, ClusteringError> {
+            if graph.edge_count() == 0 {
+                return Err(ClusteringError::EmptyGraph);
+            }
+            //编号沿用Graph中点首次出现的顺序，避免HashMap的随机迭代顺序影响聚类结果
+            let v = graph.vertices().to_vec();
+            if k > v.len() {
+                return Err(ClusteringError::KExceedsVertexCount { k, vertices: v.len() });
+            }
+            let index = v.iter().enumerate().map(|(i, &x)| (x, i)).collect();
+            let edges: Vec::<Edge<usize, W>> = graph.csr().triples().map(|(start, end, weight)| Edge { start, end, weight }).collect();
+            Ok(Affinity {
+                k,
                 E: edges.clone(),
-                V: v.clone(),
-                uf: ArrayUnion::new_and_init(v),
-                clost_neighbors: HashMap::new(),
-                merged: HashMap::new()
-            }
-        }
-    
-        pub fn print_all_clusters(&self) {
-            for (name, clusters) in self.uf.items.iter() {
-                println!("cluster{}: {:?}", name, clusters);
-                println!();
-            }
-        }
-    
-        fn merge_with_cloest_neighbors(&mut self, v: T, mut v_stack: HashMap::<T, Option<T>>) -> HashMap::<T, Option<T>>{
-            if self.merged.contains_key(&v) {
-                return v_stack;//v_stack记录该函数的栈里目前都寻找了哪些v，避免死循环
-            }
-            if let Some(self_closet) = self.clost_neighbors.get(&v) {
-                if let Some(self_cloest_cloest) = self.clost_neighbors.get(self_closet) {
-                    if v == *self_cloest_cloest {
-                        let findv = self.uf.find(v);
-                        let find_clost_neighbor = self.uf.find(*self_closet);
-                        self.uf.union(findv, find_clost_neighbor);//出现所有权问题，考虑将调用的函数设为&mut self
-                        //可直接写为self.uf.union(self.uf.find(v), self.uf.find(*self.clost_neighbors.get(&v).unwrap()))， 考虑到对find函数的多次调用
-                        self.merged.insert(v, None);
-                        self.merged.insert(*self_closet, None);
-                        return v_stack;
-                    } else {
-                        let findv = self.uf.find(v);
-                        let find_clost_neighbor = self.uf.find(*self_closet);
-                        if v_stack.contains_key(&v) {
-                            //v_stack.remove(&v);
-                            return v_stack;
-                        } else {
-                            v_stack.insert(v, None);
-                            v_stack = self.merge_with_cloest_neighbors(*self_closet, v_stack);
-                            v_stack.remove(&v);
-                            self.uf.union(findv, find_clost_neighbor);
-                            self.merged.insert(v, None);
-                            return  v_stack;
-                        }
-                    }
+                vertex_edges: edges,
+                uf: DisjointSet::new(v.len()),
+                vertex_weight: graph.vertex_weights().to_vec(),
+                cluster_weight: graph.vertex_weights().to_vec(),
+                hierarchy: Hierarchy::new(v.clone()),
+                V: v,
+                index,
+                sparsify_rounds: Vec::new(),
+                constraints: ConstraintSet::default(),
+                contraction: Duration::ZERO,
+            })
+        }
+
+        pub fn vertices(&self) -> &[T] {
+            &self.V
+        }
+
+        //当前cluster之间的边，端点为cluster名（根节点）
+        pub fn edges(&self) -> Vec::<Edge<T, W>> {
+            self.E.iter().map(|e| Edge { start: self.V[e.start], end: self.V[e.end], weight: e.weight }).collect()
+        }
+
+        //make_cluster中边稀疏化每一轮的统计，直接调用new_and_init时为空
+        pub fn sparsify_rounds(&self) -> &[SparsifyRound] {
+            &self.sparsify_rounds
+        }
+
+        //完整的层次聚类结果，可在任意层或任意cluster数处切割
+        pub fn hierarchy(&self) -> &Hierarchy<T, W> {
+            &self.hierarchy
+        }
+
+        //当前的所有cluster，按根的编号排列，每个cluster的第一个点为其名字（根节点）
+        pub fn clusters(&self) -> Vec::<Vec::<T>> {
+            self.uf.roots().map(|root| self.uf.members(root).map(|x| self.V[x]).collect()).collect()
+        }
+
+        fn id(&self, v: T) -> Result<usize, ClusteringError> {
+            self.index.get(&v).copied().ok_or_else(|| ClusteringError::UnknownVertex(v.to_string()))
+        }
+
+        pub fn find(&mut self, v: T) -> Result<T, ClusteringError> {//返回包含v的cluster名，即根节点
+            let id = self.id(v)?;
+            Ok(self.V[self.uf.find(id)])
+        }
+
+        pub fn cluster_weight(&mut self, v: T) -> Result<W, ClusteringError> {//包含v的cluster的点权和
+            let id = self.id(v)?;
+            let root = self.uf.find(id);
+            Ok(self.cluster_weight[root])
+        }
+
+        //合并a、b所在的cluster并累加点权，返回新的根；已在同一个cluster或违反约束时返回None
+        fn merge(&mut self, a: usize, b: usize) -> Option<usize> {
+            let (a, b) = (self.uf.find(a), self.uf.find(b));
+            if a == b || !self.constraints.allows(&self.uf, &self.cluster_weight, a, b) {
+                return None;
+            }
+            let root = self.uf.union(a, b)?;
+            self.cluster_weight[root] = self.cluster_weight[a] + self.cluster_weight[b];
+            self.constraints.merged(root, a, b);
+            Some(root)
+        }
+
+        //设置约束并立即合并所有must-link，之后的合并都不会违反cannot-link和容量。
+        //must-link的合并记为hierarchy中权重为零的一层。点不存在时不改变状态
+        pub fn set_constraints(&mut self, constraints: &Constraints<T, W>) -> Result<ConstraintReport<T, W>, ClusteringError> {
+            let ids = |pairs: &[(T, T)]| -> Result<Vec::<(usize, usize)>, ClusteringError> {
+                pairs.iter().map(|&(a, b)| Ok((self.id(a)?, self.id(b)?))).collect()
+            };
+            let (must_link, cannot_link) = (ids(&constraints.must_link)?, ids(&constraints.cannot_link)?);
+            self.constraints = ConstraintSet::new(self.V.len(), must_link, cannot_link, constraints.max_cluster_weight);
+            //已有的cluster逐个重新登记带有cannot-link的点
+            for v in 0..self.V.len() {
+                let root = self.uf.find(v);
+                if root != v {
+                    self.constraints.merged(root, root, v);
+                }
+            }
+            let before: Vec::<usize> = self.uf.roots().collect();
+            let links = self.merge_must_links();
+            if !links.is_empty() {
+                self.edges_update();
+                self.record_level(&before, &links, false);
+            }
+            Ok(self.constraint_report())
+        }
+
+        //links为成功的must-link合并，连接边权记为零
+        fn merge_must_links(&mut self) -> Vec::<(usize, W)> {
+            let mut links = Vec::new();
+            for i in 0..self.constraints.must_link.len() {
+                let (a, _) = self.constraints.must_link[i];
+                if self.merge(a, self.constraints.must_link[i].1).is_some() {
+                    links.push((a, W::zero()));
+                }
+            }
+            links
+        }
+
+        //当前clustering不满足的约束
+        pub fn constraint_report(&mut self) -> ConstraintReport<T, W> {
+            let mut report = ConstraintReport { split_must_links: Vec::new(), joined_cannot_links: Vec::new(), oversized: Vec::new() };
+            for i in 0..self.constraints.must_link.len() {
+                let (a, b) = self.constraints.must_link[i];
+                if !self.uf.same_set(a, b) {
+                    report.split_must_links.push((self.V[a], self.V[b]));
                 }
             }
-            v_stack
-        }
-    
-        fn fragment_process(&mut self, round: u32) {
-            let init_group: Vec::<T> = self.uf.get_items();
-            for group_name in init_group {
-                if self.uf.items.contains_key(&group_name) {
-                    if *self.uf.size.get(&group_name).unwrap() < 2usize.pow(round) {
-                        let mut edges_of_group = Vec::<Edge<T>>::new();
-                        for e in &self.E {
-                            if e.start == group_name {
-                                edges_of_group.push(e.clone());
-                            }
+            for i in 0..self.constraints.cannot_link.len() {
+                let (a, b) = self.constraints.cannot_link[i];
+                if self.uf.same_set(a, b) {
+                    report.joined_cannot_links.push((self.V[a], self.V[b]));
+                }
+            }
+            if let Some(cap) = self.constraints.max_cluster_weight {
+                report.oversized = self.uf.roots().filter(|&r| self.cluster_weight[r] > cap).map(|r| (self.V[r], self.cluster_weight[r])).collect();
+            }
+            report
+        }
+
+        //links记录成功的合并：(合并中的一个点, 连接边的权重)，cluster数降到floor时停止
+        fn fragment_process(&mut self, round: u32, floor: usize, links: &mut Vec::<(usize, W)>) {
+            let threshold = fragment_threshold(self.vertex_weight.iter().copied().min(), round);
+            //邻接表只建一次，不再为每个小cluster扫描全部的边
+            let csr = Csr::from_triples(self.V.len(), self.E.iter().map(|e| (e.start, e.end, e.weight)));
+            let init_group: Vec::<usize> = self.uf.roots().collect();
+            for group in init_group {
+                if self.uf.is_root(group) && self.cluster_weight[group] < threshold {
+                    let mut edges_of_group: Vec::<(usize, W)> = csr.neighbors(group).collect();
+                    edges_of_group.sort_by_key(|x| x.1);
+                    for (end, weight) in edges_of_group {
+                        if self.uf.set_count() <= floor {
+                            return;
                         }
-                        edges_of_group.sort_by_key(|x| x.weight);
-                        for e in &edges_of_group {
-                            if self.uf.find(e.end) != group_name {
-                                self.uf.union(group_name, e.end);
-                            }
+                        if self.merge(group, end).is_some() {
+                            links.push((group, weight));
                         }
                     }
                 }
             }
         }
-    
+
         fn edges_update(&mut self) {
-            let mut new_edges = Vec::<Edge<T>>::new();
-                for e in &self.E {
-                    if self.uf.find(e.start) != self.uf.find(e.end) {
-                        new_edges.push(Edge {
-                            start: self.uf.find(e.start),
-                            end: self.uf.find(e.end),
-                            weight: e.weight,
-                        })
-                    }
-                }
-            self.E = new_edges;
+            let start = Instant::now();
+            //先把每个点的根算好，之后对边的收缩只读，可以并行
+            let roots: Vec::<usize> = (0..self.V.len()).map(|x| self.uf.find(x)).collect();
+            self.E = maybe_par_iter!(&self.E)
+                .filter_map(|e| {
+                    let (start, end) = (roots[e.start], roots[e.end]);
+                    if start == end { None } else { Some(Edge { start, end, weight: e.weight }) }
+                })
+                .collect();
+            self.contraction += start.elapsed();
+        }
+
+        //为before中的每个cluster选出最近邻：(目标在before中的下标, 边权, 距离)，最近邻自身也有最近邻时才保留
+        fn nearest_neighbors<L: Linkage<W> + ?Sized>(&self, before: &[usize], linkage: &L) -> Vec::<Option<(usize, W, L::Distance)>> {
+            let picked = self.nearest_links(before, linkage);
+            let has_nearest: Vec::<bool> = picked.iter().map(Option::is_some).collect();
+            picked.into_iter().map(|p| p.filter(|&(c, _, _)| has_nearest[c])).collect()
+        }
+
+        fn nearest_links<L: Linkage<W> + ?Sized>(&self, before: &[usize], linkage: &L) -> Vec::<Option<(usize, W, L::Distance)>> {
+            let mut local = vec![usize::MAX; self.V.len()];
+            for (i, &v) in before.iter().enumerate() {
+                local[v] = i;
+            }
+            //edges_update之后边的两端都是根；违反约束的合并不作为候选
+            let ends: Vec::<(usize, usize, W)> = maybe_par_iter!(&self.E)
+                .filter(|e| self.constraints.allows(&self.uf, &self.cluster_weight, e.start, e.end))
+                .map(|e| (local[e.start], local[e.end], e.weight))
+                .collect();
+            let csr = Csr::from_triples(before.len(), ends);
+            let weights: Vec::<W> = before.iter().map(|&c| self.cluster_weight[c]).collect();
+            let view = LinkView::new(&weights, |i| csr.neighbors(i));
+            maybe_par_iter!(0..before.len())
+                .map(|i| view.nearest(linkage, i))
+                .collect()
         }
-    
-        fn clustering(&mut self, FragmentProcess: bool, CommonNeighborCluster: bool) {
-            let mut number_of_clusters = self.V.len();
-            let mut vertexs = self.V.clone();
+
+        //CommonNeighborCluster为true时按最大边聚合（Max），否则按最小边聚合（Min）
+        pub fn clustering(&mut self, FragmentProcess: bool, CommonNeighborCluster: bool) -> Result<(), ClusteringError> {
+            if CommonNeighborCluster {
+                self.clustering_with(FragmentProcess, &Max)
+            } else {
+                self.clustering_with(FragmentProcess, &Min)
+            }
+        }
+
+        //每个cluster与linkage给出的距离最小的邻居合并
+        pub fn clustering_with<L: Linkage<W> + ?Sized>(&mut self, FragmentProcess: bool, linkage: &L) -> Result<(), ClusteringError> {
+            self.clustering_with_progress(FragmentProcess, linkage, |_| {})
+        }
+
+        //与clustering_with相同，每一轮结束后把本轮的统计交给progress
+        pub fn clustering_with_progress<L, F>(&mut self, FragmentProcess: bool, linkage: &L, progress: F) -> Result<(), ClusteringError>
+        where
+            L: Linkage<W> + ?Sized,
+            F: FnMut(&RoundStats),
+        {
+            let config = ClusteringConfig { fragment_process: FragmentProcess, ..ClusteringConfig::default() };
+            let report = self.clustering_with_config(&config, linkage, progress)?;
+            if report.stop == StopReason::NoMerges {
+                return Err(ClusteringError::NotConverged { rounds: report.rounds, clusters: report.clusters, k: self.k });
+            }
+            Ok(())
+        }
+
+        //按config控制的clustering，停止时报告是哪个条件先满足的。
+        //exact_k时停在k之上会继续逐步合并直到恰好k个cluster，任何一轮都不会合并到k以下
+        pub fn clustering_with_config<L, F>(&mut self, config: &ClusteringConfig, linkage: &L, mut progress: F) -> Result<ClusteringReport, ClusteringError>
+        where
+            L: Linkage<W> + ?Sized,
+            F: FnMut(&RoundStats),
+        {
+            let span = info_span!("clustering", vertices = self.V.len(), edges = self.E.len(), k = self.k);
+            let _guard = span.enter();
+            let start = Instant::now();
+            let floor = if config.exact_k { self.k } else { 1 };
+            let mut number_of_clusters = self.uf.set_count();
             let mut count = 0;
-            while number_of_clusters > self.k && count < 5 {
+            self.contraction = Duration::ZERO;
+            let stop = loop {
+                if number_of_clusters <= self.k {
+                    break StopReason::ReachedK;
+                }
+                if count >= config.max_rounds {
+                    break StopReason::MaxRounds;
+                }
+                if config.time_budget.is_some_and(|budget| start.elapsed() >= budget) {
+                    break StopReason::TimeBudget;
+                }
                 count += 1;
-                println!("-----------------------------------after {} rounds clustering-------------------------------", count);
-                self.clost_neighbors = HashMap::new();
-                self.merged = HashMap::new();
-                let selfe = &self.E;
-                let mut EEV = edges_of_every_vertexs(selfe);
-                let mut min_edges = Vec::<Edge<T>>::new();
-                let mut v_stack = HashMap::<T, Option<T>>::new();
-    
-                for value in EEV.values_mut() {
-                    value.sort_by_key(|x| x.weight);
-                    if CommonNeighborCluster == true {
-                        value.reverse();
+                let _round = debug_span!("round", round = count).entered();
+                let (before, mut links) = self.merge_round(linkage, floor);
+
+                //更新点
+                if self.uf.set_count() == number_of_clusters {
+                    //本轮没有任何合并（例如剩余的cluster之间已经没有边），继续迭代也无法达到k
+                    break StopReason::NoMerges;
+                }
+                let merged = number_of_clusters - self.uf.set_count();
+                number_of_clusters = self.uf.set_count();
+
+                if config.fragment_process {
+                    //处理碎片，可以避免极端不平衡的碎片，但会降低性能
+                    self.fragment_process(count, floor, &mut links);
+                    self.edges_update();
+                }
+                self.record_level(&before, &links, linkage.heavier_is_closer());
+                self.report_round(count, start, &mut progress);
+                if (merged as f64) < config.min_progress * before.len() as f64 {
+                    break StopReason::SlowProgress;
+                }
+            };
+
+            let mut final_merges = 0;
+            if config.exact_k && self.uf.set_count() > self.k {
+                let _pass = debug_span!("final_merge", clusters = self.uf.set_count(), k = self.k).entered();
+                let mut round = count;
+                while self.uf.set_count() > self.k {
+                    round += 1;
+                    let clusters = self.uf.set_count();
+                    let (before, mut links) = self.merge_round(linkage, self.k);
+                    if self.uf.set_count() == clusters {
+                        //cluster之间已经没有边或合并都违反约束，把最轻的两个cluster合并
+                        match self.merge_fallback(linkage) {
+                            Some((v, Some(weight))) => links.push((v, weight)),
+                            //没有连接边，record_level把这次合并记为不相连
+                            Some((_, None)) => {}
+                            None => break,
+                        }
+                        self.edges_update();
+                    }
+                    final_merges += clusters - self.uf.set_count();
+                    self.record_level(&before, &links, linkage.heavier_is_closer());
+                    self.report_round(round, start, &mut progress);
+                }
+            }
+
+            let report = ClusteringReport { stop, rounds: count, clusters: self.uf.set_count(), final_merges, elapsed: start.elapsed() };
+            info!(?report.stop, rounds = count, clusters = report.clusters, final_merges, elapsed_ms = report.elapsed.as_millis() as u64, "clustering finished");
+            Ok(report)
+        }
+
+        //每个cluster与其最近邻合并，等价于合并最近邻图中的连通分量。会使cluster数低于floor时
+        //按连接边从近到远逐条合并，到floor为止。返回本轮开始时的cluster与合并的连接边
+        fn merge_round<L: Linkage<W> + ?Sized>(&mut self, linkage: &L, floor: usize) -> (Vec::<usize>, Vec::<(usize, W)>) {
+            let before: Vec::<usize> = self.uf.roots().collect();
+            let clost_neighbors = self.nearest_neighbors(&before, linkage);
+            let mut links = Vec::<(usize, W)>::new();
+
+            let next: Vec::<usize> = clost_neighbors.iter().enumerate().map(|(i, c)| c.as_ref().map_or(i, |&(c, _, _)| c)).collect();
+            let labels = nn_components(&next);
+            let components = labels.iter().enumerate().filter(|&(i, &l)| i == l).count();
+            //有约束时一个分量内的合并可能被拒绝，所以沿最近邻边逐条合并
+            if components >= floor && self.constraints.is_empty() {
+                for (i, &v) in before.iter().enumerate() {
+                    if labels[i] != i {
+                        self.merge(before[labels[i]], v);
+                    }
+                    if let Some((_, weight, _)) = clost_neighbors[i] {
+                        links.push((v, weight));
                     }
-                    min_edges.push(value[0].clone());
                 }
-    
-                for edge in min_edges {
-                    self.clost_neighbors.insert(edge.start, edge.end);
+            } else {
+                //与不受限时的顺序一致，按linkage的距离从近到远
+                let mut candidates: Vec::<(usize, usize, W, L::Distance)> = clost_neighbors
+                    .into_iter()
+                    .enumerate()
+                    .filter_map(|(i, c)| c.map(|(c, weight, d)| (i, c, weight, d)))
+                    .collect();
+                candidates.sort_by(|a, b| a.3.cmp(&b.3).then(a.0.cmp(&b.0)));
+                for (i, c, weight, _) in candidates {
+                    if self.uf.set_count() <= floor {
+                        break;
+                    }
+                    if self.merge(before[i], before[c]).is_some() {
+                        links.push((before[i], weight));
+                    }
+                }
+            }
+
+            //更新两个cluster之间的边
+            self.edges_update();
+            (before, links)
+        }
+
+        //一轮没有任何合并时exact_k的最后手段。优先在有边相连且满足约束的cluster对中合并linkage距离最近的一对，
+        //距离相同时取点权和较小的，返回(合并中的一个点, Some(连接边权))；没有这样的一对时（图不连通或约束不允许）
+        //合并点权最小且满足约束的两个cluster，返回(合并中的一个点, None)；都不行时返回None
+        fn merge_fallback<L: Linkage<W> + ?Sized>(&mut self, linkage: &L) -> Option<(usize, Option<W>)> {
+            let mut roots: Vec::<usize> = self.uf.roots().collect();
+            let nearest = self.nearest_links(&roots, linkage);
+            let closest = nearest
+                .into_iter()
+                .enumerate()
+                .filter_map(|(i, n)| n.map(|(c, weight, d)| (d, self.cluster_weight[roots[i]] + self.cluster_weight[roots[c]], i, c, weight)))
+                .min_by(|a, b| a.0.cmp(&b.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+            //nearest_links只考虑满足约束的边，所以合并一定成功
+            if let Some((_, _, i, c, weight)) = closest {
+                if self.merge(roots[i], roots[c]).is_some() {
+                    return Some((roots[i], Some(weight)));
+                }
+            }
+            roots.sort_by_key(|&r| (self.cluster_weight[r], r));
+            for i in 0..roots.len() {
+                for j in i + 1..roots.len() {
+                    if self.merge(roots[i], roots[j]).is_some() {
+                        debug!(a = %self.V[roots[i]], b = %self.V[roots[j]], "merged clusters without an edge between them");
+                        return Some((roots[i], None));
+                    }
                 }
-                
-                for v in &vertexs {
-                    if !self.merged.contains_key(&v) {
-                        v_stack = self.merge_with_cloest_neighbors(*v, v_stack);
+            }
+            None
+        }
+
+        fn report_round<F: FnMut(&RoundStats)>(&mut self, round: u32, start: Instant, progress: &mut F) {
+            let contraction = std::mem::take(&mut self.contraction);
+            let stats = RoundStats { round, clusters: self.uf.set_count(), edges: self.E.len(), elapsed: start.elapsed(), contraction };
+            debug!(clusters = stats.clusters, edges = stats.edges, elapsed_ms = stats.elapsed.as_millis() as u64, contraction_us = contraction.as_micros() as u64, "clustering round finished");
+            progress(&stats);
+        }
+
+        pub fn apply_changes(&mut self, changes: &[EdgeChange<T, W>], CommonNeighborCluster: bool) -> Result<ChangeReport<T>, ClusteringError> {
+            if CommonNeighborCluster {
+                self.apply_changes_with(changes, &Max)
+            } else {
+                self.apply_changes_with(changes, &Min)
+            }
+        }
+
+        //按顺序应用changes，拆散含有被修改边端点的cluster，只让拆出的点按linkage重新合并，
+        //直到cluster数不超过修改前的数量。其余cluster保持不变，只会吸收拆出的点。
+        //任何一个修改出错时不改变状态。hierarchy仍是最初clustering的记录
+        pub fn apply_changes_with<L: Linkage<W> + ?Sized>(&mut self, changes: &[EdgeChange<T, W>], linkage: &L) -> Result<ChangeReport<T>, ClusteringError> {
+            let _span = debug_span!("apply_changes", changes = changes.len()).entered();
+            let mut edges = self.vertex_edges.clone();
+            let mut touched = Vec::<usize>::new();
+            for change in changes {
+                let (start, end) = match change {
+                    EdgeChange::Insert(e) | EdgeChange::Reweight(e) => (e.start, e.end),
+                    EdgeChange::Delete { start, end } => (*start, *end),
+                };
+                let (s, t) = (self.id(start)?, self.id(end)?);
+                let unknown = || ClusteringError::UnknownEdge { start: start.to_string(), end: end.to_string() };
+                match change {
+                    //与建图时一样拒绝负权重
+                    EdgeChange::Insert(e) => edges.push(Edge { start: s, end: t, weight: check_weight(e.weight)? }),
+                    EdgeChange::Delete { .. } => {
+                        let count = edges.len();
+                        edges.retain(|e| e.start != s || e.end != t);
+                        if edges.len() == count {
+                            return Err(unknown());
+                        }
+                    }
+                    EdgeChange::Reweight(changed) => {
+                        let weight = check_weight(changed.weight)?;
+                        let mut found = false;
+                        for e in edges.iter_mut().filter(|e| e.start == s && e.end == t) {
+                            e.weight = weight;
+                            found = true;
+                        }
+                        if !found {
+                            return Err(unknown());
+                        }
+                    }
+                }
+                touched.push(s);
+                touched.push(t);
+            }
+            self.vertex_edges = edges;
+
+            let n = self.V.len();
+            let target = self.uf.set_count();
+            let old_root: Vec::<usize> = (0..n).map(|v| self.uf.find(v)).collect();
+            let mut dissolved = vec![false; n];//以修改前的根为下标
+            for &v in &touched {
+                dissolved[old_root[v]] = true;
+            }
+            //重建并查集：未受影响的cluster原样保留，被拆散的cluster中的点成为单点cluster
+            self.uf = DisjointSet::new(n);
+            self.cluster_weight = self.vertex_weight.clone();
+            self.constraints.reset(n);
+            for v in 0..n {
+                if !dissolved[old_root[v]] {
+                    self.merge(old_root[v], v);
+                }
+            }
+            self.merge_must_links();
+            self.E = self.vertex_edges.clone();
+            self.edges_update();
+
+            //free[root]表示该cluster只由拆出的点组成；只有这样的cluster会去找最近邻
+            let mut free: Vec::<bool> = (0..n).map(|v| dissolved[old_root[v]]).collect();
+            let mut rounds = 0;
+            while self.uf.set_count() > target && self.uf.roots().any(|r| free[r]) {
+                rounds += 1;
+                let before: Vec::<usize> = self.uf.roots().collect();
+                let nearest = self.nearest_neighbors(&before, linkage);
+                let next: Vec::<usize> = nearest
+                    .iter()
+                    .enumerate()
+                    .map(|(i, c)| match c {
+                        Some((c, _, _)) if free[before[i]] => *c,
+                        _ => i,
+                    })
+                    .collect();
+                let labels = nn_components(&next);
+                let number_of_clusters = self.uf.set_count();
+                for (i, &v) in before.iter().enumerate() {
+                    if labels[i] != i {
+                        let both_free = free[self.uf.find(before[labels[i]])] && free[self.uf.find(v)];
+                        if let Some(root) = self.merge(before[labels[i]], v) {
+                            free[root] = both_free;
+                        }
                     }
                 }
-    
-                //更新两个cluster之间的边
                 self.edges_update();
-    
-                //更新点
-                vertexs = self.uf.get_items();
-                number_of_clusters = vertexs.len();
-    
-                if FragmentProcess == true {
-                    //处理碎片，可以避免极端不平衡的碎片，但会降低性能
-                    self.fragment_process(count);
-                    self.edges_update();
-                    //self.print_all_clusters();
+                if self.uf.set_count() == number_of_clusters {
+                    break;
+                }
+            }
+            debug!(rounds, clusters = self.uf.set_count(), "changes applied");
+            Ok(self.change_report(&old_root, &dissolved, rounds))
+        }
+
+        //按重合的点数把新cluster与修改前的cluster对应起来，对应不上的点记为移动
+        fn change_report(&mut self, old_root: &[usize], dissolved: &[bool], rounds: u32) -> ChangeReport<T> {
+            let n = self.V.len();
+            let new_root: Vec::<usize> = (0..n).map(|v| self.uf.find(v)).collect();
+            let mut identity = vec![usize::MAX; n];//以新的根为下标，值为对应的修改前的根
+            for v in 0..n {
+                if !dissolved[old_root[v]] {
+                    identity[new_root[v]] = old_root[v];
+                }
+            }
+            //只由拆出的点组成的cluster按重合点数从多到少认领修改前的cluster，相同时按编号
+            let mut overlap = HashMap::<(usize, usize), usize>::new();
+            for v in 0..n {
+                if identity[new_root[v]] == usize::MAX {
+                    *overlap.entry((new_root[v], old_root[v])).or_insert(0) += 1;
+                }
+            }
+            let mut candidates: Vec::<((usize, usize), usize)> = overlap.into_iter().collect();
+            candidates.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+            let mut claimed = vec![false; n];
+            for ((new, old), _) in candidates {
+                if identity[new] == usize::MAX && !claimed[old] {
+                    identity[new] = old;
+                    claimed[old] = true;
                 }
-                //print_edges(&self.E);
-                //self.print_all_clusters();
-                println!("present number of clusters is: {}", number_of_clusters);
+            }
+            ChangeReport {
+                dissolved: (0..n).filter(|&r| dissolved[r]).map(|r| self.V[r]).collect(),
+                moved: (0..n)
+                    .filter(|&v| identity[new_root[v]] != old_root[v])
+                    .map(|v| VertexMove { vertex: self.V[v], from: self.V[old_root[v]], to: self.V[new_root[v]] })
+                    .collect(),
+                rounds,
+                clusters: self.uf.set_count(),
             }
         }
 
+        //把本轮开始时的cluster按合并后的根分组，写入hierarchy的一层
+        fn record_level(&mut self, before: &[usize], links: &[(usize, W)], heavier_is_closer: bool) {
+            let mut position = HashMap::<usize, usize>::new();
+            let mut merges = Vec::<ClusterMerge<W>>::new();
+            for &c in before {
+                let parent = self.uf.find(c);
+                let i = *position.entry(parent).or_insert_with(|| {
+                    merges.push(ClusterMerge { parent, children: Vec::new(), weight: W::zero(), linked: true });
+                    merges.len() - 1
+                });
+                merges[i].children.push(c);
+            }
+            //最弱的连接边：边权表示距离时是最大的权重，表示相似度（例如common neighbor数）时是最小的权重
+            let mut weakest = HashMap::<usize, W>::new();
+            for &(v, weight) in links {
+                let parent = self.uf.find(v);
+                let w = weakest.entry(parent).or_insert(weight);
+                *w = if heavier_is_closer { (*w).min(weight) } else { (*w).max(weight) };
+            }
+            merges.retain(|m| m.children.len() > 1);
+            for m in merges.iter_mut() {
+                m.children.sort_unstable();
+                //没有连接边的合并只来自exact_k对不连通的cluster的合并
+                match weakest.get(&m.parent) {
+                    Some(&w) => m.weight = w,
+                    None => m.linked = false,
+                }
+            }
+            //不相连的合并排在最后
+            if heavier_is_closer {
+                merges.sort_by(|a, b| b.linked.cmp(&a.linked).then(b.weight.cmp(&a.weight)).then(a.parent.cmp(&b.parent)));
+            } else {
+                merges.sort_by(|a, b| b.linked.cmp(&a.linked).then(a.weight.cmp(&b.weight)).then(a.parent.cmp(&b.parent)));
+            }
+            self.hierarchy.push_level(merges);
+        }
+
         pub fn linear_embed(&self) -> Vec::<T> {
             let mut line = Vec::<T>::new();
-            for (_, cluster) in &self.uf.items {
-                line.append(&mut cluster.clone());
+            for root in self.uf.roots() {
+                line.extend(self.uf.members(root).map(|x| self.V[x]));
             }
             line
         }
     }
 
-    pub fn edges_of_every_vertexs<T:Debug + Display + Copy + Hash + Eq> (edges: &Vec::<Edge<T>>) -> HashMap::<T, Vec::<Edge<T>>> {//找到每个点的所有边
-        let mut edges_of_v = HashMap::<T, Vec::<Edge<T>>>::new();
-        for edge in edges {
-            if edges_of_v.contains_key(&edge.start) {
-                edges_of_v.get_mut(&edge.start).unwrap().push(edge.clone());
-            } else {
-                edges_of_v.insert(edge.start, vec!(edge.clone()));
+    //cluster点权低于最轻的点的2^round倍时视为碎片，点权都为1时即cluster大小小于2^round；
+    //最轻的点权为0时按1计算，翻倍到类型的最大值为止
+    pub(crate) fn fragment_threshold<W: Weight>(lightest: Option<W>, round: u32) -> W {
+        let mut threshold = lightest.filter(|&w| w > W::zero()).unwrap_or_else(W::one);
+        for _ in 0..round {
+            let doubled = threshold.saturating_add(threshold);
+            if doubled == threshold {
+                break;
             }
+            threshold = doubled;
         }
-        edges_of_v
+        threshold
     }
-    
-    impl<T:Debug + Display + Copy + Hash + Eq> ArrayUnion<T> {
-        fn new_and_init(V: Vec::<T>) -> Self {
-            let mut ArrUni = ArrayUnion {
-                group: HashMap::new(),
-                size: HashMap::new(),
-                items: HashMap::new(),
-            };
-            for v in V {
-                ArrUni.group.insert(v, v);
-                ArrUni.size.insert(v, 1);
-                ArrUni.items.insert(v, vec!(v));
-            }
-            ArrUni
-        }
-    
-        fn find(&self, target: T) -> T {//返回包含target的group id
-             if let Some(gp) = self.group.get(&target) {
-                 *gp
-             } else {
-                 panic!("Error happened when finding the target's group!");
-             }
-        }
-    
-        fn union(&mut self, mut a: T, mut b: T) {
-            if !(self.items.contains_key(&a) && self.items.contains_key(&b)) {
-                //panic!("Error: a and b are not both in items");
-                return ;
-            }
-    
-            if self.size.get(&a).unwrap() > self.size.get(&b).unwrap() {
-                let temp = a;
-                a = b;
-                b = temp;
-            }
-    
-            for s in self.items.clone().get_mut(&a) {
-                if let Some(x) = self.group.get_mut(&a) {
-                    *x = b
-                } else {
-                    panic!("Failed to get a from items a");
-                }
-    
-                if let Some(x) = self.items.get_mut(&b) {
-                    (*x).append(s);
-                } else {
-                    panic!("Failed to get a from items b");
-                }
+
+    //最近邻图中每个点恰有一条出边（没有最近邻的点指向自己），其弱连通分量即本轮合并后的cluster。
+    //倍增到2^r >= n步后每个点都落在所在分量的环上，取环上最小的下标作为整个分量的标签
+    pub(crate) fn nn_components(next: &[usize]) -> Vec::<usize> {
+        let n = next.len();
+        let mut to = next.to_vec();
+        let mut low: Vec::<usize> = (0..n).collect();//从v出发走2^r步经过的点中最小的下标
+        let mut steps = 1;
+        while steps < n {
+            let (to2, low2): (Vec::<usize>, Vec::<usize>) = maybe_par_iter!(0..n)
+                .map(|v| (to[to[v]], low[v].min(low[to[v]])))
+                .unzip();
+            to = to2;
+            low = low2;
+            steps *= 2;
+        }
+        maybe_par_iter!(0..n).map(|v| low[to[v]]).collect()
+    }
+
+    fn vertices_in_order<T:Copy + Hash + Eq, W> (edges: &[Edge<T, W>]) -> Vec::<T> {
+        let mut v_set = HashSet::<T>::new();
+        let mut v = Vec::<T>::new();
+        for e in edges.iter() {
+            if v_set.insert(e.start) {
+                v.push(e.start);
+            }
+            if v_set.insert(e.end) {
+                v.push(e.end);
             }
-    
-            *self.size.get_mut(&b).unwrap() += *self.size.get(&a).unwrap();
-            self.size.remove(&a);
-            self.items.remove(&a);
         }
-    
-        fn get_items(&self) -> Vec::<T> {
-            self.items.keys().into_iter().map(|&x| x).collect()//将hashmap中所有key或者value输出的方法
+        v
+    }
+
+    //转换为以common neighbor数为边权的图：两点每有一个共同的邻居（同一个终点），边权加一。
+    //逐点流式计算，不建点对表；结果按点对中先出现的点的顺序排列
+    pub fn common_neighbor_edges<T:Copy + Hash + Eq, W> (edges: &[Edge<T, W>]) -> Vec::<Edge<T>> {
+        crate::neighborhood::common_neighbors(edges, &crate::neighborhood::NeighborhoodOptions::default())
+    }
+
+    pub fn edges_of_every_vertexs<T:Debug + Display + Copy + Hash + Eq, W: Weight> (edges: &[Edge<T, W>]) -> HashMap::<T, Vec::<Edge<T, W>>> {//找到每个点的所有边
+        let mut edges_of_v = HashMap::<T, Vec::<Edge<T, W>>>::new();
+        for edge in edges {
+            edges_of_v.entry(edge.start).or_default().push(edge.clone());
         }
+        edges_of_v
+    }
+
+    //对图的一次修改，边是有向的，无向边的两个方向需要分别给出
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub enum EdgeChange<T, W = usize> {
+        Insert(Edge<T, W>),
+        Delete { start: T, end: T },//删除从start到end的所有边
+        Reweight(Edge<T, W>),//把从start到end的所有边的权重改为weight
     }
-    
-    pub struct Edge<T> {
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct VertexMove<T> {
+        pub vertex: T,
+        pub from: T,//修改前所在cluster的名字
+        pub to: T,//修改后所在cluster的名字
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct ChangeReport<T> {
+        pub dissolved: Vec::<T>,//被拆散后重新聚类的cluster，用修改前的名字表示
+        pub moved: Vec::<VertexMove<T>>,//换了cluster的点，按编号顺序
+        pub rounds: u32,
+        pub clusters: usize,
+    }
+
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct Edge<T, W = usize> {
         pub start: T,
         pub end: T,
-        pub weight: usize
+        pub weight: W
     }
-    
+
     /*impl<T:Debug + Display + Copy + Hash + Eq> Edge<T> {
         fn clone(&self) -> Self {
             Edge {
@@ -266,7 +813,7 @@ pub mod affinity_clustering {
         }
     }*/
 
-    impl<T:Debug + Display + Copy + Hash + Eq> Clone for Edge<T> {
+    impl<T:Copy, W:Copy> Clone for Edge<T, W> {
         fn clone(&self) -> Self {
             Edge {
                 start: self.start,
@@ -275,82 +822,148 @@ pub mod affinity_clustering {
             }
         }
     }
-    
-    fn MST<T:Debug + Display + Copy + Hash + Eq> (edges: &mut Vec::<Edge<T>>) -> Vec::<Edge<T>> {
-        let mut mst = Vec::<Edge<T>>::new();
-        edges.sort_by_key(|x| x.weight);
-        let mut v_set = HashMap::<T, Option<usize>>::new();//使用空value的hashmap作为集合
-        for e in edges.iter() {//直接使用edge调用into_iter，发生所有权转移
-            v_set.insert(e.start, None);
-            v_set.insert(e.end, None);
+
+    //以下为边稀疏化使用的函数：把边按(起点的partition_key, 终点的partition_key)随机分组，
+    //每组只保留其最小生成树。全图MST中的边一定是其所在组的MST边，所以稀疏化后的边集与原图有相同的MST
+    type StartGroups<T, W> = Vec::<(T, Vec::<Edge<T, W>>)>;//按起点分组的边
+    type EndGroups<T, W> = Vec::<(T, Vec::<(usize, Edge<T, W>)>)>;//按终点分组的边及其partition_key
+    type KeyedEdges<T, W> = Vec::<(T, (usize, Edge<T, W>))>;//(终点, (partition_key, 边))
+
+    //每一轮稀疏化后的统计信息
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct SparsifyRound {
+        pub round: u32,
+        pub partitions: usize,//本轮每一端的随机划分数k
+        pub edges: usize,//本轮结束后剩余的边数
+        pub c: f32,//本轮结束后的c = ln(m)/ln(n) - 1
+    }
+
+    //clustering_with_config的停止条件，默认与clustering相同：最多5轮，不限进度和时间
+    #[derive(Debug, Clone, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct ClusteringConfig {
+        pub fragment_process: bool,
+        pub max_rounds: u32,
+        pub min_progress: f64,//一轮合并掉的cluster占本轮开始时cluster数的比例低于该值时停止
+        pub time_budget: Option<Duration>,//超过后不再开始新的一轮
+        pub exact_k: bool,//停在k之上时继续逐对合并到恰好k个cluster
+    }
+
+    impl Default for ClusteringConfig {
+        fn default() -> Self {
+            ClusteringConfig { fragment_process: false, max_rounds: 5, min_progress: 0.0, time_budget: None, exact_k: false }
         }
-        let V = v_set.keys().into_iter().map(|&x| x).collect();
-        let mut UF = ArrayUnion::new_and_init(V);
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub enum StopReason {
+        ReachedK,
+        MaxRounds,
+        SlowProgress,//一轮的进度低于min_progress
+        TimeBudget,
+        NoMerges,//一轮中没有任何合并，例如剩余的cluster之间已经没有边
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct ClusteringReport {
+        pub stop: StopReason,//最先满足的停止条件，exact_k的合并在其之后进行
+        pub rounds: u32,//不含exact_k的合并
+        pub clusters: usize,
+        pub final_merges: usize,//exact_k时为了达到k额外合并的次数
+        pub elapsed: Duration,
+    }
+
+    //clustering每一轮结束后的统计，交给clustering_with_progress的回调
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct RoundStats {
+        pub round: u32,
+        pub clusters: usize,//本轮结束后剩余的cluster数
+        pub edges: usize,//本轮结束后cluster之间剩余的边数
+        pub elapsed: Duration,//从clustering开始到本轮结束的时间
+        pub contraction: Duration,//本轮把边收缩到cluster根上（edges_update）所用的时间
+    }
+
+    fn MST<T:Debug + Display + Copy + Hash + Eq, W: Weight> (edges: &mut [Edge<T, W>]) -> Vec::<Edge<T, W>> {
+        let mut mst = Vec::<Edge<T, W>>::new();
+        edges.sort_by_key(|x| x.weight);
+        let v = vertices_in_order(edges);
+        let index: HashMap::<T, usize> = v.iter().enumerate().map(|(i, &x)| (x, i)).collect();
+        let mut uf = DisjointSet::new(v.len());
         for e in edges.iter() {
-            let u_group = UF.find(e.start);
-            let v_group = UF.find(e.end);
-            
-            if u_group != v_group {
+            if uf.union(index[&e.start], index[&e.end]).is_some() {
                 mst.push(e.clone());
-                UF.union(u_group, v_group)
             }
         }
         mst
     }
-    
-    fn partition1<T:Debug + Display + Copy + Hash + Eq> (v_edges: &HashMap::<T, Vec::<Edge<T>>>, k: usize) 
-                        ->Vec::<(T, (usize, Edge<T>))> {
-        //对相同起点的边进行随机划分，并分配partition_key
-        let mut out = Vec::<(T, (usize, Edge<T>))>::new();
-        let mut rng = thread_rng();
-        let partition_key =  rng.gen_range(0, k);
-        for (v, edges) in v_edges.iter() {
+
+    fn group_by_start<T:Debug + Display + Copy + Hash + Eq, W: Weight> (edges: &[Edge<T, W>]) -> StartGroups<T, W> {
+        //按起点分组，分组顺序为起点首次出现的顺序，保证随机数的抽取顺序固定
+        let mut index = HashMap::<T, usize>::new();
+        let mut out = StartGroups::<T, W>::new();
+        for e in edges {
+            let i = *index.entry(e.start).or_insert_with(|| {
+                out.push((e.start, Vec::new()));
+                out.len() - 1
+            });
+            out[i].1.push(e.clone());
+        }
+        out
+    }
+
+    fn partition1<T:Debug + Display + Copy + Hash + Eq, W: Weight, R: Rng + ?Sized> (v_edges: &StartGroups<T, W>, k: usize, rng: &mut R)
+                        ->KeyedEdges<T, W> {
+        //对相同起点的边进行随机划分，并分配partition_key，每个起点单独抽取
+        let mut out = KeyedEdges::<T, W>::new();
+        for (_, edges) in v_edges.iter() {
+            let partition_key =  rng.gen_range(0, k);
             for e in edges {
                 out.push((e.end, (partition_key, e.clone())));
             }
         }
         out
     }
-    
-    fn group_by_end<T:Debug + Display + Copy + Hash + Eq> (edges: Vec::<(T, (usize, Edge<T>))>) -> HashMap::<T, Vec::<(usize, Edge<T>)>> {
-        let mut out = HashMap::<T, Vec::<(usize, Edge<T>)>>::new();
+
+    fn group_by_end<T:Debug + Display + Copy + Hash + Eq, W: Weight> (edges: KeyedEdges<T, W>) -> EndGroups<T, W> {
+        //按终点分组，分组顺序为终点首次出现的顺序，保证后续随机数的抽取顺序固定
+        let mut index = HashMap::<T, usize>::new();
+        let mut out = EndGroups::<T, W>::new();
         for edge in edges {
-            if out.contains_key(&edge.0) {
-                out.get_mut(&edge.0).unwrap().push((edge.1.0, edge.1.1));
-            } else {
-                out.insert(edge.0, vec!((edge.1.0, edge.1.1)));
-            }
+            let i = *index.entry(edge.0).or_insert_with(|| {
+                out.push((edge.0, Vec::new()));
+                out.len() - 1
+            });
+            out[i].1.push((edge.1.0, edge.1.1));
         }
         out
     }
-    
-    fn group_and_MST<T:Debug + Display + Copy + Hash + Eq> (edges: Vec::<((usize, usize), Edge<T>)>) -> Vec::<Edge<T>> {
-        let mut cluster_edges = HashMap::<(usize, usize), Vec::<Edge<T>>>::new();
-        let mut mst = Vec::<Edge<T>>::new();
+
+    fn group_and_MST<T:Debug + Display + Copy + Hash + Eq, W: Weight> (edges: Vec::<((usize, usize), Edge<T, W>)>) -> Vec::<Edge<T, W>> {
+        let mut cluster_edges = HashMap::<(usize, usize), Vec::<Edge<T, W>>>::new();
+        let mut mst = Vec::<Edge<T, W>>::new();
         for e in edges {
-            if cluster_edges.contains_key(&e.0) {
-                cluster_edges.get_mut(&e.0).unwrap().push(e.1);
-            } else {
-                cluster_edges.insert(e.0, vec!(e.1));
-            }
+            cluster_edges.entry(e.0).or_default().push(e.1);
         }
-    
-        for (_, mut ed) in cluster_edges {
-            //println!("before MST is: {}", e.len());
-            let mut mst_output = MST(&mut ed);
-    
-            //println!("after MST is: {}", mst_output.len());
+
+        let mut keys: Vec::<(usize, usize)> = cluster_edges.keys().copied().collect();
+        keys.sort_unstable();
+        for key in keys {
+            let ed = cluster_edges.get_mut(&key).unwrap();
+            let mut mst_output = MST(ed);
             mst.append(&mut mst_output);
         }
         mst
     }
-    
-    fn partition2<T:Debug + Display + Copy + Hash + Eq> (v_edges: &HashMap::<T, Vec::<(usize, Edge<T>)>>, k: usize) 
-                        -> Vec::<((usize, usize), Edge<T>)> {
+
+    fn partition2<T:Debug + Display + Copy + Hash + Eq, W: Weight, R: Rng + ?Sized> (v_edges: &EndGroups<T, W>, k: usize, rng: &mut R)
+                        -> Vec::<((usize, usize), Edge<T, W>)> {
         //对相同终点的边进行随机划分，并分配first_partition
-        let mut out = Vec::<((usize, usize), Edge<T>)>::new();
-        for (v, edges) in v_edges.iter() {
-            let mut rng = thread_rng();
+        let mut out = Vec::<((usize, usize), Edge<T, W>)>::new();
+        for (_, edges) in v_edges.iter() {
             let partition_key =  rng.gen_range(0, k);
             for e in edges {
                 let first_partition = e.0;
@@ -360,14 +973,57 @@ pub mod affinity_clustering {
         }
         out
     }
-    
-    pub fn make_random_graph_matrix (verticle: usize) -> (Array2::<usize>, Vec::<Edge<usize>>) {//随机生成一个图，usize类型不满足ndarray::IntoDimension特征，故verticle用usize
+
+    //重复进行 partition1 -> group_by_end -> partition2 -> group_and_MST，直到c <= epsilon
+    pub fn sparsify<T:Debug + Display + Copy + Hash + Eq, W: Weight, R: Rng + ?Sized>(mut edges: Vec::<Edge<T, W>>, epsilon: f32, rng: &mut R)
+        -> (Vec::<Edge<T, W>>, Vec::<SparsifyRound>) {
+        let mut rounds = Vec::<SparsifyRound>::new();
+        let n = vertices_in_order(&edges).len() as f32;
+        if n < 2.0 {
+            return (edges, rounds);
+        }
+        //记录原图中的有向边，稀疏化后把保留下来的边的反向边补回，使每个点仍有出边
+        let original: HashMap::<(T, T), W> = edges.iter().map(|e| ((e.start, e.end), e.weight)).collect();
+        let c_of = |m: f32| m.ln().ceil() / n.ln().ceil() - 1.0;
+        let mut c: f32 = c_of(edges.len() as f32);
+        let mut round = 0;
+        let _span = debug_span!("sparsify", vertices = n as usize, edges = edges.len(), epsilon).entered();
+        while c > epsilon {
+            let k = ((n.powf((c - epsilon) / 2.0).floor()) as usize).max(1);
+            let before = edges.len();
+            let eev = group_by_start(&edges);
+            let half_partition = partition1(&eev, k, rng);
+            let same_start = group_by_end(half_partition);
+            let full_partition = partition2(&same_start, k, rng);
+            edges = group_and_MST(full_partition);
+            round += 1;
+            c = c_of(edges.len() as f32);
+            rounds.push(SparsifyRound { round, partitions: k, edges: edges.len(), c });
+            debug!(round, partitions = k, edges = edges.len(), c, "sparsify round finished");
+            if edges.len() >= before {
+                break;
+            }
+        }
+        if round > 0 {
+            let mut kept: HashSet::<(T, T)> = edges.iter().map(|e| (e.start, e.end)).collect();
+            for i in 0..edges.len() {
+                let (s, t) = (edges[i].start, edges[i].end);
+                if let Some(&weight) = original.get(&(t, s)) {
+                    if kept.insert((t, s)) {
+                        edges.push(Edge { start: t, end: s, weight });
+                    }
+                }
+            }
+        }
+        (edges, rounds)
+    }
+
+    pub fn make_random_graph_matrix<R: Rng + ?Sized> (verticle: usize, rng: &mut R) -> (Array2::<usize>, Vec::<Edge<usize>>) {//随机生成一个图，usize类型不满足ndarray::IntoDimension特征，故verticle用usize
         let mut data = Array2::<usize>::zeros((verticle, verticle));
         let mut i = 0;
-        let mut j = 0;
         let mut edges = Vec::<Edge<usize>>::new();
         while i < verticle {
-            j = 0;
+            let mut j = 0;
             while j < verticle {
                 if i == j {
                     data.row_mut(i)[j] = 0;
@@ -376,21 +1032,21 @@ pub mod affinity_clustering {
                     data.row_mut(i)[j] = data.row(j)[i];
                     j += 1;
                 } else {
-                    let rand_number1 = rand::thread_rng().gen_range(1, j-i+1);
+                    let rand_number1 = rng.gen_range(1, j-i+1);
                     if rand_number1 != 1 {
                         data.row_mut(i)[j] = 10000;
                     } else {
-                        let rand_number2 = rand::thread_rng().gen_range(1, j-i+1);
+                        let rand_number2 = rng.gen_range(1, j-i+1);
                         data.row_mut(i)[j] = rand_number2;
                         edges.push(Edge {
                             start: i,
                             end: j,
-                            weight: rand_number2 as usize
+                            weight: rand_number2
                         });
                         edges.push(Edge {
                             start: j,
                             end: i,
-                            weight: rand_number2 as usize
+                            weight: rand_number2
                         })
                         }
                     j += 1;
@@ -398,50 +1054,459 @@ pub mod affinity_clustering {
             }
             i += 1;
         }
-        //print_edges(&edges);
         (data, edges)
     }
-    
-    pub fn print_edges<T:Debug + Display + Copy + Hash + Eq> (edges: &Vec::<Edge<T>>) {
-        for edge in edges {
-            println!("start:{}, end:{}, weight:{}" ,edge.start, edge.end, edge.weight);
-        }
-    }
 
-    pub fn make_cluster<T:Debug + Display + Copy + Hash + Eq>(epsilon: f32, mut edges: Vec::<Edge<T>>, cluster_threshold: usize, FragmentProcess: bool,
-        CommonNeighborCluster: bool) -> Affinity<T> {
-        let mut v_set = HashSet::<T>::new();
-        for e in edges.iter() {
-            v_set.insert(e.start);
-            v_set.insert(e.end);
-        }//对edges扫描以计算v_set可以只算一次，待优化
-        let n = v_set.len() as f32;
-        let mut m = edges.len() as f32;
-        let mut c: f32 = m.ln().ceil() / n.ln().ceil() - 1.0;
-        //println!("total edges is:{}", edges.len());
-        /*while c > epsilon {
-            let k = (n.powf((c - epsilon) / 2.0).floor()) as usize;
-            c = m.ln().ceil() / n.ln().ceil() - 1.0;
-            let eev = edges_of_every_vertexs(&edges);
-            let half_partition = partition1(&eev, k);
-            let same_start = group_by_end(half_partition);
-            let full_partition = partition2(&same_start, k);
-            edges = group_and_MST(full_partition);
-            m = edges.len() as f32;
-            println!("total edges of MST is:{}, present c is: {}", edges.len(), c);
-        }*/
-        let mut af = Affinity::new_and_init(&edges, cluster_threshold);
-        af.clustering(FragmentProcess, CommonNeighborCluster);//CommonNeighborCluster为true表示对commonneighbor进行聚合
-        //af.print_all_clusters();
-        af
+    //rng用于边稀疏化时的随机划分，传入相同种子的rng可复现同样的结果
+    pub fn make_cluster<T:Debug + Display + Copy + Hash + Eq + Send + Sync, W: Weight, R: Rng + ?Sized>(epsilon: f32, edges: Vec::<Edge<T, W>>, cluster_threshold: usize, FragmentProcess: bool,
+        CommonNeighborCluster: bool, rng: &mut R) -> Result<Affinity<T, W>, ClusteringError> {
+        let _span = info_span!("make_cluster", edges = edges.len(), cluster_threshold).entered();
+        let (edges, rounds) = sparsify(edges, epsilon, rng);
+        let graph = Graph::from_edges(&edges)?;
+        let mut af = Affinity::new_and_init(&graph, cluster_threshold)?;
+        af.sparsify_rounds = rounds;
+        af.clustering(FragmentProcess, CommonNeighborCluster)?;//CommonNeighborCluster为true表示对commonneighbor进行聚合
+        Ok(af)
     }
 
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::affinity_clustering::*;
+    use crate::disjoint_set::DisjointSet;
+    use crate::graph::Graph;
+    use crate::weight::ordered_float::OrderedFloat;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn same_seed_same_graph() {
+        let (m1, e1) = make_random_graph_matrix(30, &mut StdRng::seed_from_u64(7));
+        let (m2, e2) = make_random_graph_matrix(30, &mut StdRng::seed_from_u64(7));
+        assert_eq!(m1, m2);
+        let w1: Vec<(usize, usize, usize)> = e1.iter().map(|e| (e.start, e.end, e.weight)).collect();
+        let w2: Vec<(usize, usize, usize)> = e2.iter().map(|e| (e.start, e.end, e.weight)).collect();
+        assert_eq!(w1, w2);
+    }
+
+    #[test]
+    fn same_seed_same_clusters() {
+        let mut runs = Vec::new();
+        for _ in 0..3 {
+            let mut rng = StdRng::seed_from_u64(42);
+            let (_, edges) = make_random_graph_matrix(60, &mut rng);
+            let af = make_cluster(0.4, edges, 4, true, false, &mut rng).unwrap();
+            runs.push(af.linear_embed());
+        }
+        assert_eq!(runs[0], runs[1]);
+        assert_eq!(runs[1], runs[2]);
+    }
+
+    fn edge(start: usize, end: usize, weight: usize) -> Edge<usize> {
+        Edge { start, end, weight }
+    }
+
+    #[test]
+    fn empty_graph_is_an_error() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let err = make_cluster(0.4, Vec::<Edge<usize>>::new(), 1, false, false, &mut rng).err();
+        assert_eq!(err, Some(ClusteringError::EmptyGraph));
+    }
+
+    #[test]
+    fn k_larger_than_vertex_count_is_an_error() {
+        let edges = vec![edge(0, 1, 1), edge(1, 0, 1)];
+        let err = Affinity::new_and_init(&Graph::from_edges(&edges).unwrap(), 3).err();
+        assert_eq!(err, Some(ClusteringError::KExceedsVertexCount { k: 3, vertices: 2 }));
+    }
+
+    #[test]
+    fn disconnected_graph_does_not_converge() {
+        //两个互不相连的分量无法合并为一个cluster
+        let edges = vec![edge(0, 1, 1), edge(1, 0, 1), edge(2, 3, 1), edge(3, 2, 1)];
+        let mut rng = StdRng::seed_from_u64(0);
+        let err = make_cluster(0.4, edges, 1, false, false, &mut rng).err();
+        assert_eq!(err, Some(ClusteringError::NotConverged { rounds: 2, clusters: 2, k: 1 }));
+    }
+
+    fn complete_graph(n: usize, seed: u64) -> Vec<Edge<usize>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut edges = Vec::new();
+        for i in 0..n {
+            for j in i + 1..n {
+                let w = rng.gen_range(1, 1000);
+                edges.push(edge(i, j, w));
+                edges.push(edge(j, i, w));
+            }
+        }
+        edges
+    }
+
+    fn mst_weight(edges: &[Edge<usize>], n: usize) -> usize {
+        let mut sorted: Vec<&Edge<usize>> = edges.iter().collect();
+        sorted.sort_by_key(|e| e.weight);
+        let mut ds = DisjointSet::new(n);
+        sorted.iter().filter(|e| ds.union(e.start, e.end).is_some()).map(|e| e.weight).sum()
+    }
+
+    #[test]
+    fn sparsify_keeps_mst_of_dense_graph() {
+        let edges = complete_graph(60, 3);
+        let (sparse, rounds) = sparsify(edges.clone(), 0.4, &mut StdRng::seed_from_u64(5));
+        assert!(!rounds.is_empty());
+        assert!(rounds.last().unwrap().c <= 0.4);
+        assert!(rounds.windows(2).all(|r| r[1].edges < r[0].edges));
+        assert!(sparse.len() < edges.len() / 2);
+        assert_eq!(mst_weight(&sparse, 60), mst_weight(&edges, 60));
+        //保留的边两个方向都在
+        let pairs: std::collections::HashSet<(usize, usize)> = sparse.iter().map(|e| (e.start, e.end)).collect();
+        assert!(pairs.iter().all(|&(s, t)| pairs.contains(&(t, s))));
+    }
+
+    #[test]
+    fn sparse_graph_is_left_alone() {
+        let (_, edges) = make_random_graph_matrix(50, &mut StdRng::seed_from_u64(1));
+        let (sparse, rounds) = sparsify(edges.clone(), 1.0, &mut StdRng::seed_from_u64(1));
+        assert!(rounds.is_empty());
+        assert_eq!(sparse.len(), edges.len());
+    }
+
+    #[test]
+    fn make_cluster_reports_sparsify_rounds() {
+        let edges = complete_graph(60, 9);
+        let af = make_cluster(0.4, edges, 6, true, false, &mut StdRng::seed_from_u64(2)).unwrap();
+        assert!(!af.sparsify_rounds().is_empty());
+        assert_eq!(af.linear_embed().len(), 60);
+    }
+
+    fn sorted(mut clusters: Vec<Vec<usize>>) -> Vec<Vec<usize>> {
+        for c in clusters.iter_mut() {
+            c.sort_unstable();
+        }
+        clusters.sort();
+        clusters
+    }
+
+    #[test]
+    fn hierarchy_records_every_round() {
+        //两条路径0-1-2和3-4-5由一条权重为10的边相连
+        let mut edges = Vec::new();
+        for &(s, t, w) in &[(0, 1, 1), (1, 2, 2), (3, 4, 1), (4, 5, 2), (2, 3, 10)] {
+            edges.push(edge(s, t, w));
+            edges.push(edge(t, s, w));
+        }
+        let mut af = Affinity::new_and_init(&Graph::from_edges(&edges).unwrap(), 1).unwrap();
+        af.clustering(false, false).unwrap();
+        let h = af.hierarchy();
+        assert_eq!(h.depth(), 2);
+        assert_eq!(h.levels()[0].len(), 2);
+        assert!(h.levels()[0].iter().all(|m| m.children.len() == 3 && m.weight == 2));
+        assert_eq!(h.levels()[1].len(), 1);
+        assert_eq!(h.levels()[1][0].weight, 10);
+
+        assert_eq!(h.cut_at_level(0).len(), 6);
+        assert_eq!(sorted(h.cut_at_level(1)), vec![vec![0, 1, 2], vec![3, 4, 5]]);
+        assert_eq!(sorted(h.cut_at_level(2)), vec![vec![0, 1, 2, 3, 4, 5]]);
+        assert_eq!(sorted(h.cut_at_clusters(2)), sorted(h.cut_at_level(1)));
+        assert_eq!(h.cut_at_clusters(4).len(), 4);
+        assert_eq!(h.cut_at_clusters(1).len(), 1);
+    }
+
+    #[test]
+    fn float_and_signed_weights_cluster_like_integers() {
+        let edges = complete_graph(40, 4);
+        let floats: Vec<Edge<usize, OrderedFloat<f64>>> =
+            edges.iter().map(|e| Edge { start: e.start, end: e.end, weight: OrderedFloat(e.weight as f64 / 8.0) }).collect();
+        let signed: Vec<Edge<usize, i64>> = edges.iter().map(|e| Edge { start: e.start, end: e.end, weight: e.weight as i64 }).collect();
+        //负权重会破坏碎片阈值和DP的单调性，在建图时拒绝
+        let negative: Vec<Edge<usize, i64>> = signed.iter().map(|e| Edge { start: e.start, end: e.end, weight: e.weight - 500 }).collect();
+        assert!(matches!(make_cluster(0.4, negative, 4, true, false, &mut StdRng::seed_from_u64(8)).err(), Some(ClusteringError::NegativeWeight(_))));
+        let expected = make_cluster(0.4, edges, 4, true, false, &mut StdRng::seed_from_u64(8)).unwrap().linear_embed();
+        assert_eq!(make_cluster(0.4, floats, 4, true, false, &mut StdRng::seed_from_u64(8)).unwrap().linear_embed(), expected);
+        assert_eq!(make_cluster(0.4, signed, 4, true, false, &mut StdRng::seed_from_u64(8)).unwrap().linear_embed(), expected);
+    }
+
+    #[test]
+    fn vertex_weights_add_up_in_clusters() {
+        let mut edges = Vec::new();
+        for &(s, t, w) in &[(0, 1, 1), (1, 2, 2), (3, 4, 1), (4, 5, 2), (2, 3, 10)] {
+            edges.push(edge(s, t, w));
+            edges.push(edge(t, s, w));
+        }
+        let graph = Graph::from_edges(&edges).unwrap().with_vertex_weights(|&v| v + 1).unwrap();
+        let mut af = Affinity::new_and_init(&graph, 2).unwrap();
+        assert_eq!(af.cluster_weight(4), Ok(5));
+        af.clustering(false, false).unwrap();
+        assert_eq!(af.cluster_weight(0), Ok(6));
+        assert_eq!(af.cluster_weight(4), Ok(15));
+        assert_eq!(af.cluster_weight(9), Err(ClusteringError::UnknownVertex("9".to_string())));
+    }
+
+    #[test]
+    fn fragment_threshold_saturates_and_treats_zero_weights_as_one() {
+        use crate::affinity_clustering::fragment_threshold;
+        //u8的阈值在第8轮之后就会溢出
+        assert_eq!(fragment_threshold(Some(1u8), 7), 128);
+        assert_eq!(fragment_threshold(Some(1u8), 40), u8::MAX);
+        assert_eq!(fragment_threshold(Some(100i16), u32::MAX), i16::MAX);
+        assert_eq!(fragment_threshold(Some(OrderedFloat(1.0f64)), 2000), OrderedFloat(f64::MAX));
+        assert_eq!(fragment_threshold(Some(0u8), 3), 8);
+        assert_eq!(fragment_threshold::<u8>(None, 0), 1);
+
+        //点权都为0的u8图仍然处理碎片
+        let mut edges = Vec::new();
+        for v in 0..7usize {
+            edges.push(Edge { start: v, end: v + 1, weight: 1u8 });
+            edges.push(Edge { start: v + 1, end: v, weight: 1u8 });
+        }
+        let graph = Graph::from_edges(&edges).unwrap().with_vertex_weights(|_| 0u8).unwrap();
+        let mut af = Affinity::new_and_init(&graph, 1).unwrap();
+        let config = ClusteringConfig { fragment_process: true, max_rounds: 40, ..Default::default() };
+        let report = af.clustering_with_config(&config, &crate::linkage::Min, |_| {}).unwrap();
+        assert_eq!(report.stop, StopReason::ReachedK);
+        assert_eq!(af.clusters().len(), 1);
+    }
+
+    //两条路径0-1-2和3-4-5由一条权重为10的边相连，聚成两个cluster
+    fn two_paths() -> Affinity<usize> {
+        let mut edges = Vec::new();
+        for &(s, t, w) in &[(0, 1, 1), (1, 2, 2), (3, 4, 1), (4, 5, 2), (2, 3, 10)] {
+            edges.push(edge(s, t, w));
+            edges.push(edge(t, s, w));
+        }
+        let mut af = Affinity::new_and_init(&Graph::from_edges(&edges).unwrap(), 2).unwrap();
+        af.clustering(false, false).unwrap();
+        af
+    }
+
+    #[test]
+    fn progress_reports_every_round() {
+        let (_, edges) = make_random_graph_matrix(60, &mut StdRng::seed_from_u64(3));
+        let graph = Graph::from_edges(&edges).unwrap();
+        let mut af = Affinity::new_and_init(&graph, 4).unwrap();
+        let mut stats = Vec::new();
+        af.clustering_with_progress(true, &crate::linkage::Min, |s| stats.push(*s)).unwrap();
+        assert_eq!(stats.len(), af.hierarchy().depth());
+        assert!(stats.iter().enumerate().all(|(i, s)| s.round as usize == i + 1));
+        assert!(stats.windows(2).all(|w| w[1].clusters < w[0].clusters && w[1].elapsed >= w[0].elapsed));
+        let last = stats.last().unwrap();
+        assert_eq!(last.clusters, af.clusters().len());
+        assert_eq!(last.edges, af.edges().len());
+    }
+
+    #[test]
+    fn config_reports_the_stopping_criterion() {
+        //路径上偶数位置的边最轻，第一轮恰好两两合并，之后一轮合并为一个cluster
+        let mut edges = Vec::new();
+        for i in 0..199 {
+            let w = if i % 2 == 0 { 1 } else { 2 + i };
+            edges.push(edge(i, i + 1, w));
+            edges.push(edge(i + 1, i, w));
+        }
+        let graph = Graph::from_edges(&edges).unwrap();
+        let run = |config: ClusteringConfig, k: usize| {
+            let mut af = Affinity::new_and_init(&graph, k).unwrap();
+            let report = af.clustering_with_config(&config, &crate::linkage::Min, |_| {}).unwrap();
+            assert_eq!(report.clusters, af.clusters().len());
+            report
+        };
+        let all = run(ClusteringConfig::default(), 1);
+        assert_eq!((all.stop, all.rounds, all.clusters), (StopReason::ReachedK, 2, 1));
+        let one = run(ClusteringConfig { max_rounds: 1, ..Default::default() }, 1);
+        assert_eq!((one.stop, one.rounds, one.clusters), (StopReason::MaxRounds, 1, 100));
+        let slow = run(ClusteringConfig { min_progress: 0.6, ..Default::default() }, 1);
+        assert_eq!((slow.stop, slow.rounds, slow.clusters), (StopReason::SlowProgress, 1, 100));
+        let timed = run(ClusteringConfig { time_budget: Some(std::time::Duration::ZERO), ..Default::default() }, 1);
+        assert_eq!((timed.stop, timed.rounds, timed.clusters), (StopReason::TimeBudget, 0, 200));
+    }
+
+    #[test]
+    fn exact_k_hits_k_precisely() {
+        let (_, edges) = make_random_graph_matrix(200, &mut StdRng::seed_from_u64(4));
+        let graph = Graph::from_edges(&edges).unwrap();
+        for &(k, max_rounds) in &[(37, 5), (5, 1), (2, 2), (113, 5)] {
+            let mut af = Affinity::new_and_init(&graph, k).unwrap();
+            let config = ClusteringConfig { max_rounds, exact_k: true, fragment_process: true, ..Default::default() };
+            let report = af.clustering_with_config(&config, &crate::linkage::Min, |_| {}).unwrap();
+            assert_eq!(report.clusters, k, "k = {}", k);
+            assert_eq!(af.clusters().len(), k);
+            assert_eq!(af.hierarchy().cut_at_level(af.hierarchy().depth()).len(), k);
+        }
+
+        //四个互不相连的分量只能靠合并最轻的cluster达到k = 1
+        let edges: Vec<Edge<usize>> = (0..4).flat_map(|c| vec![edge(2 * c, 2 * c + 1, 1), edge(2 * c + 1, 2 * c, 1)]).collect();
+        let mut af = Affinity::new_and_init(&Graph::from_edges(&edges).unwrap(), 1).unwrap();
+        let report = af.clustering_with_config(&ClusteringConfig { exact_k: true, ..Default::default() }, &crate::linkage::Min, |_| {}).unwrap();
+        assert_eq!(report.stop, StopReason::NoMerges);
+        assert_eq!((report.clusters, report.final_merges), (1, 3));
+    }
+
+    #[test]
+    fn exact_k_prefers_linked_merges_and_marks_unlinked_ones() {
+        //0 -> 1只有单向边，1没有出边所以不会被选为最近邻；2、3、4是三角形，与0、1不相连
+        let mut edges = vec![edge(0, 1, 5)];
+        for &(s, t) in &[(2, 3), (3, 4), (2, 4)] {
+            edges.push(edge(s, t, 1));
+            edges.push(edge(t, s, 1));
+        }
+        let mut af = Affinity::new_and_init(&Graph::from_edges(&edges).unwrap(), 1).unwrap();
+        let report = af.clustering_with_config(&ClusteringConfig { exact_k: true, ..Default::default() }, &crate::linkage::Min, |_| {}).unwrap();
+        assert_eq!((report.stop, report.clusters, report.final_merges), (StopReason::NoMerges, 1, 2));
+        let levels = af.hierarchy().levels();
+        let last: Vec<(Vec<usize>, usize, bool)> = levels[levels.len() - 2..].iter().flatten().map(|m| (m.children.clone(), m.weight, m.linked)).collect();
+        assert_eq!(last, vec![(vec![0, 1], 5, true), (vec![0, 2], 0, false)]);
+        assert_eq!(af.hierarchy().cut_at_clusters(2).len(), 2);
+    }
+
+    #[test]
+    fn reweighted_edge_moves_only_its_endpoint() {
+        let mut af = two_paths();
+        let (old, stable) = (af.find(2).unwrap(), af.find(4).unwrap());
+        let changes = vec![EdgeChange::Reweight(edge(1, 2, 20)), EdgeChange::Reweight(edge(2, 1, 20))];
+        let report = af.apply_changes(&changes, false).unwrap();
+        assert_eq!(report.dissolved, vec![old]);
+        assert_eq!(report.moved, vec![VertexMove { vertex: 2, from: old, to: stable }]);
+        assert_eq!(report.clusters, 2);
+        assert_eq!(af.find(4), Ok(stable));
+        assert_eq!(af.find(2), Ok(stable));
+        assert_eq!(af.find(0), af.find(1));
+        assert!(af.edges().iter().all(|e| e.weight == 20));
+    }
+
+    #[test]
+    fn deleted_bridge_reclusters_both_sides_in_place() {
+        let mut af = two_paths();
+        let before = sorted(af.hierarchy().cut_at_level(1));
+        let changes = vec![EdgeChange::Delete { start: 2, end: 3 }, EdgeChange::Delete { start: 3, end: 2 }];
+        let report = af.apply_changes(&changes, false).unwrap();
+        assert_eq!(report.dissolved.len(), 2);
+        assert!(report.moved.is_empty());
+        assert!(af.edges().is_empty());
+        let mut clusters = Vec::new();
+        for v in 0..6 {
+            clusters.push(af.find(v).unwrap());
+        }
+        let mut after = vec![Vec::new(); 6];
+        for (v, c) in clusters.into_iter().enumerate() {
+            after[c].push(v);
+        }
+        assert_eq!(sorted(after.into_iter().filter(|c| !c.is_empty()).collect()), before);
+    }
+
+    #[test]
+    fn bad_change_leaves_the_clustering_alone() {
+        let mut af = two_paths();
+        let line = af.linear_embed();
+        let changes = vec![EdgeChange::Insert(edge(0, 5, 1)), EdgeChange::Delete { start: 0, end: 4 }];
+        let err = af.apply_changes(&changes, false).err();
+        assert_eq!(err, Some(ClusteringError::UnknownEdge { start: "0".to_string(), end: "4".to_string() }));
+        assert_eq!(af.linear_embed(), line);
+        assert_eq!(af.edges().len(), 2);
+        assert_eq!(af.apply_changes(&[EdgeChange::Insert(edge(0, 9, 1))], false).err(), Some(ClusteringError::UnknownVertex("9".to_string())));
+    }
+
+    #[test]
+    fn negative_changes_are_rejected() {
+        let mut edges = Vec::new();
+        for &(s, t, w) in &[(0, 1, 1i64), (1, 2, 2), (3, 4, 1), (4, 5, 2), (2, 3, 10)] {
+            edges.push(Edge { start: s, end: t, weight: w });
+            edges.push(Edge { start: t, end: s, weight: w });
+        }
+        let mut af = Affinity::new_and_init(&Graph::from_edges(&edges).unwrap(), 2).unwrap();
+        af.clustering(false, false).unwrap();
+        let (line, clusters, cluster_edges) = (af.linear_embed(), af.clusters(), af.edges().len());
+        let reweight = vec![EdgeChange::Reweight(Edge { start: 1, end: 2, weight: 5 }), EdgeChange::Reweight(Edge { start: 2, end: 1, weight: -5 })];
+        assert_eq!(af.apply_changes(&reweight, false).err(), Some(ClusteringError::NegativeWeight("-5".to_string())));
+        let insert = vec![EdgeChange::Insert(Edge { start: 0, end: 5, weight: -1 })];
+        assert_eq!(af.apply_changes(&insert, false).err(), Some(ClusteringError::NegativeWeight("-1".to_string())));
+        assert_eq!((af.linear_embed(), af.clusters(), af.edges().len()), (line, clusters, cluster_edges));
+        //之后合法的修改照常生效
+        let report = af.apply_changes(&[EdgeChange::Reweight(Edge { start: 1, end: 2, weight: 5 })], false).unwrap();
+        assert_eq!(report.clusters, 2);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn checkpoint_resumes_clustering() {
+        let (_, edges) = make_random_graph_matrix(60, &mut StdRng::seed_from_u64(6));
+        let graph = Graph::from_edges(&edges).unwrap();
+        let checkpoint = serde_json::to_string(&Affinity::new_and_init(&graph, 4).unwrap()).unwrap();
+        let mut resumed: Affinity<usize> = serde_json::from_str(&checkpoint).unwrap();
+        let mut af = Affinity::new_and_init(&graph, 4).unwrap();
+        assert_eq!(resumed.clustering(true, false), af.clustering(true, false));
+        assert_eq!(resumed.clusters(), af.clusters());
+        assert_eq!(resumed.hierarchy().levels(), af.hierarchy().levels());
+
+        //聚类后的状态用bincode保存，恢复后增量更新的结果与原对象一致
+        let mut restored: Affinity<usize> = bincode::deserialize(&bincode::serialize(&af).unwrap()).unwrap();
+        assert_eq!(restored.find(7), af.find(7));
+        let changes = [EdgeChange::Insert(edge(0, 59, 1)), EdgeChange::Insert(edge(59, 0, 1))];
+        assert_eq!(restored.apply_changes(&changes, false), af.apply_changes(&changes, false));
+        assert_eq!(restored.clusters(), af.clusters());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn results_round_trip_through_json() {
+        let mut af = two_paths();
+        let json = serde_json::to_string(af.hierarchy()).unwrap();
+        let hierarchy: crate::hierarchy::Hierarchy<usize> = serde_json::from_str(&json).unwrap();
+        assert_eq!(hierarchy.levels(), af.hierarchy().levels());
+        assert_eq!(sorted(hierarchy.cut_at_level(1)), sorted(af.clusters()));
+
+        let edges: Vec<Edge<usize>> = serde_json::from_str(&serde_json::to_string(&af.edges()).unwrap()).unwrap();
+        assert_eq!(edges.len(), 2);
+        let report = af.apply_changes(&[EdgeChange::Reweight(edge(1, 2, 20))], false).unwrap();
+        let json = serde_json::to_string(&report).unwrap();
+        assert_eq!(serde_json::from_str::<ChangeReport<usize>>(&json).unwrap(), report);
+    }
+
+    #[test]
+    fn nn_components_match_union_find() {
+        let mut rng = StdRng::seed_from_u64(11);
+        for n in [1usize, 2, 7, 64, 300].iter().copied() {
+            let next: Vec<usize> = (0..n).map(|_| rng.gen_range(0, n)).collect();
+            let labels = crate::affinity_clustering::nn_components(&next);
+            let mut ds = DisjointSet::new(n);
+            for (v, &c) in next.iter().enumerate() {
+                ds.union(v, c);
+            }
+            for v in 0..n {
+                assert!(ds.same_set(v, labels[v]));
+                assert_eq!(labels[v], labels[next[v]]);
+                assert_eq!(labels[labels[v]], labels[v]);
+            }
+        }
+    }
+
+    //在planted community上的聚类质量，分数低于现有水平时测试失败
+    fn agreement(topology: &crate::generators::Topology, edges: &[Edge<usize>], k: usize) -> crate::metrics::Agreement {
+        let graph = Graph::from_edges(edges).unwrap().with_vertex_weights(|&v| topology.vertex_weights[v]).unwrap();
+        let mut af = Affinity::new_and_init(&graph, k).unwrap();
+        af.clustering(false, true).unwrap();
+        crate::metrics::compare(&af.clusters(), &topology.planted().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn clustering_recovers_planted_communities() {
+        use crate::generators::{fat_tree, leaf_spine, stochastic_block_model, Weights};
+        let w = Weights::default();
+        let ft = fat_tree(8, &w, &mut StdRng::seed_from_u64(0));
+        let a = agreement(&ft, &ft.edges, 8);
+        assert!(a.nmi >= 0.8 && a.ari >= 0.65 && a.purity >= 0.8, "fat-tree pods: {:?}", a);
+
+        let ls = leaf_spine(4, 8, 6, &w, &mut StdRng::seed_from_u64(0));
+        let a = agreement(&ls, &ls.edges, 8);
+        assert!(a.nmi >= 0.85 && a.ari >= 0.8 && a.f1 >= 0.9, "leaf-spine racks: {:?}", a);
+
+        let sbm = stochastic_block_model(&[50, 50, 50, 50], 0.3, 0.01, &w, &mut StdRng::seed_from_u64(2));
+        let a = agreement(&sbm, &common_neighbor_edges(&sbm.edges), 4);
+        assert!(a.nmi >= 0.95 && a.ari >= 0.95, "sbm blocks: {:?}", a);
+    }
+}
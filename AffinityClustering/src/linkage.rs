@@ -0,0 +1,337 @@
+//! How `Affinity::clustering` picks the neighbour each cluster merges with.
+//!
+//! Every round the edges between clusters are summarised per neighbour into a
+//! `Link`, and each cluster merges with the neighbour its `Linkage` gives the
+//! lowest distance. Ties go to the neighbour whose first edge came first.
+//! `Min` is the default affinity and `Max` the common-neighbour one; both
+//! reproduce the original edge-by-edge choice exactly.
+
+use crate::weight::ordered_float::OrderedFloat;
+use crate::weight::Weight;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+/// Summary of the edges from one cluster to one neighbour.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link<W> {
+    pub cluster: usize,//邻居在LinkView中的编号
+    pub edges: usize,
+    pub total: W,
+    pub min: W,
+    pub max: W,
+    pub first_min: usize,//最轻的边中第一条在本cluster边表中的位置
+    pub last_max: usize,//最重的边中最后一条在本cluster边表中的位置
+}
+
+/// The cluster graph of one round, with clusters numbered `0..len()`.
+pub struct LinkView<'a, W> {
+    links: Vec<Vec<Link<W>>>,//按邻居首次出现的顺序
+    sorted: Vec<Vec<usize>>,//links[c]的下标，按邻居编号排序
+    cluster_weight: &'a [W],
+}
+
+impl<'a, W: Weight> LinkView<'a, W> {
+    /// Builds the view from the outgoing edges of every cluster, in edge order.
+    pub fn new<I, F>(cluster_weight: &'a [W], edges_of: F) -> Self
+    where
+        I: Iterator<Item = (usize, W)>,
+        F: Fn(usize) -> I + Sync + Send,
+    {
+        let links: Vec<Vec<Link<W>>> = maybe_par_iter!(0..cluster_weight.len())
+            .map(|c| summarize(edges_of(c)))
+            .collect();
+        let sorted = links
+            .iter()
+            .map(|l| {
+                let mut order: Vec<usize> = (0..l.len()).collect();
+                order.sort_unstable_by_key(|&i| l[i].cluster);
+                order
+            })
+            .collect();
+        LinkView { links, sorted, cluster_weight }
+    }
+
+    pub fn len(&self) -> usize {
+        self.links.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.links.is_empty()
+    }
+
+    /// Links of `c`, one per neighbour, in order of the neighbour's first edge.
+    pub fn links(&self, c: usize) -> &[Link<W>] {
+        &self.links[c]
+    }
+
+    pub fn link(&self, a: usize, b: usize) -> Option<&Link<W>> {
+        let links = &self.links[a];
+        self.sorted[a].binary_search_by_key(&b, |&i| links[i].cluster).ok().map(|i| &links[self.sorted[a][i]])
+    }
+
+    /// Pairs of links from `a` and from `b` to every neighbour they share.
+    pub fn shared(&self, a: usize, b: usize) -> Vec<(&Link<W>, &Link<W>)> {
+        let (la, lb) = (&self.links[a], &self.links[b]);
+        let (sa, sb) = (&self.sorted[a], &self.sorted[b]);
+        let (mut i, mut j) = (0, 0);
+        let mut out = Vec::new();
+        while i < sa.len() && j < sb.len() {
+            let (x, y) = (&la[sa[i]], &lb[sb[j]]);
+            match x.cluster.cmp(&y.cluster) {
+                std::cmp::Ordering::Less => i += 1,
+                std::cmp::Ordering::Greater => j += 1,
+                std::cmp::Ordering::Equal => {
+                    out.push((x, y));
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+        out
+    }
+
+    /// Sum of the vertex weights in `c`.
+    pub fn cluster_weight(&self, c: usize) -> W {
+        self.cluster_weight[c]
+    }
+
+    //c的最近邻、记录到hierarchy中的连接边权及距离；距离相同时取先出现的邻居
+    pub(crate) fn nearest<L: Linkage<W> + ?Sized>(&self, linkage: &L, c: usize) -> Option<(usize, W, L::Distance)> {
+        let mut best: Option<(&Link<W>, L::Distance)> = None;
+        for link in &self.links[c] {
+            let d = linkage.distance(self, c, link);
+            if best.as_ref().is_none_or(|(_, b)| d < *b) {
+                best = Some((link, d));
+            }
+        }
+        best.map(|(link, d)| (link.cluster, if linkage.heavier_is_closer() { link.max } else { link.min }, d))
+    }
+}
+
+fn summarize<W: Weight, I: Iterator<Item = (usize, W)>>(edges: I) -> Vec<Link<W>> {
+    let mut position = HashMap::<usize, usize>::new();
+    let mut links = Vec::<Link<W>>::new();
+    for (p, (end, weight)) in edges.enumerate() {
+        let i = *position.entry(end).or_insert_with(|| {
+            links.push(Link { cluster: end, edges: 0, total: W::zero(), min: weight, max: weight, first_min: p, last_max: p });
+            links.len() - 1
+        });
+        let link = &mut links[i];
+        link.edges += 1;
+        link.total = link.total + weight;
+        if weight < link.min {
+            link.min = weight;
+            link.first_min = p;
+        }
+        if weight >= link.max {
+            link.max = weight;
+            link.last_max = p;
+        }
+    }
+    links
+}
+
+/// Affinity between a cluster and one of its neighbours.
+pub trait Linkage<W: Weight>: Sync {
+    type Distance: Ord + Send;
+
+    /// Distance from cluster `from` to the neighbour `link.cluster`; each
+    /// cluster merges with the neighbour at the smallest distance.
+    fn distance(&self, view: &LinkView<'_, W>, from: usize, link: &Link<W>) -> Self::Distance;
+
+    /// Whether heavier edges mean closer clusters. The hierarchy records a
+    /// link by its strongest edge and a merge by its weakest link.
+    ///
+    /// True for linkages that read edges as similarities: `Max`, and the
+    /// similarity measures `Jaccard` and `Cosine`, whose distance is one
+    /// minus the similarity. False for `Min` and `Average`, which read edges
+    /// as distances.
+    fn heavier_is_closer(&self) -> bool {
+        false
+    }
+}
+
+/// Lightest edge wins; ties go to the edge listed first.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Min;
+
+/// Heaviest edge wins; ties go to the edge listed last.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Max;
+
+/// Lowest mean edge weight between the two clusters wins.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Average;
+
+/// Neighbourhood overlap `|N(a) ∩ N(b)| / |N(a) ∪ N(b)|`, ignoring weights;
+/// the most similar neighbour wins.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Jaccard;
+
+/// Cosine similarity of the weighted adjacency rows of the two clusters; the
+/// most similar neighbour wins.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Cosine;
+
+impl<W: Weight> Linkage<W> for Min {
+    type Distance = (W, usize);
+
+    fn distance(&self, _: &LinkView<'_, W>, _: usize, link: &Link<W>) -> Self::Distance {
+        (link.min, link.first_min)
+    }
+}
+
+impl<W: Weight> Linkage<W> for Max {
+    type Distance = Reverse<(W, usize)>;
+
+    fn distance(&self, _: &LinkView<'_, W>, _: usize, link: &Link<W>) -> Self::Distance {
+        Reverse((link.max, link.last_max))
+    }
+
+    fn heavier_is_closer(&self) -> bool {
+        true
+    }
+}
+
+impl<W: Weight> Linkage<W> for Average {
+    type Distance = OrderedFloat<f64>;
+
+    fn distance(&self, _: &LinkView<'_, W>, _: usize, link: &Link<W>) -> Self::Distance {
+        OrderedFloat(link.total.to_f64() / link.edges as f64)
+    }
+}
+
+impl<W: Weight> Linkage<W> for Jaccard {
+    type Distance = OrderedFloat<f64>;
+
+    fn distance(&self, view: &LinkView<'_, W>, from: usize, link: &Link<W>) -> Self::Distance {
+        let shared = view.shared(from, link.cluster).len() as f64;
+        let union = (view.links(from).len() + view.links(link.cluster).len()) as f64 - shared;
+        OrderedFloat(1.0 - shared / union)
+    }
+
+    fn heavier_is_closer(&self) -> bool {
+        true
+    }
+}
+
+impl<W: Weight> Linkage<W> for Cosine {
+    type Distance = OrderedFloat<f64>;
+
+    fn distance(&self, view: &LinkView<'_, W>, from: usize, link: &Link<W>) -> Self::Distance {
+        let norm = |c: usize| view.links(c).iter().map(|l| l.total.to_f64().powi(2)).sum::<f64>().sqrt();
+        let dot = view.shared(from, link.cluster).iter().map(|(a, b)| a.total.to_f64() * b.total.to_f64()).sum::<f64>();
+        let denominator = norm(from) * norm(link.cluster);
+        OrderedFloat(if denominator == 0.0 { 1.0 } else { 1.0 - dot / denominator })
+    }
+
+    fn heavier_is_closer(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::affinity_clustering::{Affinity, ClusteringConfig, Edge};
+    use crate::graph::{Csr, Graph};
+
+    //0到1有权重4和1的两条边，0到2有一条权重2的边，1与2之间有边
+    fn view_of(weights: &[usize]) -> LinkView<'_, usize> {
+        let triples = vec![(0, 1, 4), (0, 2, 2), (0, 1, 1), (1, 0, 4), (1, 0, 1), (1, 2, 3), (2, 0, 2), (2, 1, 3)];
+        let csr = Csr::from_triples(3, triples);
+        LinkView::new(weights, |c| csr.neighbors(c).collect::<Vec<_>>().into_iter())
+    }
+
+    #[test]
+    fn links_summarise_parallel_edges() {
+        let weights = [1, 1, 1];
+        let view = view_of(&weights);
+        assert_eq!(view.len(), 3);
+        assert_eq!(view.links(0).len(), 2);
+        assert_eq!(view.link(0, 1), Some(&Link { cluster: 1, edges: 2, total: 5, min: 1, max: 4, first_min: 2, last_max: 0 }));
+        assert_eq!(view.link(0, 0), None);
+        let shared: Vec<usize> = view.shared(0, 1).iter().map(|(a, _)| a.cluster).collect();
+        assert_eq!(shared, vec![2]);
+    }
+
+    #[test]
+    fn built_in_linkages_pick_their_neighbour() {
+        let weights = [1, 1, 1];
+        let view = view_of(&weights);
+        assert_eq!(view.nearest(&Min, 0).map(|(c, w, _)| (c, w)), Some((1, 1)));
+        assert_eq!(view.nearest(&Max, 0).map(|(c, w, _)| (c, w)), Some((1, 4)));
+        assert_eq!(view.nearest(&Average, 0).map(|(c, w, _)| (c, w)), Some((2, 2)));
+        //1与2的邻域{0, 2}、{0, 1}交集为{0}，Jaccard距离为2/3；0与2的邻域交集为{1}，同样是2/3，先出现的邻居优先
+        assert_eq!(view.nearest(&Jaccard, 2).map(|(c, w, _)| (c, w)), Some((0, 2)));
+    }
+
+    #[test]
+    fn similarity_linkages_agree_on_direction() {
+        assert!(!Linkage::<usize>::heavier_is_closer(&Min) && !Linkage::<usize>::heavier_is_closer(&Average));
+        assert!(Linkage::<usize>::heavier_is_closer(&Max));
+        assert!(Linkage::<usize>::heavier_is_closer(&Jaccard) && Linkage::<usize>::heavier_is_closer(&Cosine));
+    }
+
+    #[test]
+    fn cosine_matches_hand_computed_similarities() {
+        let weights = [1, 1, 1];
+        let view = view_of(&weights);
+        //按邻居求和后的邻接行：0 = (-, 5, 2)，1 = (5, -, 3)，2 = (2, 3, -)
+        let d01 = 1.0 - 2.0 * 3.0 / (29f64.sqrt() * 34f64.sqrt());
+        let d02 = 1.0 - 5.0 * 3.0 / (29f64.sqrt() * 13f64.sqrt());
+        let d12 = 1.0 - 5.0 * 2.0 / (34f64.sqrt() * 13f64.sqrt());
+        let distance = |a: usize, b: usize| Linkage::distance(&Cosine, &view, a, view.link(a, b).unwrap()).0;
+        for &(a, b, d) in &[(0, 1, d01), (0, 2, d02), (1, 2, d12)] {
+            assert!((distance(a, b) - d).abs() < 1e-12 && (distance(b, a) - d).abs() < 1e-12, "{} {}", a, b);
+        }
+        //0与2最相似，其次是1与2，0与1最不相似；记录的连接边取最重的一条
+        assert!(d02 < d12 && d12 < d01);
+        assert_eq!(view.nearest(&Cosine, 0).map(|(c, w, _)| (c, w)), Some((2, 2)));
+        assert_eq!(view.nearest(&Cosine, 1).map(|(c, w, _)| (c, w)), Some((2, 3)));
+        assert_eq!(view.nearest(&Cosine, 2).map(|(c, w, _)| (c, w)), Some((0, 2)));
+
+        //停在两个cluster时先合并的是最相似的0与2
+        let triples = [(0, 1, 4), (0, 2, 2), (0, 1, 1), (1, 0, 4), (1, 0, 1), (1, 2, 3), (2, 0, 2), (2, 1, 3)];
+        let edges: Vec<Edge<usize>> = triples.iter().map(|&(start, end, weight)| Edge { start, end, weight }).collect();
+        let mut af = Affinity::new_and_init(&Graph::from_edges(&edges).unwrap(), 2).unwrap();
+        let config = ClusteringConfig { exact_k: true, ..Default::default() };
+        af.clustering_with_config(&config, &Cosine, |_| {}).unwrap();
+        assert_eq!(af.find(0), af.find(2));
+        assert_ne!(af.find(0), af.find(1));
+    }
+
+    //用户自定义的linkage：与点权和最小的邻居合并
+    struct Lightest;
+
+    impl Linkage<usize> for Lightest {
+        type Distance = usize;
+
+        fn distance(&self, view: &LinkView<'_, usize>, _: usize, link: &Link<usize>) -> usize {
+            view.cluster_weight(link.cluster)
+        }
+    }
+
+    #[test]
+    fn user_linkage_drives_clustering() {
+        //路径0-1-2-3，边权都相同，点1最重。Min会让2跟随先列出的边并入1，所有点合成一个cluster
+        let mut edges = Vec::new();
+        for i in 0..3 {
+            edges.push(Edge { start: i, end: i + 1, weight: 1 });
+            edges.push(Edge { start: i + 1, end: i, weight: 1 });
+        }
+        let graph = Graph::from_edges(&edges).unwrap().with_vertex_weights(|&v| if v == 1 { 10 } else { 1 }).unwrap();
+        let mut af = Affinity::new_and_init(&graph, 2).unwrap();
+        af.clustering_with(false, &Lightest).unwrap();
+        assert_eq!(af.find(0), af.find(1));
+        assert_eq!(af.find(2), af.find(3));
+        assert_ne!(af.find(0), af.find(2));
+        assert_eq!(af.cluster_weight(0), Ok(11));
+
+        let mut min = Affinity::new_and_init(&graph, 2).unwrap();
+        min.clustering_with(false, &Min).unwrap();
+        assert_eq!(min.find(0), min.find(3));
+    }
+}
@@ -0,0 +1,313 @@
+//划分的质量指标，以及聚类与真实社区等基准的一致性。
+//边按给定的方式计算：双向列出每条无向边的图（如生成器的输出）割边权重翻倍，模块度、传导率和归一化割不受影响
+
+use crate::affinity_clustering::{ClusteringError, Edge};
+use crate::weight::Weight;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::hash::Hash;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PartMetrics<W = usize> {
+    pub vertices: usize,
+    pub node_weight: W,
+    pub internal_weight: W,//两端都在该part内的边权和
+    pub cut: W,//恰有一端在该part内的边权和，出边入边都算
+    pub volume: W,//part内所有点的加权度数和
+    pub conductance: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PartitionMetrics<W = usize> {
+    pub parts: Vec<PartMetrics<W>>,
+    pub edge_cut: W,//跨part的边权总和
+    pub max_part_cut: W,
+    pub modularity: f64,
+    pub max_conductance: f64,
+    pub normalized_cut: f64,
+    //最重部件的点权除以部件平均点权
+    pub imbalance: f64,
+    //部件点权的变异系数
+    pub node_weight_cv: f64,
+}
+
+//每个点权重为1时评估parts
+pub fn evaluate<T: Copy + Hash + Eq + Display, W: Weight>(edges: &[Edge<T, W>], parts: &[Vec<T>]) -> Result<PartitionMetrics<W>, ClusteringError> {
+    evaluate_weighted(edges, parts, |_| W::one())
+}
+
+//按node_weight给出的点权评估parts，每条边的端点都必须恰好属于一个部件
+pub fn evaluate_weighted<T, W, F>(edges: &[Edge<T, W>], parts: &[Vec<T>], node_weight: F) -> Result<PartitionMetrics<W>, ClusteringError>
+where
+    T: Copy + Hash + Eq + Display,
+    W: Weight,
+    F: Fn(&T) -> W,
+{
+    let mut part_of = HashMap::<T, usize>::new();
+    let mut stats: Vec<PartMetrics<W>> = Vec::with_capacity(parts.len());
+    for (p, part) in parts.iter().enumerate() {
+        let mut node_weight_sum = W::zero();
+        for v in part {
+            if part_of.insert(*v, p).is_some() {
+                return Err(ClusteringError::DuplicateVertex(v.to_string()));
+            }
+            node_weight_sum = node_weight_sum + node_weight(v);
+        }
+        stats.push(PartMetrics {
+            vertices: part.len(),
+            node_weight: node_weight_sum,
+            internal_weight: W::zero(),
+            cut: W::zero(),
+            volume: W::zero(),
+            conductance: 0.0,
+        });
+    }
+
+    let lookup = |v: &T| part_of.get(v).copied().ok_or_else(|| ClusteringError::UnknownVertex(v.to_string()));
+    let mut out_weight = vec![W::zero(); parts.len()];
+    let mut total = W::zero();
+    let mut edge_cut = W::zero();
+    for e in edges {
+        let (s, t) = (lookup(&e.start)?, lookup(&e.end)?);
+        total = total + e.weight;
+        out_weight[s] = out_weight[s] + e.weight;
+        stats[s].volume = stats[s].volume + e.weight;
+        stats[t].volume = stats[t].volume + e.weight;
+        if s == t {
+            stats[s].internal_weight = stats[s].internal_weight + e.weight;
+        } else {
+            stats[s].cut = stats[s].cut + e.weight;
+            stats[t].cut = stats[t].cut + e.weight;
+            edge_cut = edge_cut + e.weight;
+        }
+    }
+
+    //有向图的模块度：sum_c (L_c / m - out_c * in_c / m^2)，边成对出现时等于无向模块度
+    let m = total.to_f64();
+    let mut modularity = 0.0;
+    let mut normalized_cut = 0.0;
+    for (p, part) in stats.iter_mut().enumerate() {
+        let in_weight = part.volume - out_weight[p];
+        if total > W::zero() {
+            modularity += part.internal_weight.to_f64() / m - (out_weight[p].to_f64() * in_weight.to_f64()) / (m * m);
+        }
+        let denominator = part.volume.min(total + total - part.volume);
+        part.conductance = if denominator == W::zero() { 0.0 } else { part.cut.to_f64() / denominator.to_f64() };
+        if part.volume > W::zero() {
+            normalized_cut += part.cut.to_f64() / part.volume.to_f64();
+        }
+    }
+
+    let k = stats.len().max(1) as f64;
+    let mean = stats.iter().map(|p| p.node_weight.to_f64()).sum::<f64>() / k;
+    let variance = stats.iter().map(|p| (p.node_weight.to_f64() - mean).powi(2)).sum::<f64>() / k;
+    let heaviest = stats.iter().map(|p| p.node_weight).max().map_or(0.0, W::to_f64);
+    let (imbalance, node_weight_cv) = if mean > 0.0 { (heaviest / mean, variance.sqrt() / mean) } else { (0.0, 0.0) };
+
+    Ok(PartitionMetrics {
+        edge_cut,
+        max_part_cut: stats.iter().map(|p| p.cut).max().unwrap_or_else(W::zero),
+        modularity,
+        max_conductance: stats.iter().map(|p| p.conductance).fold(0.0, f64::max),
+        normalized_cut,
+        imbalance,
+        node_weight_cv,
+        parts: stats,
+    })
+}
+
+//聚类与基准的一致程度，完全一致时每项都为1
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Agreement {
+    pub vertices: usize,
+    //互信息除以两个熵的平均值
+    pub nmi: f64,
+    //调整兰德指数：随机聚类为0，比随机还差时为负
+    pub ari: f64,
+    //每个点所在簇中多数基准类所占的比例
+    pub purity: f64,
+    //每个基准类与任一簇的最佳F1，按类大小加权
+    pub f1: f64,
+}
+
+//比较聚类found与基准truth，found中每个点必须恰好属于一个类；
+//truth中不在found里的点（如从未进入聚类的孤立点）算作单独的簇
+pub fn compare<T: Copy + Hash + Eq + Display>(found: &[Vec<T>], truth: &[Vec<T>]) -> Result<Agreement, ClusteringError> {
+    let mut class_of = HashMap::<T, usize>::new();
+    for (j, class) in truth.iter().enumerate() {
+        for v in class {
+            if class_of.insert(*v, j).is_some() {
+                return Err(ClusteringError::DuplicateVertex(v.to_string()));
+            }
+        }
+    }
+    //列联表：(cluster, class)到共同点数，用BTreeMap保证求和顺序固定
+    let mut table = BTreeMap::<(usize, usize), usize>::new();
+    let mut seen = HashMap::<T, ()>::new();
+    let mut cluster_size = vec![0usize; found.len()];
+    let mut class_size = vec![0usize; truth.len()];
+    for (i, cluster) in found.iter().enumerate() {
+        for v in cluster {
+            if seen.insert(*v, ()).is_some() {
+                return Err(ClusteringError::DuplicateVertex(v.to_string()));
+            }
+            let j = *class_of.get(v).ok_or_else(|| ClusteringError::UnknownVertex(v.to_string()))?;
+            *table.entry((i, j)).or_insert(0) += 1;
+            cluster_size[i] += 1;
+            class_size[j] += 1;
+        }
+    }
+    //没有聚到的点各自成为单点cluster，按truth中的顺序编号
+    for (j, class) in truth.iter().enumerate() {
+        for v in class {
+            if seen.insert(*v, ()).is_none() {
+                table.insert((cluster_size.len(), j), 1);
+                cluster_size.push(1);
+                class_size[j] += 1;
+            }
+        }
+    }
+    let n = seen.len();
+    if n == 0 {
+        return Ok(Agreement { vertices: 0, nmi: 1.0, ari: 1.0, purity: 1.0, f1: 1.0 });
+    }
+
+    let total = n as f64;
+    let entropy = |sizes: &[usize]| -sizes.iter().filter(|&&s| s > 0).map(|&s| s as f64 / total * (s as f64 / total).ln()).sum::<f64>();
+    let (h_found, h_truth) = (entropy(&cluster_size), entropy(&class_size));
+    let mutual: f64 = table
+        .iter()
+        .map(|(&(i, j), &c)| c as f64 / total * (c as f64 * total / (cluster_size[i] as f64 * class_size[j] as f64)).ln())
+        .sum();
+    //两边都只有一类时互信息和熵都为0，视为完全一致
+    let nmi = if h_found + h_truth == 0.0 { 1.0 } else { 2.0 * mutual / (h_found + h_truth) };
+
+    let pairs = |s: usize| (s * s.saturating_sub(1) / 2) as f64;
+    let index: f64 = table.values().map(|&c| pairs(c)).sum();
+    let (sum_found, sum_truth) = (cluster_size.iter().map(|&s| pairs(s)).sum::<f64>(), class_size.iter().map(|&s| pairs(s)).sum::<f64>());
+    let expected = sum_found * sum_truth / pairs(n).max(1.0);
+    let max_index = (sum_found + sum_truth) / 2.0;
+    let ari = if max_index == expected { 1.0 } else { (index - expected) / (max_index - expected) };
+
+    let mut majority = vec![0usize; cluster_size.len()];
+    let mut best_f1 = vec![0f64; truth.len()];
+    for (&(i, j), &c) in &table {
+        majority[i] = majority[i].max(c);
+        best_f1[j] = best_f1[j].max(2.0 * c as f64 / (cluster_size[i] + class_size[j]) as f64);
+    }
+    let purity = majority.iter().sum::<usize>() as f64 / total;
+    let f1 = best_f1.iter().zip(&class_size).map(|(f, &s)| f * s as f64).sum::<f64>() / total;
+
+    Ok(Agreement { vertices: n, nmi, ari, purity, f1 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //两个三角形0-1-2和3-4-5，由权重为1的边2-3相连，每条边两个方向都列出
+    fn two_triangles() -> Vec<Edge<usize>> {
+        let undirected = [(0, 1, 2), (1, 2, 2), (0, 2, 2), (3, 4, 2), (4, 5, 2), (3, 5, 2), (2, 3, 1)];
+        undirected
+            .iter()
+            .flat_map(|&(a, b, w)| vec![Edge { start: a, end: b, weight: w }, Edge { start: b, end: a, weight: w }])
+            .collect()
+    }
+
+    #[test]
+    fn two_triangles_split_at_the_bridge() {
+        let m = evaluate(&two_triangles(), &[vec![0, 1, 2], vec![3, 4, 5]]).unwrap();
+        assert_eq!(m.edge_cut, 2);
+        assert_eq!(m.max_part_cut, 2);
+        assert_eq!(m.parts[0].internal_weight, 12);
+        assert_eq!(m.parts[0].volume, 26);
+        assert!((m.modularity - (12.0 / 26.0 - 0.25) * 2.0).abs() < 1e-12);
+        assert!((m.max_conductance - 1.0 / 13.0).abs() < 1e-12);
+        assert!((m.normalized_cut - 2.0 / 13.0).abs() < 1e-12);
+        assert_eq!(m.imbalance, 1.0);
+        assert_eq!(m.node_weight_cv, 0.0);
+
+        let single = evaluate(&two_triangles(), &[(0..6).collect()]).unwrap();
+        assert_eq!(single.edge_cut, 0);
+        assert_eq!(single.modularity, 0.0);
+        assert!(single.modularity < m.modularity);
+    }
+
+    #[test]
+    fn node_weights_drive_balance() {
+        let m = evaluate_weighted(&two_triangles(), &[vec![0, 1], vec![2, 3, 4, 5]], |&v| v).unwrap();
+        assert_eq!(m.parts[0].node_weight, 1);
+        assert_eq!(m.parts[1].node_weight, 14);
+        assert!((m.imbalance - 14.0 / 7.5).abs() < 1e-12);
+        assert!((m.node_weight_cv - 6.5 / 7.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn float_and_signed_weights_are_evaluated_in_their_own_type() {
+        use crate::weight::ordered_float::OrderedFloat;
+        let halves: Vec<Edge<usize, OrderedFloat<f64>>> =
+            two_triangles().iter().map(|e| Edge { start: e.start, end: e.end, weight: OrderedFloat(e.weight as f64 / 2.0) }).collect();
+        let parts = [vec![0, 1, 2], vec![3, 4, 5]];
+        let m = evaluate(&halves, &parts).unwrap();
+        assert_eq!(m.edge_cut, OrderedFloat(1.0));
+        assert_eq!((m.parts[0].internal_weight, m.parts[0].volume), (OrderedFloat(6.0), OrderedFloat(13.0)));
+        assert_eq!(m.parts[0].node_weight, OrderedFloat(3.0));
+        let integer = evaluate(&two_triangles(), &parts).unwrap();
+        assert_eq!((m.modularity, m.max_conductance, m.normalized_cut), (integer.modularity, integer.max_conductance, integer.normalized_cut));
+
+        let signed: Vec<Edge<usize, i64>> = two_triangles().iter().map(|e| Edge { start: e.start, end: e.end, weight: e.weight as i64 }).collect();
+        let s = evaluate_weighted(&signed, &parts, |&v| v as i64).unwrap();
+        assert_eq!((s.edge_cut, s.max_part_cut, s.parts[1].node_weight), (2i64, 2, 12));
+    }
+
+    #[test]
+    fn every_vertex_in_exactly_one_part() {
+        let edges = two_triangles();
+        assert_eq!(evaluate(&edges, &[vec![0, 1, 2], vec![3, 4]]), Err(ClusteringError::UnknownVertex("5".to_string())));
+        assert_eq!(
+            evaluate(&edges, &[vec![0, 1, 2, 3], vec![3, 4, 5]]),
+            Err(ClusteringError::DuplicateVertex("3".to_string()))
+        );
+    }
+
+    #[test]
+    fn agreement_with_the_truth() {
+        let truth = vec![vec![0, 1, 2], vec![3, 4, 5]];
+        let same = compare(&[vec![5, 4, 3], vec![2, 1, 0]], &truth).unwrap();
+        assert_eq!((same.vertices, same.nmi, same.ari, same.purity, same.f1), (6, 1.0, 1.0, 1.0, 1.0));
+
+        //把点2移到另一边：列联表为[[2, 0], [1, 3]]
+        let off = compare(&[vec![0, 1], vec![2, 3, 4, 5]], &truth).unwrap();
+        assert!((off.purity - 5.0 / 6.0).abs() < 1e-12);
+        assert!((off.f1 - (0.8 * 3.0 + 6.0 / 7.0 * 3.0) / 6.0).abs() < 1e-12);
+        //sum C(n_ij, 2) = 4，期望值为 7 * 6 / 15
+        let expected = 7.0 * 6.0 / 15.0;
+        assert!((off.ari - (4.0 - expected) / (6.5 - expected)).abs() < 1e-12);
+        assert!(off.nmi > 0.0 && off.nmi < 1.0);
+
+        let single = compare(&[(0..6).collect()], &truth).unwrap();
+        assert_eq!((single.nmi, single.ari, single.purity), (0.0, 0.0, 0.5));
+    }
+
+    #[test]
+    fn vertices_missing_from_the_clustering_are_singletons() {
+        let truth = vec![vec![0, 1, 2], vec![3, 4, 5, 6]];
+        //等同于[[0, 1], [3, 4, 5], [2], [6]]：sum C(n_ij, 2) = 4，期望值为 4 * 9 / 21
+        let a = compare(&[vec![0, 1], vec![3, 4, 5]], &truth).unwrap();
+        assert_eq!(a, compare(&[vec![0, 1], vec![3, 4, 5], vec![2], vec![6]], &truth).unwrap());
+        assert_eq!((a.vertices, a.purity), (7, 1.0));
+        assert!((a.ari - 32.0 / 67.0).abs() < 1e-12);
+        assert!(a.nmi < 1.0 && a.f1 < 1.0);
+        //完全没有聚到时每个点都是单点cluster
+        let none = compare(&[], &truth).unwrap();
+        assert_eq!((none.vertices, none.purity), (7, 1.0));
+        assert!(none.ari.abs() < 1e-12);
+        assert_eq!(compare(&[vec![0, 7]], &truth), Err(ClusteringError::UnknownVertex("7".to_string())));
+        assert_eq!(compare(&[vec![0], vec![0]], &truth), Err(ClusteringError::DuplicateVertex("0".to_string())));
+    }
+}
@@ -0,0 +1,298 @@
+//不建点对表的邻域相似度图：两个点共享出邻居时相连，权重为公共邻居数、Jaccard系数或Adamic–Adar分数。
+//依次通过每个点的反向邻接找到点对，用排序归并求两个邻居表的交集，除输出外只占用O(n + m)。
+//被超过max_shared个点共享的邻居为hub，hub要么跳过，要么只由按种子抽样的max_shared个点共享；重复边在共享计数中只算一次
+
+use crate::affinity_clustering::Edge;
+use crate::graph::Csr;
+use crate::weight::ordered_float::OrderedFloat;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum HubPolicy {
+    //hub不连接任何点
+    Skip,
+    //按options的种子抽取max_shared个点共享hub
+    Sample,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NeighborhoodOptions {
+    pub max_shared: Option<usize>,//被超过该数量的点共享的邻居视为hub，None表示不限制
+    pub hubs: HubPolicy,
+    pub seed: u64,//HubPolicy::Sample抽样使用的随机种子
+}
+
+impl Default for NeighborhoodOptions {
+    fn default() -> Self {
+        NeighborhoodOptions { max_shared: None, hubs: HubPolicy::Sample, seed: 0 }
+    }
+}
+
+//计算相似度所需的每对点的统计量
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Overlap {
+    pub common: usize,//共同邻居数，平行边按重数相乘计入
+    pub shared: usize,//不同的共同邻居数，不计重数
+    pub adamic_adar: f64,//共同邻居x的贡献为1/ln(共享x的点数)
+    pub degree: (usize, usize),//两个点（去掉hub之后）的出度，平行边按重数计入
+    pub distinct_degree: (usize, usize),//两个点（去掉hub之后）不同邻居的个数
+}
+
+//去掉hub之后的邻接表：forward按邻居编号排序，reverse[x]为共享x的点
+struct Neighborhoods {
+    forward: Csr<()>,
+    reverse: Csr<()>,
+    shared: Vec<usize>,//共享x的不同点数，按抽样之前的原图计算
+    distinct: Vec<usize>,//每个点不同邻居的个数
+}
+
+impl Neighborhoods {
+    fn new(n: usize, pairs: &[(usize, usize)], options: &NeighborhoodOptions) -> Self {
+        let full = Csr::from_triples(n, pairs.iter().map(|&(s, t)| (t, s, ())));
+        let mut shared = Vec::with_capacity(n);
+        let mut rng = StdRng::seed_from_u64(options.seed);
+        let mut kept = Vec::with_capacity(pairs.len());
+        for x in 0..n {
+            let sharing = full.targets(x);
+            //平行边不重复计数，权重和hub上限都按不同的点数计算
+            let mut distinct = sharing.to_vec();
+            distinct.sort_unstable();
+            distinct.dedup();
+            shared.push(distinct.len());
+            match options.max_shared {
+                Some(cap) if distinct.len() > cap => {
+                    if options.hubs == HubPolicy::Sample {
+                        //按点抽样，同一个点的平行边一起保留
+                        let (sample, _) = distinct.partial_shuffle(&mut rng, cap);
+                        sample.sort_unstable();
+                        kept.extend(sharing.iter().filter(|v| sample.binary_search(v).is_ok()).map(|&v| (v, x)));
+                    }
+                }
+                _ => kept.extend(sharing.iter().map(|&v| (v, x))),
+            }
+        }
+        //按终点逐个加入，所以每个点的邻居按编号排好序
+        let forward = Csr::from_triples(n, kept.iter().map(|&(v, x)| (v, x, ())));
+        let reverse = Csr::from_triples(n, kept.iter().map(|&(v, x)| (x, v, ())));
+        let distinct = (0..n).map(|v| forward.targets(v).windows(2).filter(|w| w[0] != w[1]).count() + usize::from(forward.degree(v) > 0)).collect();
+        Neighborhoods { forward, reverse, shared, distinct }
+    }
+
+    //两个有序邻居表的归并求交，重复的邻居按重数相乘
+    fn overlap(&self, a: usize, b: usize) -> Overlap {
+        let (na, nb) = (self.forward.targets(a), self.forward.targets(b));
+        let (mut i, mut j) = (0, 0);
+        let (mut common, mut shared) = (0, 0);
+        let mut adamic_adar = 0.0;
+        while i < na.len() && j < nb.len() {
+            match na[i].cmp(&nb[j]) {
+                std::cmp::Ordering::Less => i += 1,
+                std::cmp::Ordering::Greater => j += 1,
+                std::cmp::Ordering::Equal => {
+                    let x = na[i];
+                    let (ri, rj) = (run(na, i), run(nb, j));
+                    common += ri * rj;
+                    shared += 1;
+                    adamic_adar += (ri * rj) as f64 / (self.shared[x] as f64).ln();
+                    i += ri;
+                    j += rj;
+                }
+            }
+        }
+        Overlap { common, shared, adamic_adar, degree: (na.len(), nb.len()), distinct_degree: (self.distinct[a], self.distinct[b]) }
+    }
+}
+
+//从下标i开始与list[i]相等的元素个数
+fn run(list: &[usize], i: usize) -> usize {
+    list[i..].iter().take_while(|&&x| x == list[i]).count()
+}
+
+//对每对共享出邻居的点按score输出双向各一条边，按较小点的顺序输出，点按在edges中首次出现编号；score返回None时丢弃该对
+pub fn for_each_similar<T, W, S, F, G>(edges: &[Edge<T, W>], options: &NeighborhoodOptions, score: S, mut emit: G)
+where
+    T: Copy + Hash + Eq,
+    S: Fn(&Overlap) -> Option<F>,
+    F: Copy,
+    G: FnMut(Edge<T, F>),
+{
+    let mut index = HashMap::<T, usize>::new();
+    let mut vertices = Vec::<T>::new();
+    let mut id = |v: T| {
+        *index.entry(v).or_insert_with(|| {
+            vertices.push(v);
+            vertices.len() - 1
+        })
+    };
+    let pairs: Vec<(usize, usize)> = edges.iter().map(|e| (id(e.start), id(e.end))).collect();
+    let n = vertices.len();
+    let hoods = Neighborhoods::new(n, &pairs, options);
+
+    //每次只保留一个点的候选，seen按点重置，不需要点对表
+    let mut seen = vec![usize::MAX; n];
+    let mut candidates = Vec::<usize>::new();
+    for v in 0..n {
+        candidates.clear();
+        for &x in hoods.forward.targets(v) {
+            for &u in hoods.reverse.targets(x) {
+                if u > v && seen[u] != v {
+                    seen[u] = v;
+                    candidates.push(u);
+                }
+            }
+        }
+        candidates.sort_unstable();
+        for &u in &candidates {
+            if let Some(weight) = score(&hoods.overlap(v, u)) {
+                emit(Edge { start: vertices[v], end: vertices[u], weight });
+                emit(Edge { start: vertices[u], end: vertices[v], weight });
+            }
+        }
+    }
+}
+
+//边权为公共出邻居数
+pub fn common_neighbors<T: Copy + Hash + Eq, W>(edges: &[Edge<T, W>], options: &NeighborhoodOptions) -> Vec<Edge<T>> {
+    let mut out = Vec::new();
+    for_each_similar(edges, options, |o| Some(o.common), |e| out.push(e));
+    out
+}
+
+//边权为两个出邻居集合的Jaccard系数，重复边只算一次
+pub fn jaccard<T: Copy + Hash + Eq, W>(edges: &[Edge<T, W>], options: &NeighborhoodOptions) -> Vec<Edge<T, OrderedFloat<f64>>> {
+    let mut out = Vec::new();
+    let score = |o: &Overlap| Some(OrderedFloat(o.shared as f64 / (o.distinct_degree.0 + o.distinct_degree.1 - o.shared) as f64));
+    for_each_similar(edges, options, score, |e| out.push(e));
+    out
+}
+
+//边权为Adamic–Adar分数：每个公共邻居x贡献1 / ln(shared(x))，shared(x)为抽样前指向x的点数
+pub fn adamic_adar<T: Copy + Hash + Eq, W>(edges: &[Edge<T, W>], options: &NeighborhoodOptions) -> Vec<Edge<T, OrderedFloat<f64>>> {
+    let mut out = Vec::new();
+    for_each_similar(edges, options, |o| Some(OrderedFloat(o.adamic_adar)), |e| out.push(e));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::affinity_clustering::make_random_graph_matrix;
+    use rand::Rng;
+
+    //原来基于点对表的实现，作为流式计算的参照
+    fn pair_map_common_neighbors(edges: &[Edge<usize>]) -> Vec<Edge<usize>> {
+        let mut reverse = HashMap::<usize, Vec<usize>>::new();//reverse[x]为以x为邻居的点
+        for e in edges {
+            reverse.entry(e.end).or_default().push(e.start);
+        }
+        let mut weight = HashMap::<(usize, usize), usize>::new();
+        for sharing in reverse.values() {
+            for &v1 in sharing {
+                for &v2 in sharing {
+                    if v1 != v2 {
+                        *weight.entry((v1, v2)).or_insert(0) += 1;
+                    }
+                }
+            }
+        }
+        weight.into_iter().map(|((start, end), weight)| Edge { start, end, weight }).collect()
+    }
+
+    fn sorted<W: Ord + Copy>(edges: &[Edge<usize, W>]) -> Vec<(usize, usize, W)> {
+        let mut out: Vec<(usize, usize, W)> = edges.iter().map(|e| (e.start, e.end, e.weight)).collect();
+        out.sort_unstable();
+        out
+    }
+
+    fn edge(start: usize, end: usize) -> Edge<usize> {
+        Edge { start, end, weight: 1 }
+    }
+
+    #[test]
+    fn uncapped_counts_match_the_pair_map() {
+        for seed in 0..5 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let (_, mut edges) = make_random_graph_matrix(80, &mut rng);
+            //平行边和自环按原实现的重数计入
+            for _ in 0..20 {
+                let (s, t) = (rng.gen_range(0, 80), rng.gen_range(0, 80));
+                edges.push(edge(s, t));
+            }
+            let streamed = common_neighbors(&edges, &NeighborhoodOptions::default());
+            assert_eq!(sorted(&streamed), sorted(&pair_map_common_neighbors(&edges)), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn jaccard_and_adamic_adar_scores() {
+        //0和1共享2、3，1还连向4；2只被0、1共享，3还被5共享
+        let edges = vec![edge(0, 2), edge(0, 3), edge(1, 2), edge(1, 3), edge(1, 4), edge(5, 3)];
+        let options = NeighborhoodOptions::default();
+        let j = jaccard(&edges, &options);
+        let pair = |e: &[Edge<usize, OrderedFloat<f64>>], a: usize, b: usize| e.iter().find(|e| e.start == a && e.end == b).unwrap().weight.0;
+        assert!((pair(&j, 0, 1) - 2.0 / 3.0).abs() < 1e-12);
+        assert!((pair(&j, 5, 1) - 1.0 / 3.0).abs() < 1e-12);
+        let aa = adamic_adar(&edges, &options);
+        assert!((pair(&aa, 1, 0) - (1.0 / 2f64.ln() + 1.0 / 3f64.ln())).abs() < 1e-12);
+        assert!((pair(&aa, 0, 5) - 1.0 / 3f64.ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn jaccard_ignores_parallel_edges() {
+        //0连向2三次、3一次，1连向2三次：作为集合{2, 3}与{2}，Jaccard为1/2
+        let edges = vec![edge(0, 2), edge(0, 2), edge(0, 2), edge(0, 3), edge(1, 2), edge(1, 2), edge(1, 2)];
+        let j = jaccard(&edges, &NeighborhoodOptions::default());
+        assert_eq!(j.len(), 2);
+        assert!(j.iter().all(|e| (e.weight.0 - 0.5).abs() < 1e-12), "{:?}", j.iter().map(|e| e.weight.0).collect::<Vec<_>>());
+        //两个点都只有重复的同一个邻居时为1
+        let twice = vec![edge(0, 2), edge(0, 2), edge(1, 2), edge(1, 2)];
+        assert!(jaccard(&twice, &NeighborhoodOptions::default()).iter().all(|e| e.weight.0 == 1.0));
+        //common neighbor仍按重数相乘
+        assert!(common_neighbors(&edges, &NeighborhoodOptions::default()).iter().all(|e| e.weight == 9));
+    }
+
+    #[test]
+    fn duplicate_pairs_count_once_towards_sharing_and_hubs() {
+        //9被0连了三次，被1、2各连一次：共享9的点数是3而不是5
+        let edges = vec![edge(0, 9), edge(0, 9), edge(0, 9), edge(1, 9), edge(2, 9)];
+        let pair = |e: &[Edge<usize, OrderedFloat<f64>>], a: usize, b: usize| e.iter().find(|e| e.start == a && e.end == b).unwrap().weight.0;
+        let aa = adamic_adar(&edges, &NeighborhoodOptions::default());
+        assert!((pair(&aa, 1, 2) - 1.0 / 3f64.ln()).abs() < 1e-12);
+        assert!((pair(&aa, 0, 1) - 3.0 / 3f64.ln()).abs() < 1e-12);
+        //上限为3时9不是hub
+        let capped = NeighborhoodOptions { max_shared: Some(3), hubs: HubPolicy::Skip, seed: 0 };
+        assert_eq!(sorted(&common_neighbors(&edges, &capped)), sorted(&common_neighbors(&edges, &NeighborhoodOptions::default())));
+        let tighter = NeighborhoodOptions { max_shared: Some(2), ..capped };
+        assert!(common_neighbors(&edges, &tighter).is_empty());
+    }
+
+    #[test]
+    fn hub_caps_bound_the_output() {
+        //星形：0..200都连向hub 1000，另外i与i+1共享一个私有邻居
+        let mut edges: Vec<Edge<usize>> = (0..200).map(|i| edge(i, 1000)).collect();
+        for i in 0..199 {
+            edges.push(edge(i, 2000 + i));
+            edges.push(edge(i + 1, 2000 + i));
+        }
+        assert_eq!(common_neighbors(&edges, &NeighborhoodOptions::default()).len(), 200 * 199);
+
+        let skip = NeighborhoodOptions { max_shared: Some(10), hubs: HubPolicy::Skip, seed: 0 };
+        let skipped = common_neighbors(&edges, &skip);
+        assert_eq!(skipped.len(), 2 * 199);
+        assert!(skipped.iter().all(|e| e.weight == 1 && e.start.max(e.end) - e.start.min(e.end) == 1));
+
+        let sample = NeighborhoodOptions { hubs: HubPolicy::Sample, ..skip };
+        let sampled = common_neighbors(&edges, &sample);
+        assert!(sampled.len() <= 2 * 199 + 10 * 9);
+        assert!(sampled.len() > 2 * 199);
+        assert_eq!(sorted(&sampled), sorted(&common_neighbors(&edges, &sample)));
+    }
+}
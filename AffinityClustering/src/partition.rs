@@ -0,0 +1,1005 @@
+//平衡的k路划分：先做affinity聚类（可选在公共邻居图上），用linear_embed排成线性序列，
+//可选用RankSwap平衡点权，再用动态规划切成k段使最大段代价最小，最后可选FM细化降低割边
+
+use crate::affinity_clustering::{common_neighbor_edges, sparsify, Affinity, ClusteringError, RoundStats};
+use crate::affinity_clustering::Edge;
+use crate::graph::{Csr, Graph};
+use crate::linkage::{Max, Min};
+use crate::refine::{fm_refine, RefineOptions, RefinePass};
+use crate::weight::Weight;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use tracing::{debug_span, info, info_span};
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PartitionOptions {
+    pub epsilon: f32,//边稀疏化参数，见sparsify
+    pub cluster_threshold: usize,//cluster数不超过该值时停止affinity clustering
+    pub fragment_process: bool,
+    pub common_neighbors: bool,//在common neighbor图上按最大边聚类
+    pub rank_swap: Option<RankSwapOptions>,//None表示不做RankSwap
+    pub refine: Option<RefineOptions>,//DP切割之后的FM refinement，None表示不做
+    pub seed: u64,//边稀疏化和interval配对使用的随机种子
+}
+
+impl Default for PartitionOptions {
+    fn default() -> Self {
+        PartitionOptions {
+            epsilon: 0.4,
+            cluster_threshold: 10,
+            fragment_process: true,
+            common_neighbors: true,
+            rank_swap: None,
+            refine: None,
+            seed: 0,
+        }
+    }
+}
+
+//RankSwap中部件和区间的配对方式，每个部件切成intervals个区间，只在配对的区间之间交换点
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RankSwapOptions {
+    //相邻部件配对，离公共边界距离相同的区间配对，点只沿序列移动一小段，保留嵌入得到的割；部件数为奇数时最后一个与左邻配对
+    Near { intervals: usize },
+    //最重的部件与最轻的配对，区间随机配对，平衡效果更强但割更大
+    Rank { intervals: usize },
+    //每一轮按点权从大到小，每个部件与至多partners个更轻的部件交换（从最轻的开始），区间j对区间j；
+    //每轮之后重新排序，所以一个重部件的点权可以分给多个轻部件
+    Greedy { intervals: usize, partners: usize },
+}
+
+impl RankSwapOptions {
+    pub fn intervals(&self) -> usize {
+        match *self {
+            RankSwapOptions::Near { intervals } | RankSwapOptions::Rank { intervals } | RankSwapOptions::Greedy { intervals, .. } => intervals,
+        }
+    }
+}
+
+//RankSwap的运行统计
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RankSwapStats<W = usize> {
+    pub sweeps: usize,//遍历所有配对的次数，包括最后一次没有交换的
+    pub swaps: usize,
+    pub max_before: W,//交换前最重的part的点权和
+    pub max_after: W,
+}
+
+//线性序列的连续切分：第p段为line[bounds[p]..bounds[p + 1]]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LineCut<W = usize> {
+    pub bounds: Vec<usize>,
+    pub costs: Vec<W>,//每个part的点权、内部边权与割边权之和
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(from = "SavedPartitioning<T, W>", bound(deserialize = "T: Deserialize<'de> + Copy + Hash + Eq, W: Deserialize<'de> + Weight"))
+)]
+pub struct Partitioning<T, W = usize> {
+    order: Vec<T>,
+    cut: LineCut<W>,
+    rank_swap: Option<RankSwapStats<W>>,
+    refinement: Vec<RefinePass<W>>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing))]
+    part: HashMap<T, usize>,//由order和cut重建，不写入序列化结果
+}
+
+//Partitioning序列化的内容，反序列化后重建part
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct SavedPartitioning<T, W> {
+    order: Vec<T>,
+    cut: LineCut<W>,
+    #[serde(default = "Option::default")]
+    rank_swap: Option<RankSwapStats<W>>,
+    #[serde(default = "Vec::new")]
+    refinement: Vec<RefinePass<W>>,
+}
+
+#[cfg(feature = "serde")]
+impl<T: Copy + Hash + Eq, W: Weight> From<SavedPartitioning<T, W>> for Partitioning<T, W> {
+    fn from(saved: SavedPartitioning<T, W>) -> Self {
+        let mut p = Partitioning::new(saved.order, saved.cut);
+        p.rank_swap = saved.rank_swap;
+        p.refinement = saved.refinement;
+        p
+    }
+}
+
+impl<T: Copy + Hash + Eq, W: Weight> Partitioning<T, W> {
+    fn new(order: Vec<T>, cut: LineCut<W>) -> Self {
+        let mut part = HashMap::with_capacity(order.len());
+        for (p, w) in cut.bounds.windows(2).enumerate() {
+            for v in &order[w[0]..w[1]] {
+                part.insert(*v, p);
+            }
+        }
+        Partitioning { order, cut, rank_swap: None, refinement: Vec::new(), part }
+    }
+
+    pub fn k(&self) -> usize {
+        self.cut.costs.len()
+    }
+
+    //切分所用的线性序列（RankSwap之后），细化后按部件重新排列，使每个部件连续
+    pub fn order(&self) -> &[T] {
+        &self.order
+    }
+
+    pub fn bounds(&self) -> &[usize] {
+        &self.cut.bounds
+    }
+
+    pub fn part(&self, p: usize) -> &[T] {
+        &self.order[self.cut.bounds[p]..self.cut.bounds[p + 1]]
+    }
+
+    pub fn parts(&self) -> Vec<Vec<T>> {
+        (0..self.k()).map(|p| self.part(p).to_vec()).collect()
+    }
+
+    pub fn part_of(&self, v: &T) -> Option<usize> {
+        self.part.get(v).copied()
+    }
+
+    pub fn costs(&self) -> &[W] {
+        &self.cut.costs
+    }
+
+    pub fn max_cost(&self) -> W {
+        self.cut.costs.iter().copied().max().unwrap_or_else(W::zero)
+    }
+
+    //RankSwap的统计，未运行时为None
+    pub fn rank_swap(&self) -> Option<&RankSwapStats<W>> {
+        self.rank_swap.as_ref()
+    }
+
+    //每一轮细化的统计，未细化时为空
+    pub fn refinement(&self) -> &[RefinePass<W>] {
+        &self.refinement
+    }
+}
+
+//把graph划分成k个部件，平衡点权以及部件内部和周围的边权
+pub fn partition<T, W>(graph: &Graph<T, W>, k: usize, options: &PartitionOptions) -> Result<Partitioning<T, W>, ClusteringError>
+where
+    T: Debug + Display + Copy + Hash + Eq + Send + Sync,
+    W: Weight,
+{
+    partition_with_progress(graph, k, options, |_| {})
+}
+
+//同partition，每轮聚类结束时把统计交给progress
+pub fn partition_with_progress<T, W, F>(graph: &Graph<T, W>, k: usize, options: &PartitionOptions, mut progress: F) -> Result<Partitioning<T, W>, ClusteringError>
+where
+    T: Debug + Display + Copy + Hash + Eq + Send + Sync,
+    W: Weight,
+    F: FnMut(&RoundStats),
+{
+    let _span = info_span!("partition", vertices = graph.vertex_count(), edges = graph.edge_count(), k).entered();
+    if graph.edge_count() == 0 {
+        return Err(ClusteringError::EmptyGraph);
+    }
+    if k == 0 || k > graph.vertex_count() {
+        return Err(ClusteringError::KExceedsVertexCount { k, vertices: graph.vertex_count() });
+    }
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let mut line = embed(graph, options, &mut rng, &mut progress)?;
+    let mut stats = None;
+    if let Some(rank_swap_options) = &options.rank_swap {
+        let node_weight = |v: &T| graph.id(v).map_or_else(W::zero, |id| graph.vertex_weight(id));
+        let (swapped, swap_stats) = rank_swap(&line, &even_bounds(line.len(), k), rank_swap_options, node_weight, &mut rng);
+        line = swapped;
+        stats = Some(swap_stats);
+    }
+    let cut = dynamic_program(graph, &line, k)?;
+    let mut partitioning = match &options.refine {
+        Some(refine) => refined(graph, line, cut, refine)?,
+        None => Partitioning::new(line, cut),
+    };
+    partitioning.rank_swap = stats;
+    Ok(partitioning)
+}
+
+//对DP的结果做FM refinement，再按part把点重新排成连续的段
+fn refined<T, W>(graph: &Graph<T, W>, line: Vec<T>, cut: LineCut<W>, options: &RefineOptions) -> Result<Partitioning<T, W>, ClusteringError>
+where
+    T: Copy + Hash + Eq + Display,
+    W: Weight,
+{
+    let k = cut.costs.len();
+    let mut part = vec![0; graph.vertex_count()];
+    for (p, w) in cut.bounds.windows(2).enumerate() {
+        for v in &line[w[0]..w[1]] {
+            part[graph.id(v).ok_or_else(|| ClusteringError::UnknownVertex(v.to_string()))?] = p;
+        }
+    }
+    let refinement = fm_refine(graph, &mut part, k, options)?;
+    let mut order = Vec::with_capacity(line.len());
+    let mut bounds = vec![0];
+    for p in 0..k {
+        order.extend(line.iter().copied().filter(|v| graph.id(v).is_some_and(|id| part[id] == p)));
+        bounds.push(order.len());
+    }
+    let costs = part_costs(&LineCosts::new(graph, &order)?, &bounds);
+    let mut partitioning = Partitioning::new(order, LineCut { bounds, costs });
+    partitioning.refinement = refinement;
+    Ok(partitioning)
+}
+
+//聚类后的线性序列；不在聚类图中的点（例如common neighbor图中的孤立点）按原图顺序接在最后
+fn embed<T, W, R, F>(graph: &Graph<T, W>, options: &PartitionOptions, rng: &mut R, progress: &mut F) -> Result<Vec<T>, ClusteringError>
+where
+    T: Debug + Display + Copy + Hash + Eq + Send + Sync,
+    W: Weight,
+    R: Rng + ?Sized,
+    F: FnMut(&RoundStats),
+{
+    let _span = debug_span!("embed", common_neighbors = options.common_neighbors).entered();
+    let edges = graph.to_edges();
+    let mut line = if options.common_neighbors {
+        cluster_line(common_neighbor_edges(&edges), options, rng, progress)?
+    } else {
+        cluster_line(edges, options, rng, progress)?
+    };
+    let placed: HashSet<T> = line.iter().copied().collect();
+    line.extend(graph.vertices().iter().copied().filter(|v| !placed.contains(v)));
+    Ok(line)
+}
+
+//common neighbor图的边权是公共邻居数，与原图的边权类型不同，所以聚类部分单独按边权类型泛化
+fn cluster_line<T, W, R, F>(cluster_edges: Vec<Edge<T, W>>, options: &PartitionOptions, rng: &mut R, progress: &mut F) -> Result<Vec<T>, ClusteringError>
+where
+    T: Debug + Display + Copy + Hash + Eq + Send + Sync,
+    W: Weight,
+    R: Rng + ?Sized,
+    F: FnMut(&RoundStats),
+{
+    let mut line = Vec::new();
+    if !cluster_edges.is_empty() {
+        let (cluster_edges, _) = sparsify(cluster_edges, options.epsilon, rng);
+        let cluster_graph = Graph::from_edges(&cluster_edges)?;
+        let threshold = options.cluster_threshold.clamp(1, cluster_graph.vertex_count());
+        let mut af = Affinity::new_and_init(&cluster_graph, threshold)?;
+        let clustered = if options.common_neighbors {
+            af.clustering_with_progress(options.fragment_process, &Max, &mut *progress)
+        } else {
+            af.clustering_with_progress(options.fragment_process, &Min, &mut *progress)
+        };
+        match clustered {
+            //没有收敛到threshold时沿用已经形成的cluster
+            Ok(()) | Err(ClusteringError::NotConverged { .. }) => {}
+            Err(e) => return Err(e),
+        }
+        line = af.linear_embed();
+    }
+    Ok(line)
+}
+
+//把长度为n的序列均分为k段，第p段为[bounds[p], bounds[p+1])
+pub fn even_bounds(n: usize, k: usize) -> Vec<usize> {
+    (0..=k).map(|i| i * n / k).collect()
+}
+
+//在bounds给出的部件之间按options配对区间交换点，直到不能再降低一对中较重的部件；
+//只有RankSwapOptions::Rank会用到rng，所以固定种子时每种方式都是确定的
+pub fn rank_swap<T, W, F, R>(line: &[T], bounds: &[usize], options: &RankSwapOptions, node_weight: F, rng: &mut R) -> (Vec<T>, RankSwapStats<W>)
+where
+    T: Copy,
+    W: Weight,
+    F: Fn(&T) -> W,
+    R: Rng + ?Sized,
+{
+    let r = options.intervals().max(1);
+    let k = bounds.len().saturating_sub(1);
+    let _span = debug_span!("rank_swap", vertices = line.len(), parts = k, intervals = r, ?options).entered();
+    //line划分为k个partition，partition划分为r个interval，interval内按点权从大到小排列
+    let mut divided = Vec::<Vec<Vec<(T, W)>>>::with_capacity(k);
+    let mut size = vec![W::zero(); k];
+    for p in 0..k {
+        let (lo, hi) = (bounds[p], bounds[p + 1]);
+        let mut partition = Vec::with_capacity(r);
+        for j in 0..r {
+            let mut interval: Vec<(T, W)> = line[lo + j * (hi - lo) / r..lo + (j + 1) * (hi - lo) / r]
+                .iter()
+                .map(|v| (*v, node_weight(v)))
+                .collect();
+            size[p] = interval.iter().fold(size[p], |sum, x| sum + x.1);
+            interval.sort_by_key(|x| Reverse(x.1));
+            partition.push(interval);
+        }
+        divided.push(partition);
+    }
+    let heaviest = |size: &[W]| size.iter().copied().max().unwrap_or_else(W::zero);
+    let mut stats = RankSwapStats { sweeps: 0, swaps: 0, max_before: heaviest(&size), max_after: W::zero() };
+
+    //第j个interval与配对partition的第pairing[j]个interval配对
+    let (pairing, fixed_pairs): (Vec<usize>, Vec<(usize, usize)>) = match *options {
+        //相邻partition配对，右侧partition的第j个interval与左侧partition倒数第j个配对；k为奇数时最后一个与左边的配对
+        RankSwapOptions::Near { .. } => {
+            let last = (k % 2 == 1 && k > 1).then(|| (k - 2, k - 1));
+            ((0..r).rev().collect(), (0..k / 2).map(|i| (2 * i, 2 * i + 1)).chain(last).collect())
+        }
+        //partition按大小排序，最大与最小配对
+        RankSwapOptions::Rank { .. } => {
+            let mut pairing: Vec<usize> = (0..r).collect();
+            pairing.shuffle(rng);
+            let mut rank: Vec<usize> = (0..k).collect();
+            rank.sort_by(|&a, &b| size[b].cmp(&size[a]));
+            (pairing, (0..k / 2).map(|i| (rank[i], rank[k - i - 1])).collect())
+        }
+        RankSwapOptions::Greedy { .. } => ((0..r).collect(), Vec::new()),
+    };
+
+    //每次交换都严格减小一对partition中较大的那个而不改变两者之和，所有partition大小的平方和严格下降，所以循环一定结束
+    loop {
+        stats.sweeps += 1;
+        let pairs = match *options {
+            RankSwapOptions::Greedy { partners, .. } => greedy_pairs(&size, partners),
+            _ => fixed_pairs.clone(),
+        };
+        let mut swapped = false;
+        for (p1, p2) in pairs {
+            for (i2, &i1) in pairing.iter().enumerate() {
+                for a in 0..divided[p1][i1].len() {
+                    let wa = divided[p1][i1][a].1;
+                    let mut best = None;
+                    let mut best_max = size[p1].max(size[p2]);
+                    for (b, &(_, wb)) in divided[p2][i2].iter().enumerate() {
+                        let swapped_max = (size[p1] - wa + wb).max(size[p2] - wb + wa);
+                        if swapped_max < best_max {
+                            best = Some(b);
+                            best_max = swapped_max;
+                        }
+                    }
+                    if let Some(b) = best {
+                        let moved = divided[p2][i2][b];
+                        size[p1] = size[p1] - wa + moved.1;
+                        size[p2] = size[p2] - moved.1 + wa;
+                        divided[p2][i2][b] = divided[p1][i1][a];
+                        divided[p1][i1][a] = moved;
+                        swapped = true;
+                        stats.swaps += 1;
+                    }
+                }
+            }
+        }
+        if !swapped {
+            break;
+        }
+    }
+    stats.max_after = heaviest(&size);
+    info!(sweeps = stats.sweeps, swaps = stats.swaps, max_before = %stats.max_before, max_after = %stats.max_after, "rank swap finished");
+    (divided.into_iter().flatten().flatten().map(|(v, _)| v).collect(), stats)
+}
+
+//partition按大小从大到小，每个与至多partners个比它轻的partition配对，最轻的优先
+fn greedy_pairs<W: Weight>(size: &[W], partners: usize) -> Vec<(usize, usize)> {
+    let mut rank: Vec<usize> = (0..size.len()).collect();
+    rank.sort_by(|&a, &b| size[b].cmp(&size[a]).then(a.cmp(&b)));
+    let mut pairs = Vec::new();
+    for (i, &heavy) in rank.iter().enumerate() {
+        pairs.extend(rank[i + 1..].iter().rev().filter(|&&light| size[light] < size[heavy]).take(partners.max(1)).map(|&light| (heavy, light)));
+    }
+    pairs
+}
+
+//动态规划需要的段数：列表中每个q都切成q/2和q-q/2，两者也在列表中
+pub fn q_list(k: usize) -> Vec<usize> {
+    let mut qs = BTreeSet::new();
+    qs.insert(k);
+    let (mut k1, mut k2) = (k, k);
+    //相邻的两个数对半分后至多得到两个不同的数
+    while k1 > 1 && k2 > 1 {
+        let halves: BTreeSet<usize> = [k1 / 2, k1 - k1 / 2, k2 / 2, k2 - k2 / 2].iter().copied().collect();
+        let mut it = halves.iter().copied();
+        k1 = it.next().unwrap();
+        k2 = it.next().unwrap_or(k1);
+        qs.extend(halves);
+    }
+    qs.into_iter().collect()
+}
+
+//把line切成k个连续段，使最大段代价最小；段代价为点权加上至少一端在段内的边权，双向边只算一次。
+//递归地在使较大一半最小的最左切点处切成q/2和q-q/2段，每次切分从两端做前缀DP，代价由前缀和与滑动窗口得到，
+//内存为O(n + m)，把L个点切成q段耗时O(q·(L + m))
+pub fn dynamic_program<T, W>(graph: &Graph<T, W>, line: &[T], k: usize) -> Result<LineCut<W>, ClusteringError>
+where
+    T: Copy + Hash + Eq + Display,
+    W: Weight,
+{
+    let n = line.len();
+    let _span = debug_span!("dynamic_program", vertices = n, k).entered();
+    if k == 0 || k > n {
+        return Err(ClusteringError::KExceedsVertexCount { k, vertices: n });
+    }
+    let costs = LineCosts::new(graph, line)?;
+    let mut cuts = Vec::with_capacity(k - 1);
+    split(&costs, 0, n - 1, k, &mut cuts);
+
+    let mut bounds = vec![0];
+    bounds.extend(cuts.iter().map(|c| c + 1));
+    bounds.push(n);
+    let costs = part_costs(&costs, &bounds);
+    info!(max_cost = %costs.iter().copied().max().unwrap_or_else(W::zero), "line cut");
+    Ok(LineCut { bounds, costs })
+}
+
+//按bounds切成的每一段的代价
+fn part_costs<W: Weight>(costs: &LineCosts<W>, bounds: &[usize]) -> Vec<W> {
+    let whole = Segment { start: 0, end: costs.node_prefix.len() - 2, forward: true };
+    bounds
+        .windows(2)
+        .map(|w| {
+            let mut part = Window::new(costs, whole, w[0]);
+            part.advance(w[0], w[1]);
+            part.cost()
+        })
+        .collect()
+}
+
+//把区间[start, end]切成q段，切割位置（左段的最后一个点）按从左到右的顺序写入cuts
+fn split<W: Weight>(costs: &LineCosts<W>, start: usize, end: usize, q: usize, cuts: &mut Vec<usize>) {
+    if q == 1 {
+        return;
+    }
+    let (left, right) = (q / 2, q - q / 2);
+    //prefix[x]为[start, start+x]切成left段的最优值，suffix[y]为[end-y, end]切成right段的最优值
+    let prefix = prefix_optimum(costs, Segment { start, end, forward: true }, left);
+    let suffix = prefix_optimum(costs, Segment { start, end, forward: false }, right);
+    let mut cut = start + left - 1;
+    let mut best = None;
+    for c in start + left - 1..=end - right {
+        let size = prefix[c - start].max(suffix[end - c - 1]);
+        if best.is_none_or(|b| size < b) {
+            best = Some(size);
+            cut = c;
+        }
+    }
+    split(costs, start, cut, left, cuts);
+    cuts.push(cut);
+    split(costs, cut + 1, end, right, cuts);
+}
+
+//返回seg的每个前缀[0, x]切成q段时最大段代价的最小值，前缀短于q时无意义，调用方不会读取。
+//F_t(x) = min_b max(F_{t-1}(b), cost(b+1, x))，F_{t-1}随b不减而cost随b不增，
+//所以最优的b是两者交叉处，且随x单调右移，每一行只需一趟双指针
+fn prefix_optimum<W: Weight>(costs: &LineCosts<W>, seg: Segment, q: usize) -> Vec<W> {
+    let len = seg.len();
+    let mut row = vec![W::zero(); len];
+    let mut whole = Window::new(costs, seg, 0);
+    for (x, r) in row.iter_mut().enumerate() {
+        whole.advance(0, x + 1);
+        *r = whole.cost();
+    }
+    for t in 2..=q {
+        let mut next = vec![W::zero(); len];
+        let mut b = t - 2;
+        let mut main = Window::new(costs, seg, b + 1);//[b+1, x]
+        let mut aux = Window::new(costs, seg, b);//[b, x]，对应候选b-1
+        for (x, slot) in next.iter_mut().enumerate().skip(t - 1) {
+            main.advance(b + 1, x + 1);
+            while b < x && row[b] < main.cost() {
+                b += 1;
+                main.advance(b + 1, x + 1);
+            }
+            let mut best = if b < x { Some(row[b]) } else { None };
+            if b > t - 2 {
+                aux.advance(b, x + 1);
+                let candidate = row[b - 1].max(aux.cost());
+                best = Some(best.map_or(candidate, |w| w.min(candidate)));
+            }
+            *slot = best.unwrap_or_else(W::zero);
+        }
+        row = next;
+    }
+    row
+}
+
+//线性序列上点权和加权度数的前缀和，以及按位置编号、去重后双向存储的邻接表
+struct LineCosts<W> {
+    node_prefix: Vec<W>,
+    degree_prefix: Vec<W>,
+    adjacency: Csr<W>,
+}
+
+impl<W: Weight> LineCosts<W> {
+    fn new<T>(graph: &Graph<T, W>, line: &[T]) -> Result<Self, ClusteringError>
+    where
+        T: Copy + Hash + Eq + Display,
+    {
+        let n = line.len();
+        let mut position = vec![usize::MAX; graph.vertex_count()];
+        let mut node_weight = Vec::with_capacity(n);
+        for (i, v) in line.iter().enumerate() {
+            let id = graph.id(v).ok_or_else(|| ClusteringError::UnknownVertex(v.to_string()))?;
+            position[id] = i;
+            node_weight.push(graph.vertex_weight(id));
+        }
+        let mut seen = HashSet::<(usize, usize)>::new();
+        let mut degree = vec![W::zero(); n];
+        let mut triples = Vec::new();
+        for (s, t, weight) in graph.csr().triples() {
+            let (a, b) = (position[s], position[t]);
+            if a == usize::MAX || b == usize::MAX || a == b || !seen.insert((a.min(b), a.max(b))) {
+                continue;
+            }
+            degree[a] = degree[a] + weight;
+            degree[b] = degree[b] + weight;
+            triples.push((a, b, weight));
+            triples.push((b, a, weight));
+        }
+        let prefix = |values: Vec<W>| {
+            let mut sums = vec![W::zero()];
+            for v in values {
+                sums.push(*sums.last().unwrap() + v);
+            }
+            sums
+        };
+        Ok(LineCosts {
+            node_prefix: prefix(node_weight),
+            degree_prefix: prefix(degree),
+            adjacency: Csr::from_triples(n, triples),
+        })
+    }
+}
+
+//位置区间[start, end]及读取方向：forward时偏移x对应位置start+x，否则对应end-x
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    start: usize,
+    end: usize,
+    forward: bool,
+}
+
+impl Segment {
+    fn len(&self) -> usize {
+        self.end - self.start + 1
+    }
+
+    fn position(&self, x: usize) -> usize {
+        if self.forward { self.start + x } else { self.end - x }
+    }
+
+    fn offset(&self, p: usize) -> Option<usize> {
+        if p < self.start || p > self.end {
+            None
+        } else if self.forward {
+            Some(p - self.start)
+        } else {
+            Some(self.end - p)
+        }
+    }
+}
+
+//Segment内偏移[lo, end)组成的区间及其内部边权，两端都只向右移动，总更新代价为O(L + m)
+struct Window<'a, W> {
+    costs: &'a LineCosts<W>,
+    seg: Segment,
+    lo: usize,
+    end: usize,
+    internal: W,
+}
+
+impl<'a, W: Weight> Window<'a, W> {
+    fn new(costs: &'a LineCosts<W>, seg: Segment, lo: usize) -> Self {
+        Window { costs, seg, lo, end: lo, internal: W::zero() }
+    }
+
+    //偏移x与区间内其他点之间的边权和
+    fn links(&self, x: usize) -> W {
+        self.costs
+            .adjacency
+            .neighbors(self.seg.position(x))
+            .filter(|&(u, _)| matches!(self.seg.offset(u), Some(y) if self.lo <= y && y < self.end && y != x))
+            .fold(W::zero(), |sum, (_, w)| sum + w)
+    }
+
+    fn advance(&mut self, lo: usize, end: usize) {
+        while self.end < end {
+            self.internal = self.internal + self.links(self.end);
+            self.end += 1;
+        }
+        while self.lo < lo {
+            self.internal = self.internal - self.links(self.lo);
+            self.lo += 1;
+        }
+    }
+
+    fn cost(&self) -> W {
+        if self.lo == self.end {
+            return W::zero();
+        }
+        let (a, b) = if self.seg.forward {
+            (self.seg.start + self.lo, self.seg.start + self.end)
+        } else {
+            (self.seg.end + 1 - self.end, self.seg.end + 1 - self.lo)
+        };
+        let c = self.costs;
+        c.node_prefix[b] - c.node_prefix[a] + c.degree_prefix[b] - c.degree_prefix[a] - self.internal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::affinity_clustering::{make_random_graph_matrix, Edge};
+    use crate::weight::ordered_float::OrderedFloat;
+    use ndarray::{Array2, Array3};
+
+    //基线affinity_test中的q_list和DynamicProgram，作为新实现在小规模输入上的对照。
+    //输入改为点序列、点权和边表，打印改为返回切割位置（左段的最后一个点）；
+    //另外只改了三处标注“修正”的地方：J取边权而非1，左侧边取C[0, j, i-1]，右段至少right个点
+    fn baseline_q_list(k: usize) -> Vec::<usize>{
+        let mut hash_qs = HashSet::<usize>::new();
+        let mut present_k1 = k;
+        let mut present_k2 = k;
+        hash_qs.insert(k);
+        while present_k1 != 1 && present_k2 != 1 {
+            let mut hash_number = HashSet::<usize>::new();
+            hash_number.insert(present_k1/2);
+            hash_number.insert(present_k1 - present_k1/2);
+            hash_number.insert(present_k2/2);
+            hash_number.insert(present_k2 - present_k2/2);
+            if hash_number.len() == 1 {
+                present_k1 = *hash_number.iter().next().unwrap();
+                present_k2 = present_k1;
+                hash_qs.insert(present_k1);
+            } else {
+                let mut iters = hash_number.iter();
+                present_k1 = *iters.next().unwrap();
+                present_k2 = *iters.next().unwrap();
+                hash_qs.insert(present_k1);
+                hash_qs.insert(present_k2);
+            }
+        }
+        let mut qs:Vec::<usize> = hash_qs.into_iter().collect();
+        qs.sort();
+        qs
+    }
+
+    #[allow(clippy::needless_range_loop)]
+    fn baseline_dynamic_program(line: &[usize], node_weight: &[usize], k: usize, edges: &HashMap::<(usize, usize), usize>) -> Vec::<usize> {
+        let mut node_position = HashMap::<usize, usize>::new();
+        for i in 0..line.len() {
+            node_position.insert(line[i], i);
+        }
+        let vertex_num = line.len();
+        let mut J = Array3::<usize>::zeros((vertex_num, vertex_num, vertex_num));
+        //J存储从区间[i, j]到点k的边长度总和,以便后续计算使用
+        //初始化J
+        for ((start, end), edge_weight) in edges {
+            J[[node_position[start], node_position[start], node_position[end]]] = *edge_weight;//修正
+        }
+        //J计算公式为J[i, j, k] = J[i, j-1, k] + J[j, j, k]
+        for length in 2..vertex_num {//length为该区间包括的点个数
+            for i in 0..vertex_num-length {
+                let j = i + length - 1;
+                for k in i+length..vertex_num {
+                    J[[i, j, k]] = J[[i, j-1, k]] + J[[j, j, k]];
+                }
+            }
+        }
+
+        let mut D = Array2::<usize>::zeros((vertex_num, vertex_num));
+        //D存储在区间[i, j]内的边权总和，计算公式为D(i, j+1) = D(i, j) + J(i, j, j+1)
+        for i in 0..vertex_num-1 {
+            for j in i+1..vertex_num {
+                D[[i, j]] = D[[i, j-1]] + J[[i, j-1, j]];
+            }
+        }
+        let mut B = Array2::<usize>::zeros((vertex_num, vertex_num));
+        //B存储在区间[i, j]内的点权总和，计算公式为B(i, j+1) = B(i, j) + w[j+1]
+        for i in 0..vertex_num {
+            B[[i, i]] = node_weight[line[i]];
+        }//初始化B
+        for i in 0..vertex_num-1 {
+            for j in i+1..vertex_num {
+                B[[i, j]] = B[[i, j-1]] + node_weight[line[j]];
+            }
+        }
+
+        let mut C = Array3::<usize>::zeros((vertex_num, vertex_num, vertex_num));
+        //C[i, j, k]存储从区间[i, k]到区间[k, j]的边长度总和
+        //初始化C
+        for i in 0..vertex_num-1 {
+            C[[i, i+1, i]] = J[[i, i, i+1]];
+        }
+        for i in 0..vertex_num-2 {
+            for j in i+2..vertex_num {
+                for cut_point in i..j {
+                    C[[i, j, cut_point]] = C[[i, j-1, cut_point]] + J[[i, cut_point, j]];
+                }
+            }
+        }
+        let mut A = Array3::<usize>::zeros((vertex_num, vertex_num, k+1));
+        let mut Ap = Array3::<Vec::<usize>>::default((vertex_num, vertex_num, k+1));
+        //存储A(i, j, q)，即中间解, Ap存储切割位置
+        //初始化A
+        for i in 0..vertex_num {
+            for j in i.. vertex_num {
+                let from_left = if i > 0 { C[[0, j, i-1]] } else { 0 };//修正
+                A[[i, j, 1]] = B[[i, j]] + D[[i, j]] + from_left + C[[i, vertex_num-1, j]];
+            }
+        }
+        //动态规划序列准备
+        let mut qs = baseline_q_list(k);
+        qs.remove(0);
+        //开始动态规划
+        for q in qs {
+            let left = q/2;
+            let right = q-q/2;
+            for i in 0..vertex_num-1 {
+                for j in i+1..vertex_num {
+                    if q <= j-i+1 {
+                        let mut min_cut_point = i;
+                        let mut min_cut_size = 10000000;
+                        for cut_point in i..j {
+                            if cut_point-i+1 >= left && j-cut_point >= right {//修正
+                                let cut_size_temp = A[[i, cut_point, left]].max(A[[cut_point+1, j, right]]);
+                                if cut_size_temp < min_cut_size {
+                                    min_cut_size = cut_size_temp;
+                                    min_cut_point = cut_point;
+                                }
+                            }
+                        }
+                        A[[i, j, q]] = min_cut_size;
+                        Ap[[i, j, q]] = Ap[[i, min_cut_point, left]].clone();
+                        Ap[[i, j, q]].push(min_cut_point);
+                        let mut right_part = Ap[[min_cut_point+1, j, right]].clone();
+                        Ap[[i, j, q]].append(&mut right_part);
+                    }
+                }
+            }
+        }
+        Ap[[0, vertex_num-1, k]].clone()
+    }
+
+    //把line切成k段的所有方式中最小的最大段代价
+    fn brute_force(graph: &Graph<usize>, line: &[usize], k: usize) -> usize {
+        fn go(graph: &Graph<usize>, line: &[usize], bounds: &mut Vec<usize>, k: usize) -> usize {
+            let n = line.len();
+            let last = *bounds.last().unwrap();
+            if bounds.len() == k {
+                bounds.push(n);
+                let cost = part_costs(graph, line, bounds).into_iter().max().unwrap();
+                bounds.pop();
+                return cost;
+            }
+            let mut best = usize::MAX;
+            for b in last + 1..n {
+                bounds.push(b);
+                best = best.min(go(graph, line, bounds, k));
+                bounds.pop();
+            }
+            best
+        }
+        go(graph, line, &mut vec![0], k)
+    }
+
+    fn part_costs(graph: &Graph<usize>, line: &[usize], bounds: &[usize]) -> Vec<usize> {
+        let mut part = HashMap::new();
+        for (p, w) in bounds.windows(2).enumerate() {
+            for v in &line[w[0]..w[1]] {
+                part.insert(*v, p);
+            }
+        }
+        let mut costs: Vec<usize> = bounds.windows(2).map(|w| w[1] - w[0]).collect();
+        let mut seen = HashSet::new();
+        for e in graph.edges() {
+            if e.start != e.end && seen.insert((e.start.min(e.end), e.start.max(e.end))) {
+                costs[part[&e.start]] += e.weight;
+                if part[&e.start] != part[&e.end] {
+                    costs[part[&e.end]] += e.weight;
+                }
+            }
+        }
+        costs
+    }
+
+    #[test]
+    fn q_list_is_closed_under_halving() {
+        assert_eq!(q_list(1), vec![1]);
+        assert_eq!(q_list(16), vec![1, 2, 4, 8, 16]);
+        assert_eq!(q_list(5), vec![1, 2, 3, 5]);
+        for k in 1..100 {
+            let qs = q_list(k);
+            for &q in qs.iter().filter(|&&q| q > 1) {
+                assert!(qs.contains(&(q / 2)) && qs.contains(&(q - q / 2)), "k = {}", k);
+            }
+        }
+    }
+
+    #[test]
+    fn dynamic_program_costs_match_brute_force() {
+        for seed in 0..4 {
+            let (_, edges) = make_random_graph_matrix(9, &mut StdRng::seed_from_u64(seed));
+            let graph = Graph::from_edges(&edges).unwrap();
+            let line: Vec<usize> = graph.vertices().to_vec();
+            for k in [1, 2, 3, 4].iter().copied().filter(|&k| k <= line.len()) {
+                let cut = dynamic_program(&graph, &line, k).unwrap();
+                assert_eq!(cut.bounds.len(), k + 1);
+                assert_eq!(cut.costs, part_costs(&graph, &line, &cut.bounds));
+                assert_eq!(*cut.costs.iter().max().unwrap(), brute_force(&graph, &line, k), "seed {} k {}", seed, k);
+            }
+        }
+    }
+
+    #[test]
+    fn dynamic_program_matches_the_table_version() {
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let (_, edges) = make_random_graph_matrix(14, &mut rng);
+            let mut line: Vec<usize> = (0..14).collect();
+            line.shuffle(&mut rng);
+            let weights: Vec<usize> = (0..14).map(|_| rng.gen_range(0, 6)).collect();
+            let graph = Graph::from_edges(&edges).unwrap().with_vertex_weights(|v| weights[*v]).unwrap();
+            let table: HashMap<(usize, usize), usize> = edges.iter().map(|e| ((e.start, e.end), e.weight)).collect();
+            for k in 1..=line.len().min(7) {
+                let cut = dynamic_program(&graph, &line, k).unwrap();
+                let cuts: Vec<usize> = cut.bounds[1..k].iter().map(|b| b - 1).collect();
+                assert_eq!(cuts, baseline_dynamic_program(&line, &weights, k, &table), "seed {} k {}", seed, k);
+            }
+        }
+    }
+
+    #[test]
+    fn dynamic_program_handles_long_lines() {
+        //10万个点的环，每段代价为点数加上内部与两端的边数
+        let n = 100_000;
+        let edges: Vec<Edge<usize>> = (0..n).map(|i| Edge { start: i, end: (i + 1) % n, weight: 1 }).collect();
+        let graph = Graph::from_edges(&edges).unwrap();
+        let line: Vec<usize> = (0..n).collect();
+        let cut = dynamic_program(&graph, &line, 8).unwrap();
+        assert_eq!(cut.bounds, even_bounds(n, 8));
+        assert!(cut.costs.iter().all(|&c| c == 2 * n / 8 + 1));
+    }
+
+    #[test]
+    fn dynamic_program_accepts_float_weights() {
+        //路径0-1-2-3，点3很重，最优切法把它单独放在一段
+        let edges: Vec<Edge<usize, OrderedFloat<f64>>> =
+            (0..3).map(|i| Edge { start: i, end: i + 1, weight: OrderedFloat(0.5) }).collect();
+        let graph = Graph::from_edges(&edges).unwrap().with_vertex_weights(|&v| OrderedFloat(if v == 3 { 4.0 } else { 1.0 })).unwrap();
+        let cut = dynamic_program(&graph, &[0, 1, 2, 3], 2).unwrap();
+        assert_eq!(cut.bounds, vec![0, 3, 4]);
+        assert_eq!(cut.costs, vec![OrderedFloat(4.5), OrderedFloat(4.5)]);
+    }
+
+    #[test]
+    fn rank_swap_never_increases_the_heaviest_part() {
+        let line: Vec<usize> = (0..40).collect();
+        let weight = |v: &usize| v % 7 + v / 10;
+        let bounds = even_bounds(line.len(), 4);
+        let heaviest = |l: &[usize]| bounds.windows(2).map(|w| l[w[0]..w[1]].iter().map(weight).sum::<usize>()).max().unwrap();
+        let modes = [RankSwapOptions::Near { intervals: 3 }, RankSwapOptions::Rank { intervals: 3 }, RankSwapOptions::Greedy { intervals: 3, partners: 2 }];
+        for mode in &modes {
+            let (swapped, stats) = rank_swap(&line, &bounds, mode, weight, &mut StdRng::seed_from_u64(1));
+            let mut sorted = swapped.clone();
+            sorted.sort_unstable();
+            assert_eq!(sorted, line);
+            assert!(heaviest(&swapped) < heaviest(&line), "{:?}", mode);
+            assert_eq!((stats.max_before, stats.max_after), (heaviest(&line), heaviest(&swapped)));
+            assert!(stats.swaps > 0 && stats.sweeps >= 2, "{:?}", stats);
+            //同一个种子结果相同
+            assert_eq!(rank_swap(&line, &bounds, mode, weight, &mut StdRng::seed_from_u64(1)), (swapped, stats));
+        }
+    }
+
+    #[test]
+    fn greedy_rank_swap_spreads_one_heavy_part() {
+        //第一个part很重，其余三个很轻：一对一配对只能分给一个part
+        let line: Vec<usize> = (0..40).collect();
+        let weight = |v: &usize| if *v < 10 { 10 } else { 1 };
+        let bounds = even_bounds(line.len(), 4);
+        let heaviest = |l: &[usize]| bounds.windows(2).map(|w| l[w[0]..w[1]].iter().map(weight).sum::<usize>()).max().unwrap();
+        let (_, one) = rank_swap(&line, &bounds, &RankSwapOptions::Greedy { intervals: 1, partners: 1 }, weight, &mut StdRng::seed_from_u64(1));
+        let (swapped, many) = rank_swap(&line, &bounds, &RankSwapOptions::Greedy { intervals: 1, partners: 3 }, weight, &mut StdRng::seed_from_u64(1));
+        assert_eq!(many.max_before, 100);
+        assert_eq!(many.max_after, heaviest(&swapped));
+        assert!(many.max_after <= one.max_after);
+        assert!(many.max_after <= 40, "{:?}", many);
+    }
+
+    #[test]
+    fn near_rank_swap_stays_between_neighbouring_parts() {
+        let line: Vec<usize> = (0..40).collect();
+        let bounds = even_bounds(line.len(), 4);
+        let swapped = rank_swap(&line, &bounds, &RankSwapOptions::Near { intervals: 2 }, |v: &usize| v % 7 + v / 10, &mut StdRng::seed_from_u64(1)).0;
+        let part = |l: &[usize], v: usize| bounds.windows(2).position(|w| l[w[0]..w[1]].contains(&v)).unwrap();
+        for &v in &line {
+            assert_eq!(part(&swapped, v) / 2, part(&line, v) / 2);
+        }
+    }
+
+    #[test]
+    fn near_rank_swap_pairs_the_last_of_an_odd_number_of_parts() {
+        //5个part，最后一个最重
+        let line: Vec<usize> = (0..50).collect();
+        let weight = |v: &usize| if *v >= 40 { 5 } else { 1 };
+        let bounds = even_bounds(line.len(), 5);
+        let (swapped, stats) = rank_swap(&line, &bounds, &RankSwapOptions::Near { intervals: 2 }, weight, &mut StdRng::seed_from_u64(1));
+        let part = |l: &[usize], v: usize| bounds.windows(2).position(|w| l[w[0]..w[1]].contains(&v)).unwrap();
+        let last: usize = swapped[bounds[4]..].iter().map(weight).sum();
+        assert_eq!(stats.max_before, 50);
+        assert!(last < 50 && stats.max_after < 50, "{:?}", stats);
+        //配对为(0, 1)、(2, 3)、(3, 4)，点不会离开{0, 1}或{2, 3, 4}
+        let group = |p: usize| p.min(3) / 2;
+        for &v in &line {
+            assert_eq!(group(part(&swapped, v)), group(part(&line, v)));
+        }
+    }
+
+    #[test]
+    fn partition_assigns_every_vertex() {
+        let (_, edges) = make_random_graph_matrix(30, &mut StdRng::seed_from_u64(5));
+        let graph = Graph::from_edges(&edges).unwrap();
+        let options = PartitionOptions { cluster_threshold: 4, rank_swap: Some(RankSwapOptions::Rank { intervals: 2 }), seed: 3, ..Default::default() };
+        let p = partition(&graph, 4, &options).unwrap();
+        assert_eq!(p.k(), 4);
+        assert_eq!(p.order().len(), graph.vertex_count());
+        for v in graph.vertices() {
+            assert!(p.part(p.part_of(v).unwrap()).contains(v));
+        }
+        assert_eq!(p.max_cost(), *p.costs().iter().max().unwrap());
+        assert!(p.rank_swap().is_some_and(|s| s.max_after <= s.max_before));
+
+        let again = partition(&graph, 4, &options).unwrap();
+        assert_eq!(again.parts(), p.parts());
+        let isolated = Graph::from_edges(&[Edge { start: 0, end: 1, weight: 1 }]).unwrap();
+        assert_eq!(partition(&isolated, 3, &options).err(), Some(ClusteringError::KExceedsVertexCount { k: 3, vertices: 2 }));
+    }
+
+    #[test]
+    fn refinement_lowers_the_cut_of_the_dp_partition() {
+        use crate::generators::{stochastic_block_model, Weights};
+        let sbm = stochastic_block_model(&[30, 30, 30, 30], 0.3, 0.03, &Weights::default(), &mut StdRng::seed_from_u64(4));
+        let graph = Graph::from_edges(&sbm.edges).unwrap();
+        let options = PartitionOptions { common_neighbors: false, cluster_threshold: 8, seed: 1, ..Default::default() };
+        let plain = partition(&graph, 4, &options).unwrap();
+        let refine = RefineOptions { balance_tolerance: 0.1, max_passes: 8 };
+        let refined = partition(&graph, 4, &PartitionOptions { refine: Some(refine), ..options }).unwrap();
+
+        let cut = |p: &Partitioning<usize>| crate::metrics::evaluate(&sbm.edges, &p.parts()).unwrap().edge_cut;
+        let passes = refined.refinement();
+        assert!(!passes.is_empty() && plain.refinement().is_empty());
+        assert_eq!(cut(&plain), 2 * passes[0].cut_before);
+        assert_eq!(cut(&refined), 2 * passes.last().unwrap().cut_after);
+        assert!(cut(&refined) <= cut(&plain));
+        assert_eq!(refined.costs(), &part_costs(&graph, refined.order(), refined.bounds())[..]);
+        for v in graph.vertices() {
+            assert!(refined.part(refined.part_of(v).unwrap()).contains(v));
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn partitioning_round_trips_through_bincode() {
+        let (_, edges) = make_random_graph_matrix(30, &mut StdRng::seed_from_u64(5));
+        let graph = Graph::from_edges(&edges).unwrap();
+        let p = partition(&graph, 3, &PartitionOptions { cluster_threshold: 4, ..Default::default() }).unwrap();
+        let restored: Partitioning<usize> = bincode::deserialize(&bincode::serialize(&p).unwrap()).unwrap();
+        assert_eq!(restored.parts(), p.parts());
+        assert_eq!(restored.costs(), p.costs());
+        assert!(graph.vertices().iter().all(|v| restored.part_of(v) == p.part_of(v)));
+
+        let metrics = crate::metrics::evaluate(&edges, &p.parts()).unwrap();
+        let json = serde_json::to_string(&metrics).unwrap();
+        assert_eq!(serde_json::from_str::<crate::metrics::PartitionMetrics>(&json).unwrap(), metrics);
+    }
+}
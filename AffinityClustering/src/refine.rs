@@ -0,0 +1,372 @@
+//! Fiduccia–Mattheyses refinement of a k-way partition.
+//!
+//! Every vertex is keyed in an ordered set by the best move it could make:
+//! to the neighbouring part it is most strongly connected to, provided the
+//! move keeps that part within the balance tolerance. A pass repeatedly moves
+//! the unlocked vertex with the highest gain, locks it and updates the gains
+//! of its neighbours and of the vertices that were waiting for the part it
+//! left to become lighter, then rolls back to the prefix of moves with the
+//! smallest cut. Passes stop once one no longer lowers the cut.
+//!
+//! Edges are treated as undirected and an edge listed in both directions
+//! counts once, as in the DP partitioner.
+
+use crate::affinity_clustering::ClusteringError;
+use crate::graph::{Csr, Graph};
+use crate::weight::Weight;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::hash::Hash;
+use std::mem;
+use tracing::{debug, debug_span};
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RefineOptions {
+    /// No move may make a part heavier than `(1 + balance_tolerance)` times
+    /// the mean part node weight, or than the heaviest part before refining
+    /// if that is heavier already.
+    pub balance_tolerance: f64,
+    pub max_passes: usize,
+}
+
+impl Default for RefineOptions {
+    fn default() -> Self {
+        RefineOptions { balance_tolerance: 0.05, max_passes: 8 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RefinePass<W = usize> {
+    pub pass: usize,
+    pub cut_before: W,
+    pub cut_after: W,
+    pub moves: usize,//回滚之后保留的移动数
+}
+
+//移动的收益，Loss < Gain，损失越大越小；用两个分支表示是为了无符号边权也能比较
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Gain<W> {
+    Loss(Reverse<W>),
+    Gain(W),
+}
+
+impl<W: Weight> Gain<W> {
+    fn of(to: W, from: W) -> Self {
+        if to >= from { Gain::Gain(to - from) } else { Gain::Loss(Reverse(from - to)) }
+    }
+}
+
+struct Fm<'a, W> {
+    adjacency: &'a Csr<W>,
+    node_weight: &'a [W],
+    part: Vec<usize>,
+    part_weight: Vec<W>,
+    part_size: Vec<usize>,
+    limit: f64,
+    buckets: BTreeSet<(Gain<W>, Reverse<usize>)>,//按收益排序，相同时编号小的优先
+    key: Vec<Option<(Gain<W>, usize)>>,//每个点在buckets中的收益和目标part
+    locked: Vec<bool>,
+    waiting: Vec<Vec<usize>>,//waiting[p]为因p太重而不能移入p的点，p变轻时重新计算
+}
+
+impl<'a, W: Weight> Fm<'a, W> {
+    //v与各part之间的边权和
+    fn connectivity(&self, v: usize) -> HashMap<usize, W> {
+        let mut conn = HashMap::new();
+        for (u, w) in self.adjacency.neighbors(v) {
+            let c = conn.entry(self.part[u]).or_insert_with(W::zero);
+            *c = *c + w;
+        }
+        conn
+    }
+
+    //v满足平衡约束的最佳移动：(收益, 目标part)，相同收益时取编号小的part；太重的part记入waiting
+    fn best_move(&mut self, v: usize) -> Option<(Gain<W>, usize)> {
+        let own = self.part[v];
+        if self.part_size[own] == 1 {
+            return None;
+        }
+        let conn = self.connectivity(v);
+        let stay = conn.get(&own).copied().unwrap_or_else(W::zero);
+        let mut best: Option<(Gain<W>, usize)> = None;
+        for (&p, &w) in &conn {
+            if p == own {
+                continue;
+            }
+            if (self.part_weight[p] + self.node_weight[v]).to_f64() > self.limit {
+                self.waiting[p].push(v);
+                continue;
+            }
+            let gain = Gain::of(w, stay);
+            if best.is_none_or(|(g, q)| gain > g || (gain == g && p < q)) {
+                best = Some((gain, p));
+            }
+        }
+        best
+    }
+
+    fn rekey(&mut self, v: usize) {
+        if let Some((gain, _)) = self.key[v].take() {
+            self.buckets.remove(&(gain, Reverse(v)));
+        }
+        if !self.locked[v] {
+            self.key[v] = self.best_move(v);
+            if let Some((gain, _)) = self.key[v] {
+                self.buckets.insert((gain, Reverse(v)));
+            }
+        }
+    }
+
+    fn move_to(&mut self, v: usize, to: usize) {
+        let from = self.part[v];
+        self.part_weight[from] = self.part_weight[from] - self.node_weight[v];
+        self.part_size[from] -= 1;
+        self.part_weight[to] = self.part_weight[to] + self.node_weight[v];
+        self.part_size[to] += 1;
+        self.part[v] = to;
+    }
+
+    fn cut(&self) -> W {
+        (0..self.part.len())
+            .flat_map(|v| self.adjacency.neighbors(v).map(move |(u, w)| (v, u, w)))
+            .filter(|&(v, u, _)| v < u && self.part[v] != self.part[u])
+            .fold(W::zero(), |sum, (_, _, w)| sum + w)
+    }
+
+    //一趟FM，返回保留的移动数
+    fn pass(&mut self, cut: W) -> (W, usize) {
+        let n = self.part.len();
+        self.buckets.clear();
+        self.locked = vec![false; n];
+        self.key = vec![None; n];
+        self.waiting = vec![Vec::new(); self.part_weight.len()];
+        for v in 0..n {
+            self.rekey(v);
+        }
+        let mut moves = Vec::<(usize, usize)>::new();//(点, 原来的part)
+        let (mut current, mut best, mut best_len) = (Gain::Gain(W::zero()), Gain::Gain(W::zero()), 0);
+        while let Some(&(gain, Reverse(v))) = self.buckets.iter().next_back() {
+            let (_, to) = self.key[v].unwrap();
+            //目标part在这期间可能变重，重新计算
+            if (self.part_weight[to] + self.node_weight[v]).to_f64() > self.limit || self.part_size[self.part[v]] == 1 {
+                self.rekey(v);
+                continue;
+            }
+            self.buckets.remove(&(gain, Reverse(v)));
+            self.key[v] = None;
+            self.locked[v] = true;
+            let from = self.part[v];
+            moves.push((v, from));
+            self.move_to(v, to);
+            current = add(current, gain);
+            if current > best {
+                best = current;
+                best_len = moves.len();
+            }
+            for i in 0..self.adjacency.degree(v) {
+                let u = self.adjacency.targets(v)[i];
+                self.rekey(u);
+            }
+            //同一个点可能被多次记入，去重之后再计算，否则每次移动都会成倍增加重复
+            let mut waiting = mem::take(&mut self.waiting[from]);
+            waiting.sort_unstable();
+            waiting.dedup();
+            for u in waiting {
+                self.rekey(u);
+            }
+        }
+        for &(v, from) in moves[best_len..].iter().rev() {
+            self.move_to(v, from);
+        }
+        let after = match best {
+            Gain::Gain(g) => cut - g,
+            Gain::Loss(_) => cut,
+        };
+        (after, best_len)
+    }
+}
+
+fn add<W: Weight>(a: Gain<W>, b: Gain<W>) -> Gain<W> {
+    match (a, b) {
+        (Gain::Gain(x), Gain::Gain(y)) => Gain::Gain(x + y),
+        (Gain::Loss(Reverse(x)), Gain::Loss(Reverse(y))) => Gain::Loss(Reverse(x + y)),
+        (Gain::Gain(x), Gain::Loss(Reverse(y))) | (Gain::Loss(Reverse(y)), Gain::Gain(x)) => Gain::of(x, y),
+    }
+}
+
+//去掉自环、同一对点只保留第一条边之后的双向邻接表
+fn undirected<T: Copy + Hash + Eq, W: Weight>(graph: &Graph<T, W>) -> Csr<W> {
+    let mut seen = HashSet::<(usize, usize)>::new();
+    let mut triples = Vec::new();
+    for (s, t, w) in graph.csr().triples() {
+        if s != t && seen.insert((s.min(t), s.max(t))) {
+            triples.push((s, t, w));
+            triples.push((t, s, w));
+        }
+    }
+    Csr::from_triples(graph.vertex_count(), triples)
+}
+
+/// Refines `part`, the part of every vertex by dense id, in place to lower
+/// the edge cut of `graph`, and returns the statistics of every pass.
+///
+/// Fails if `part` does not cover every vertex or names a part `>= k`.
+pub fn fm_refine<T, W>(graph: &Graph<T, W>, part: &mut [usize], k: usize, options: &RefineOptions) -> Result<Vec<RefinePass<W>>, ClusteringError>
+where
+    T: Copy + Hash + Eq,
+    W: Weight,
+{
+    if part.len() != graph.vertex_count() {
+        return Err(ClusteringError::PartLengthMismatch { len: part.len(), vertices: graph.vertex_count() });
+    }
+    if let Some((vertex, &p)) = part.iter().enumerate().find(|&(_, &p)| p >= k) {
+        return Err(ClusteringError::PartOutOfRange { vertex, part: p, k });
+    }
+    let _span = debug_span!("fm_refine", vertices = graph.vertex_count(), k).entered();
+    let adjacency = undirected(graph);
+    let node_weight = graph.vertex_weights();
+    let mut part_weight = vec![W::zero(); k];
+    let mut part_size = vec![0; k];
+    for (v, &p) in part.iter().enumerate() {
+        part_weight[p] = part_weight[p] + node_weight[v];
+        part_size[p] += 1;
+    }
+    let total: f64 = node_weight.iter().map(|w| w.to_f64()).sum();
+    let heaviest = part_weight.iter().map(|w| w.to_f64()).fold(0.0, f64::max);
+    let limit = ((1.0 + options.balance_tolerance) * total / k as f64).max(heaviest);
+    let mut fm = Fm {
+        adjacency: &adjacency,
+        node_weight,
+        part: part.to_vec(),
+        part_weight,
+        part_size,
+        limit,
+        buckets: BTreeSet::new(),
+        key: Vec::new(),
+        locked: Vec::new(),
+        waiting: Vec::new(),
+    };
+
+    let mut passes = Vec::new();
+    let mut cut = fm.cut();
+    for pass in 1..=options.max_passes {
+        let (after, moves) = fm.pass(cut);
+        debug!(pass, cut_before = %cut, cut_after = %after, moves, "refinement pass finished");
+        passes.push(RefinePass { pass, cut_before: cut, cut_after: after, moves });
+        if after >= cut {
+            break;
+        }
+        cut = after;
+    }
+    part.copy_from_slice(&fm.part);
+    Ok(passes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::affinity_clustering::Edge;
+    use crate::generators::{stochastic_block_model, WeightNoise, Weights};
+    use crate::partition::{partition, PartitionOptions, RankSwapOptions};
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::SeedableRng;
+
+    fn cut_of(graph: &Graph<usize>, part: &[usize]) -> usize {
+        let adjacency = undirected(graph);
+        adjacency.triples().filter(|&(s, t, _)| s < t && part[s] != part[t]).map(|(_, _, w)| w).sum()
+    }
+
+    #[test]
+    fn refinement_lowers_the_cut_within_tolerance() {
+        let sbm = stochastic_block_model(&[40, 40, 40, 40], 0.3, 0.02, &Weights::default(), &mut StdRng::seed_from_u64(1));
+        let graph = Graph::from_edges(&sbm.edges).unwrap();
+        //随机的平衡划分
+        let mut part: Vec<usize> = (0..graph.vertex_count()).map(|v| v % 4).collect();
+        part.shuffle(&mut StdRng::seed_from_u64(2));
+        let before = cut_of(&graph, &part);
+        let options = RefineOptions { balance_tolerance: 0.1, max_passes: 10 };
+        let passes = fm_refine(&graph, &mut part, 4, &options).unwrap();
+
+        assert_eq!(passes[0].cut_before, before);
+        assert_eq!(passes.last().unwrap().cut_after, cut_of(&graph, &part));
+        assert!(passes.windows(2).all(|p| p[1].cut_before == p[0].cut_after));
+        assert!(passes.iter().all(|p| p.cut_after <= p.cut_before));
+        assert!(cut_of(&graph, &part) * 2 < before);
+        for p in 0..4 {
+            let size = part.iter().filter(|&&q| q == p).count();
+            assert!(size as f64 <= 1.1 * 40.0, "part {} has {} vertices", p, size);
+        }
+    }
+
+    #[test]
+    fn refinement_keeps_an_optimal_partition() {
+        //两个三角形由一条边相连
+        let mut edges = Vec::new();
+        for &(s, t) in &[(0, 1), (1, 2), (0, 2), (3, 4), (4, 5), (3, 5), (2, 3)] {
+            edges.push(Edge { start: s, end: t, weight: 1 });
+            edges.push(Edge { start: t, end: s, weight: 1 });
+        }
+        let graph = Graph::from_edges(&edges).unwrap();
+        let mut part = vec![0, 0, 0, 1, 1, 1];
+        let passes = fm_refine(&graph, &mut part, 2, &RefineOptions::default()).unwrap();
+        assert_eq!(part, vec![0, 0, 0, 1, 1, 1]);
+        assert_eq!(passes, vec![RefinePass { pass: 1, cut_before: 1, cut_after: 1, moves: 0 }]);
+    }
+
+    #[test]
+    fn moves_blocked_by_a_full_part_resume_once_it_lightens() {
+        //part 0 = {y, a, b}，part 1 = {c, d, e}，part 2 = {x, z}，每个part最多3个点。
+        //x想移入part 0但它已满；y移到part 2之后part 0变轻，x应在同一趟里移过去，尽管x与y不相邻
+        let (y, a, b, c, d, e, x, z) = (0, 1, 2, 3, 4, 5, 6, 7);
+        let mut edges = Vec::new();
+        for &(s, t, w) in &[(y, z, 5), (y, a, 1), (a, b, 1), (x, a, 3), (x, b, 3), (x, z, 1), (c, d, 1), (d, e, 1), (c, e, 1)] {
+            edges.push(Edge { start: s, end: t, weight: w });
+            edges.push(Edge { start: t, end: s, weight: w });
+        }
+        let graph = Graph::from_edges(&edges).unwrap();
+        let of = |v: usize| graph.id(&v).unwrap();
+        let mut part = vec![0; 8];
+        for (v, p) in [(y, 0), (a, 0), (b, 0), (c, 1), (d, 1), (e, 1), (x, 2), (z, 2)] {
+            part[of(v)] = p;
+        }
+        let options = RefineOptions { balance_tolerance: 0.0, max_passes: 1 };
+        let passes = fm_refine(&graph, &mut part, 3, &options).unwrap();
+        assert_eq!(passes, vec![RefinePass { pass: 1, cut_before: 11, cut_after: 2, moves: 2 }]);
+        assert_eq!((part[of(x)], part[of(y)]), (0, 2));
+    }
+
+    #[test]
+    fn uneven_vertex_weights_keep_the_waiting_lists_small() {
+        //一成的点权重为100，DP切割后的part接近上限，很多移动被挡住；等待重新计算的点不能随移动成倍增加
+        let weights = Weights {
+            node: WeightNoise { low: 3, high: 6, noise_weight: 100, noise_rate: 10 },
+            edge: WeightNoise { low: 1, high: 2, noise_weight: 1, noise_rate: 0 },
+        };
+        let sbm = stochastic_block_model(&[30, 30, 30], 0.5, 0.01, &weights, &mut StdRng::seed_from_u64(2021));
+        let graph = sbm.graph();
+        let options = PartitionOptions {
+            rank_swap: Some(RankSwapOptions::Rank { intervals: 5 }),
+            refine: Some(RefineOptions { balance_tolerance: 0.1, max_passes: 8 }),
+            seed: 2021,
+            ..Default::default()
+        };
+        let p = partition(&graph, 3, &options).unwrap();
+        let refinement = p.refinement();
+        assert!(!refinement.is_empty());
+        assert!(refinement.iter().all(|pass| pass.cut_after <= pass.cut_before));
+    }
+
+    #[test]
+    fn malformed_partitions_are_rejected() {
+        let edges = vec![Edge { start: 0, end: 1, weight: 1 }, Edge { start: 1, end: 0, weight: 1 }];
+        let graph = Graph::from_edges(&edges).unwrap();
+        let options = RefineOptions::default();
+        assert_eq!(fm_refine(&graph, &mut [0], 2, &options), Err(ClusteringError::PartLengthMismatch { len: 1, vertices: 2 }));
+        assert_eq!(fm_refine(&graph, &mut [0, 2], 2, &options), Err(ClusteringError::PartOutOfRange { vertex: 1, part: 2, k: 2 }));
+    }
+}
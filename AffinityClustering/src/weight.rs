@@ -0,0 +1,76 @@
+//! Numeric types usable as vertex and edge weights.
+//!
+//! Clustering only compares and adds weights; the DP partitioner also
+//! subtracts them. Floats work through `ordered_float::OrderedFloat`, which
+//! supplies the total order `Ord` needs. Signed types are accepted, but
+//! every algorithm assumes weights are non-negative and `Graph` rejects
+//! negative ones.
+
+use ordered_float::OrderedFloat;
+use std::fmt::{Debug, Display};
+use std::ops::{Add, Sub};
+
+pub use ordered_float;
+
+pub trait Weight: Copy + Ord + Default + Debug + Display + Add<Output = Self> + Sub<Output = Self> + Send + Sync {
+    /// Weight of a vertex when none is given.
+    fn one() -> Self;
+
+    fn zero() -> Self {
+        Self::default()
+    }
+
+    /// Lossy conversion for linkages that average or normalise weights.
+    fn to_f64(self) -> f64;
+
+    /// `self + other`, stopping at the largest value of the type.
+    fn saturating_add(self, other: Self) -> Self;
+}
+
+macro_rules! integer_weight {
+    ($($t:ty),*) => {
+        $(impl Weight for $t {
+            fn one() -> Self {
+                1
+            }
+
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+
+            fn saturating_add(self, other: Self) -> Self {
+                <$t>::saturating_add(self, other)
+            }
+        })*
+    };
+}
+
+integer_weight!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+impl Weight for OrderedFloat<f32> {
+    fn one() -> Self {
+        OrderedFloat(1.0)
+    }
+
+    fn to_f64(self) -> f64 {
+        f64::from(self.0)
+    }
+
+    fn saturating_add(self, other: Self) -> Self {
+        OrderedFloat((self.0 + other.0).min(f32::MAX))
+    }
+}
+
+impl Weight for OrderedFloat<f64> {
+    fn one() -> Self {
+        OrderedFloat(1.0)
+    }
+
+    fn to_f64(self) -> f64 {
+        self.0
+    }
+
+    fn saturating_add(self, other: Self) -> Self {
+        OrderedFloat((self.0 + other.0).min(f64::MAX))
+    }
+}
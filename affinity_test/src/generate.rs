@@ -0,0 +1,23 @@
+use rand::Rng;
+use AffinityClustering::affinity_clustering::Edge;
+
+//随机图：前10%的点权重在[30, 50]中，其余在[1, 5]中；i<j之间有边的概率为1/(j-i)
+pub fn make_random_graph<R: Rng + ?Sized>(verticle: usize, rng: &mut R) -> (Vec::<Edge<usize>>, Vec::<usize>) {
+    let mut edges = Vec::<Edge<usize>>::new();
+    let mut random_weight = Vec::<usize>::new();
+    for _ in 0..verticle/10 {
+        random_weight.push(rng.gen_range(30, 51));
+    }
+    for _ in verticle/10..verticle {
+        random_weight.push(rng.gen_range(1, 6));
+    }
+    for i in 0..verticle {
+        for j in i+1..verticle {
+            if rng.gen_range(1, j-i+1) == 1 {
+                edges.push(Edge { start: i, end: j, weight: 1 });
+                edges.push(Edge { start: j, end: i, weight: 1 });
+            }
+        }
+    }
+    (edges, random_weight)
+}
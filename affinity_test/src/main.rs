@@ -1,1088 +1,686 @@
-use core::cmp::max;
-use rand::{Rng, thread_rng};
-use std::fmt::{Display, Debug};
-use std::cmp::{Eq};
-use ndarray::{Array3, ArrayBase, Array2};
-use std::collections::{HashSet, HashMap};
-use std::hash::Hash;
-use std::marker::Copy;
-use std::cmp::{PartialEq};
-extern crate stopwatch;
-use stopwatch::{Stopwatch};
-
-pub struct ArrayUnion<T> {
-    group: HashMap::<T, T>,
-    size: HashMap::<T, usize>,
-    items: HashMap::<T, Vec::<T>>,
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use AffinityClustering::affinity_clustering::{sparsify, Affinity, ClusteringConfig, ClusteringError, ClusteringReport, Edge, RoundStats, StopReason};
+use AffinityClustering::constraints::{ConstraintReport, Constraints};
+use AffinityClustering::generators::{self, Topology, WeightNoise, Weights};
+use AffinityClustering::graph::Graph;
+use AffinityClustering::io::{read_graph_file, write_graph_file, GraphFormat};
+use AffinityClustering::linkage::{Average, Cosine, Jaccard, Linkage, Max, Min};
+use AffinityClustering::metrics::{compare, evaluate_weighted, Agreement, PartitionMetrics};
+use AffinityClustering::neighborhood::{self, HubPolicy, NeighborhoodOptions};
+use AffinityClustering::partition::{partition_with_progress, PartitionOptions, RankSwapOptions, RankSwapStats};
+use AffinityClustering::refine::{RefineOptions, RefinePass};
+use tracing::Level;
+
+mod generate;
+mod table;
+
+use table::{OutputFormat, VertexWeights};
+
+/// Graph clustering and partitioning experiments.
+#[derive(Parser)]
+#[command(name = "affinity_test")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    /// Log the phases to standard error: -v for a summary of every phase, -vv for every round as well
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
 }
 
-pub struct Affinity<T> {
-    k: usize,
-    pub E: Vec::<Edge_py<T>>,
-    pub V: Vec::<T>,//目前所有节点均为已知，故不需要集合类型
-    uf: ArrayUnion::<T>,
-    clost_neighbors: HashMap::<T, T>,
-    merged: HashMap::<T, Option::<T>>//使用没有value的hashmap作为集合类型
+#[derive(Subcommand)]
+enum Command {
+    /// Generate a graph, or convert one between file formats
+    #[command(subcommand)]
+    Generate(GraphKind),
+    /// Run affinity clustering and write the clusters
+    Cluster(ClusterArgs),
+    /// Partition a graph into k parts and write the part of every vertex
+    Partition(PartitionArgs),
+    /// Evaluate a vertex,part assignment against a graph
+    Evaluate(EvaluateArgs),
 }
 
-impl<T:Debug + Display + Copy + Hash + Eq> Affinity<T> {
-    pub fn new_and_init(edges: &Vec<Edge_py<T>>, k: usize) -> Self {
-        let mut v_set = HashMap::<T, Option<T>>::new();
-        for e in edges.iter() {
-            v_set.insert(e.start, None);
-            v_set.insert(e.end, None);
-        }
-        let v: Vec::<T> = v_set.keys().into_iter().map(|&x| x).collect();
-        println!("number of vertexs is:{}", v.len());
-        Affinity {
-            k: k,
-            E: edges.clone(),
-            V: v.clone(),
-            uf: ArrayUnion::new_and_init(v),
-            clost_neighbors: HashMap::new(),
-            merged: HashMap::new()
-        }
-    }
-
-    pub fn print_all_clusters(&self) {
-        for (name, clusters) in self.uf.items.iter() {
-            println!("cluster{}: {:?}", name, clusters);
-            println!();
-        }
-    }
-
-    fn merge_with_cloest_neighbors(&mut self, v: T, mut v_stack: HashMap::<T, Option<T>>) -> HashMap::<T, Option<T>>{
-        if self.merged.contains_key(&v) {
-            return v_stack;//v_stack记录该函数的栈里目前都寻找了哪些v，避免死循环
-        }
-        if let Some(self_closet) = self.clost_neighbors.get(&v) {
-            if let Some(self_cloest_cloest) = self.clost_neighbors.get(self_closet) {
-                if v == *self_cloest_cloest {
-                    let findv = self.uf.find(v);
-                    let find_clost_neighbor = self.uf.find(*self_closet);
-                    let tag = self.uf.try_union(findv, find_clost_neighbor);//出现所有权问题，考虑将调用的函数设为&mut self
-                    //可直接写为self.uf.union(self.uf.find(v), self.uf.find(*self.clost_neighbors.get(&v).unwrap()))， 考虑到对find函数的多次调用
-                    if tag == true {
-                        self.merged.insert(v, None);
-                        self.merged.insert(*self_closet, None);
-                    }
-                    return v_stack;
-                } else {
-                    let findv = self.uf.find(v);
-                    let find_clost_neighbor = self.uf.find(*self_closet);
-                    if v_stack.contains_key(&v) {
-                        //v_stack.remove(&v);
-                        return v_stack;
-                    } else {
-                        v_stack.insert(v, None);
-                        unsafe {
-                            v_stack = self.merge_with_cloest_neighbors(self_closet.clone(), v_stack);
-                        }
-                        v_stack.remove(&v);
-                        let tag = self.uf.try_union(findv, find_clost_neighbor);
-                        if tag == true {
-                            self.merged.insert(v, None);
-                            self.merged.insert(*self.clost_neighbors.get(&v).unwrap(), None);
-                        }
-                        return  v_stack;
-                    }
-                }
-            }
-        }
-        v_stack
-    }
-
-    fn fragment_process(&mut self, round: u32) {
-        let init_group: Vec::<T> = self.uf.get_items();
-        for group_name in init_group {
-            if self.uf.items.contains_key(&group_name) {
-                if *self.uf.size.get(&group_name).unwrap() < 2usize.pow(round) {
-                    let mut edges_of_group = Vec::<Edge_py<T>>::new();
-                    for e in &self.E {
-                        if e.start == group_name {
-                            edges_of_group.push(e.clone());
-                        }
-                    }
-                    edges_of_group.sort_by_key(|x| x.weight);
-                    for e in &edges_of_group {
-                        if self.uf.find(e.end) != group_name {
-                            let atg = self.uf.try_union(group_name, e.end);
-                        }
-                    }
-                }
-            }
-        }
-    }
-
-    fn edges_update(&mut self) {
-        let mut new_edges = Vec::<Edge_py<T>>::new();
-            for e in &self.E {
-                if self.uf.find(e.start) != self.uf.find(e.end) {
-                    new_edges.push(Edge_py {
-                        start: self.uf.find(e.start),
-                        end: self.uf.find(e.end),
-                        weight: e.weight,
-                    })
-                }
-            }
-        self.E = new_edges;
-    }
-
-    fn clustering(&mut self, FragmentProcess: bool, CommonNeighborCluster: bool) {
-        let mut number_of_clusters = self.V.len();
-        let mut vertexs = self.V.clone();
-        let mut count = 0;
-        while number_of_clusters > self.k && count < 5 {
-            count += 1;
-            println!("-----------------------------------after {} rounds clustering-------------------------------", count);
-            self.clost_neighbors = HashMap::new();
-            self.merged = HashMap::new();
-            let selfe = &self.E;
-            let mut EEV = edges_of_every_vertexs(selfe);
-            let mut min_edges = Vec::<Edge_py<T>>::new();
-            let mut v_stack = HashMap::<T, Option<T>>::new();
-            //println!("EEV number is:{}", EEV.len());
-
-            for value in EEV.values_mut() {
-                value.sort_by_key(|x| x.weight);
-                if CommonNeighborCluster == true {
-                    value.reverse();
-                }
-                min_edges.push(value[0].clone());
-            }
-
-            for edge in min_edges {
-                self.clost_neighbors.insert(edge.start, edge.end);
-            }
-            
-            for v in &vertexs {
-                if !self.merged.contains_key(&v) {
-                    v_stack = self.merge_with_cloest_neighbors(*v, v_stack);
-                }
-            }
-
-            //更新两个cluster之间的边
-            self.edges_update();
-
-            //更新点
-            vertexs = self.uf.get_items();
-            number_of_clusters = vertexs.len();
-
-            if FragmentProcess == true {
-                //处理碎片，可以避免极端不平衡的碎片，但会降低性能
-                self.fragment_process(count);
-                self.edges_update();
-                //self.print_all_clusters();
-            }
-            //print_edges(&self.E);
-            //self.print_all_clusters();
-            println!("present number of clusters is: {}", number_of_clusters);
-        }
-    }
-
-    pub fn linear_embed(&self) -> Vec::<T> {
-        let mut line = Vec::<T>::new();
-        for (_, cluster) in &self.uf.items {
-            line.append(&mut cluster.clone());
-        }
-        println!("   ");
-        line
-    }
+#[derive(Subcommand)]
+enum GraphKind {
+    /// k-ary fat-tree
+    FatTree {
+        #[arg(long, default_value_t = 12)]
+        k: usize,
+        #[command(flatten)]
+        weights: WeightArgs,
+        #[command(flatten)]
+        out: GraphOutput,
+    },
+    /// Two-tier leaf-spine (folded Clos) network with hosts under every leaf
+    LeafSpine {
+        #[arg(long, default_value_t = 8)]
+        spines: usize,
+        #[arg(long, default_value_t = 16)]
+        leaves: usize,
+        #[arg(long, default_value_t = 8)]
+        hosts: usize,
+        #[command(flatten)]
+        weights: WeightArgs,
+        #[command(flatten)]
+        out: GraphOutput,
+    },
+    /// Torus with wrap-around links, e.g. --dims 8,8 or --dims 4,4,4
+    Torus {
+        #[arg(long, value_delimiter = ',', default_values_t = [8, 8])]
+        dims: Vec<usize>,
+        #[command(flatten)]
+        weights: WeightArgs,
+        #[command(flatten)]
+        out: GraphOutput,
+    },
+    /// Dragonfly: fully connected router groups joined by global links
+    Dragonfly {
+        #[arg(long, default_value_t = 9)]
+        groups: usize,
+        #[arg(long, default_value_t = 4)]
+        routers: usize,
+        #[arg(long, default_value_t = 2)]
+        hosts: usize,
+        #[command(flatten)]
+        weights: WeightArgs,
+        #[command(flatten)]
+        out: GraphOutput,
+    },
+    /// Erdős–Rényi graph: every pair is linked with probability p
+    ErdosRenyi {
+        #[arg(long, default_value_t = 600)]
+        vertices: usize,
+        #[arg(long, default_value_t = 0.01)]
+        p: f64,
+        #[command(flatten)]
+        weights: WeightArgs,
+        #[command(flatten)]
+        out: GraphOutput,
+    },
+    /// Barabási–Albert graph: every new vertex links to m vertices by preferential attachment
+    BarabasiAlbert {
+        #[arg(long, default_value_t = 600)]
+        vertices: usize,
+        #[arg(long, default_value_t = 3)]
+        m: usize,
+        #[command(flatten)]
+        weights: WeightArgs,
+        #[command(flatten)]
+        out: GraphOutput,
+    },
+    /// Stochastic block model with blocks of the given sizes, e.g. --sizes 100,100,200
+    Sbm {
+        #[arg(long, value_delimiter = ',', default_values_t = [100, 100, 100, 100])]
+        sizes: Vec<usize>,
+        #[arg(long, default_value_t = 0.1)]
+        p_in: f64,
+        #[arg(long, default_value_t = 0.005)]
+        p_out: f64,
+        #[command(flatten)]
+        weights: WeightArgs,
+        #[command(flatten)]
+        out: GraphOutput,
+    },
+    /// Random graph where i < j are linked with probability 1 / (j - i)
+    Random {
+        #[arg(long, default_value_t = 600)]
+        vertices: usize,
+        #[command(flatten)]
+        out: GraphOutput,
+    },
+    /// Read a graph file and write it in another format
+    File {
+        #[arg(short, long)]
+        input: PathBuf,
+        #[arg(short, long)]
+        output: PathBuf,
+    },
 }
 
-pub fn edges_of_every_vertexs<T:Debug + Display + Copy + Hash + Eq> (edges: &Vec::<Edge_py<T>>) -> HashMap::<T, Vec::<Edge_py<T>>> {//找到每个点的所有边
-    let mut edges_of_v = HashMap::<T, Vec::<Edge_py<T>>>::new();
-    for edge in edges {
-        if edges_of_v.contains_key(&edge.start) {
-            edges_of_v.get_mut(&edge.start).unwrap().push(edge.clone());
-        } else {
-            edges_of_v.insert(edge.start, vec!(edge.clone()));
-        }
-
-        if edges_of_v.contains_key(&edge.end) {
-            edges_of_v.get_mut(&edge.end).unwrap().push(edge.clone());
-        } else {
-            edges_of_v.insert(edge.end, vec!(edge.clone()));
-        }
-    }
-    println!("   ");
-    edges_of_v
+#[derive(Args)]
+struct GraphOutput {
+    /// Graph file to write; the format follows the extension (.csv, .txt, .graph, .gr, .mtx)
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Also write the vertex weights as a vertex,weight CSV file
+    #[arg(long)]
+    weights: Option<PathBuf>,
+    /// Also write the planted communities as a vertex,community CSV file
+    #[arg(long)]
+    communities: Option<PathBuf>,
+    #[arg(long, default_value_t = 2021)]
+    seed: u64,
 }
 
-impl<T:Debug + Display + Copy + Hash + Eq> ArrayUnion<T> {
-    fn new_and_init(V: Vec::<T>) -> Self {
-        let mut ArrUni = ArrayUnion {
-            group: HashMap::new(),
-            size: HashMap::new(),
-            items: HashMap::new(),
-        };
-        for v in V {
-            ArrUni.group.insert(v, v);
-            ArrUni.size.insert(v, 1);
-            ArrUni.items.insert(v, vec!(v));
-        }
-        println!("   ");
-        ArrUni
-    }
-
-    fn find(&self, target: T) -> T {//返回包含target的group id
-            if let Some(gp) = self.group.get(&target) {
-                *gp
-            } else {
-                panic!("Error happened when finding the target's group!");
-            }
-    }
-
-    fn try_union(&mut self, mut a: T, mut b: T) -> bool {
-        if !(self.items.contains_key(&a) && self.items.contains_key(&b)) {
-            //println!("Error: a and b are not both in items");
-            return false
-        }
-
-        if self.items.get(&a) == self.items.get(&b) {
-            println!("Error: These two group are in a same items");
-            return false
-        }
-
-        if self.size.get(&a).unwrap() > self.size.get(&b).unwrap() {
-            let temp = a;
-            a = b;
-            b = temp;
-        }
-        
-        for s in self.items.clone().get(&a).unwrap() {
-            if let Some(x) = self.group.get_mut(&a) {
-                *x = b
-            } else {
-                println!("Failed to get group from items a");
-                return false
-            }
-
-            if let Some(x) = self.items.get_mut(&b) {
-                (*x).push(*s);
-            } else {
-                println!("Failed to get group from items b");
-                return false
-            }
-        }
-
-        *self.size.get_mut(&b).unwrap() += *self.size.get(&a).unwrap();
-        self.size.remove(&a);
-        self.items.remove(&a);
-        true
-    }
-
-    fn get_items(&self) -> Vec::<T> {
-        self.items.keys().into_iter().map(|&x| x).collect()//将hashmap中所有key或者value输出
-    }
-}
-
-pub struct Edge_py<T> {
-    pub start: T,
-    pub end: T,
-    pub weight: usize
+#[derive(Args)]
+struct WeightArgs {
+    /// Vertex weights are drawn from [low, high)...
+    #[arg(long, default_value_t = 3)]
+    low: usize,
+    #[arg(long, default_value_t = 6)]
+    high: usize,
+    /// ...except for noise-rate percent of the vertices, which weigh noise-weight
+    #[arg(long, default_value_t = 100)]
+    noise_weight: usize,
+    #[arg(long, default_value_t = 10)]
+    noise_rate: u32,
+    /// Edge weights are drawn from [edge-low, edge-high)...
+    #[arg(long, default_value_t = 1)]
+    edge_low: usize,
+    #[arg(long, default_value_t = 2)]
+    edge_high: usize,
+    /// ...except for edge-noise-rate percent of the links, which weigh edge-noise-weight
+    #[arg(long, default_value_t = 1)]
+    edge_noise_weight: usize,
+    #[arg(long, default_value_t = 0)]
+    edge_noise_rate: u32,
 }
 
-/*impl<T:Debug + Display + Copy + Hash + Eq> Edge_py<T> {
-    fn clone(&self) -> Self {
-        Edge_py {
-            start: self.start,
-            end: self.end,
-            weight: self.weight,
-        }
-    }
-}*/
-
-impl<T:Debug + Display + Copy + Hash + Eq> Clone for Edge_py<T> {
-    fn clone(&self) -> Self {
-        Edge_py {
-            start: self.start,
-            end: self.end,
-            weight: self.weight,
+impl WeightArgs {
+    fn weights(&self) -> Weights {
+        Weights {
+            node: WeightNoise { low: self.low, high: self.high, noise_weight: self.noise_weight, noise_rate: self.noise_rate },
+            edge: WeightNoise { low: self.edge_low, high: self.edge_high, noise_weight: self.edge_noise_weight, noise_rate: self.edge_noise_rate },
         }
     }
 }
 
-fn MST<T:Debug + Display + Copy + Hash + Eq> (edges: &mut Vec::<Edge_py<T>>) -> Vec::<Edge_py<T>> {
-    let mut mst = Vec::<Edge_py<T>>::new();
-    edges.sort_by_key(|x| x.weight);
-    let mut v_set = HashMap::<T, Option<usize>>::new();//使用空value的hashmap作为集合
-    for e in edges.iter() {//直接使用edge调用into_iter，发生所有权转移
-        v_set.insert(e.start, None);
-        v_set.insert(e.end, None);
-    }
-    let V = v_set.keys().into_iter().map(|&x| x).collect();
-    let mut UF = ArrayUnion::new_and_init(V);
-    for e in edges.iter() {
-        let u_group = UF.find(e.start);
-        let v_group = UF.find(e.end);
-        
-        if u_group != v_group {
-            mst.push(e.clone());
-            let tag = UF.try_union(u_group, v_group);
-        }
-    }
-    mst
+#[derive(Args)]
+struct GraphInput {
+    /// Graph file; the format follows the extension
+    #[arg(short, long)]
+    input: PathBuf,
+    /// vertex,weight CSV file; vertices not listed weigh 1
+    #[arg(long)]
+    weights: Option<PathBuf>,
 }
 
-fn partition1<T:Debug + Display + Copy + Hash + Eq> (v_edges: &HashMap::<T, Vec::<Edge_py<T>>>, k: usize) 
-                    ->Vec::<(T, (usize, Edge_py<T>))> {
-    //对相同起点的边进行随机划分，并分配partition_key
-    let mut out = Vec::<(T, (usize, Edge_py<T>))>::new();
-    let mut rng = thread_rng();
-    let partition_key =  rng.gen_range(0, k);
-    for (v, edges) in v_edges.iter() {
-        for e in edges {
-            out.push((e.end, (partition_key, e.clone())));
-        }
-    }
-    out
+#[derive(Args)]
+struct ResultOutput {
+    #[arg(long, value_enum, default_value_t = OutputFormat::Json)]
+    format: OutputFormat,
+    /// Result file; standard output if omitted
+    #[arg(short, long)]
+    output: Option<PathBuf>,
 }
 
-fn group_by_end<T:Debug + Display + Copy + Hash + Eq> (edges: Vec::<(T, (usize, Edge_py<T>))>) -> HashMap::<T, Vec::<(usize, Edge_py<T>)>> {
-    let mut out = HashMap::<T, Vec::<(usize, Edge_py<T>)>>::new();
-    for edge in edges {
-        if out.contains_key(&edge.0) {
-            out.get_mut(&edge.0).unwrap().push((edge.1.0, edge.1.1));
-        } else {
-            out.insert(edge.0, vec!((edge.1.0, edge.1.1)));
-        }
-    }
-    out
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum LinkageKind {
+    Min,
+    Max,
+    Average,
+    Jaccard,
+    Cosine,
 }
 
-fn group_and_MST<T:Debug + Display + Copy + Hash + Eq> (edges: Vec::<((usize, usize), Edge_py<T>)>) -> Vec::<Edge_py<T>> {
-    let mut cluster_edges = HashMap::<(usize, usize), Vec::<Edge_py<T>>>::new();
-    let mut mst = Vec::<Edge_py<T>>::new();
-    for e in edges {
-        if cluster_edges.contains_key(&e.0) {
-            cluster_edges.get_mut(&e.0).unwrap().push(e.1);
-        } else {
-            cluster_edges.insert(e.0, vec!(e.1));
-        }
-    }
-
-    for (_, mut ed) in cluster_edges {
-        //println!("before MST is: {}", e.len());
-        let mut mst_output = MST(&mut ed);
-
-        //println!("after MST is: {}", mst_output.len());
-        mst.append(&mut mst_output);
-    }
-    mst
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum HubKind {
+    Skip,
+    Sample,
 }
 
-fn partition2<T:Debug + Display + Copy + Hash + Eq> (v_edges: &HashMap::<T, Vec::<(usize, Edge_py<T>)>>, k: usize) 
-                    -> Vec::<((usize, usize), Edge_py<T>)> {
-    //对相同终点的边进行随机划分，并分配first_partition
-    let mut out = Vec::<((usize, usize), Edge_py<T>)>::new();
-    for (v, edges) in v_edges.iter() {
-        let mut rng = thread_rng();
-        let partition_key =  rng.gen_range(0, k);
-        for e in edges {
-            let first_partition = e.0;
-            let edge = e.1.clone();
-            out.push(((first_partition, partition_key), edge));
-        }
-    }
-    out
+#[derive(Args)]
+struct ClusterArgs {
+    #[command(flatten)]
+    graph: GraphInput,
+    #[arg(long, value_enum, default_value_t = LinkageKind::Min)]
+    linkage: LinkageKind,
+    /// Stop once at most this many clusters are left
+    #[arg(long, default_value_t = 10)]
+    threshold: usize,
+    /// Merge fragments that are much lighter than the rest after every round
+    #[arg(long)]
+    fragment_process: bool,
+    #[arg(long, default_value_t = 5)]
+    max_rounds: u32,
+    /// Stop once a round merges away less than this fraction of the clusters
+    #[arg(long, default_value_t = 0.0)]
+    min_progress: f64,
+    /// Do not start another round after this many milliseconds
+    #[arg(long)]
+    time_budget_ms: Option<u64>,
+    /// Keep merging pairwise until exactly `threshold` clusters are left
+    #[arg(long)]
+    exact_k: bool,
+    /// vertex,vertex CSV file of pairs that must end up in the same cluster
+    #[arg(long)]
+    must_link: Option<PathBuf>,
+    /// vertex,vertex CSV file of pairs that must not end up in the same cluster
+    #[arg(long)]
+    cannot_link: Option<PathBuf>,
+    /// No merge may create a cluster heavier than this
+    #[arg(long)]
+    max_cluster_weight: Option<usize>,
+    /// Cluster the common-neighbour graph instead of the graph itself
+    #[arg(long)]
+    common_neighbors: bool,
+    /// Treat neighbours shared by more than this many vertices as hubs when building the common-neighbour graph
+    #[arg(long)]
+    max_shared: Option<usize>,
+    #[arg(long, value_enum, default_value_t = HubKind::Sample)]
+    hubs: HubKind,
+    /// Sparsification target, see `sparsify`
+    #[arg(long, default_value_t = 0.4)]
+    epsilon: f32,
+    #[arg(long, default_value_t = 2021)]
+    seed: u64,
+    #[command(flatten)]
+    out: ResultOutput,
 }
 
-pub fn make_random_graph_matrix (verticle: usize) -> (Array2::<usize>, Vec::<Edge_py<usize>>) {
-    //随机生成一个图，usize类型不满足ndarray::IntoDimension特征，故verticle用usize
-    let mut data = Array2::<usize>::zeros((verticle, verticle));
-    let mut i = 0;
-    let mut j = 0;
-    let mut edges = Vec::<Edge_py<usize>>::new();
-    while i < verticle {
-        j = 0;
-        while j < verticle {
-            if i == j {
-                data.row_mut(i)[j] = 0;
-                j += 1;
-            } else if j < i {
-                data.row_mut(i)[j] = data.row(j)[i];
-                j += 1;
-            } else {
-                let rand_number1 = rand::thread_rng().gen_range(1, j-i+1);
-                if rand_number1 != 1 {
-                    data.row_mut(i)[j] = 10000;
-                } else {
-                    let rand_number2 = rand::thread_rng().gen_range(1, j-i+1);
-                    data.row_mut(i)[j] = rand_number2;
-                    edges.push(Edge_py {
-                        start: i,
-                        end: j,
-                        weight: rand_number2 as usize
-                    });
-                    edges.push(Edge_py {
-                        start: j,
-                        end: i,
-                        weight: rand_number2 as usize
-                    })
-                    }
-                j += 1;
-            }
-        }
-        i += 1;
-    }
-    //print_edges(&edges);
-    (data, edges)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum RankSwapKind {
+    None,
+    Near,
+    Rank,
+    Greedy,
 }
 
-pub fn print_edges<T:Debug + Display + Copy + Hash + Eq> (edges: &Vec::<Edge_py<T>>) {
-    for edge in edges {
-        println!("start:{}, end:{}, weight:{}" ,edge.start, edge.end, edge.weight);
-    }
+#[derive(Args)]
+struct PartitionArgs {
+    #[command(flatten)]
+    graph: GraphInput,
+    #[arg(short, long)]
+    k: usize,
+    #[arg(long, value_enum, default_value_t = RankSwapKind::Rank)]
+    rank_swap: RankSwapKind,
+    /// Intervals per part for RankSwap; defaults to sqrt(vertices / k)
+    #[arg(long)]
+    intervals: Option<usize>,
+    /// Lighter parts each part swaps with per sweep in greedy RankSwap
+    #[arg(long, default_value_t = 2)]
+    partners: usize,
+    /// Refine the DP cut with Fiduccia–Mattheyses moves, letting parts grow this fraction above the mean node weight
+    #[arg(long)]
+    refine: Option<f64>,
+    #[arg(long, default_value_t = 8)]
+    refine_passes: usize,
+    #[arg(long, default_value_t = 10)]
+    threshold: usize,
+    #[arg(long)]
+    no_fragment_process: bool,
+    #[arg(long)]
+    no_common_neighbors: bool,
+    #[arg(long, default_value_t = 0.4)]
+    epsilon: f32,
+    #[arg(long, default_value_t = 2021)]
+    seed: u64,
+    #[command(flatten)]
+    out: ResultOutput,
 }
 
-pub fn make_cluster<T:Debug + Display + Copy + Hash + Eq>(epsilon: f32, mut edges: Vec::<Edge_py<T>>, cluster_threshold: usize, FragmentProcess: bool,
-    CommonNeighborCluster: bool) -> Affinity<T> {
-    let mut v_set = HashSet::<T>::new();
-    for e in edges.iter() {
-        v_set.insert(e.start);
-        v_set.insert(e.end);
-    }//对edges扫描以计算v_set可以只算一次，待优化
-    let n = v_set.len() as f32;
-    let mut m = edges.len() as f32;
-    let mut c: f32 = m.ln().ceil() / n.ln().ceil() - 1.0;
-    //println!("total edges is:{}", edges.len());
-    /*while c > epsilon {
-        let k = (n.powf((c - epsilon) / 2.0).floor()) as usize;
-        c = m.ln().ceil() / n.ln().ceil() - 1.0;
-        let eev = edges_of_every_vertexs(&edges);
-        let half_partition = partition1(&eev, k);
-        let same_start = group_by_end(half_partition);
-        let full_partition = partition2(&same_start, k);
-        edges = group_and_MST(full_partition);
-        m = edges.len() as f32;
-        println!("total edges of MST is:{}, present c is: {}", edges.len(), c);
-    }*/
-    let mut af = Affinity::new_and_init(&edges, cluster_threshold);
-    af.clustering(FragmentProcess, CommonNeighborCluster);//CommonNeighborCluster为true表示对commonneighbor进行聚合
-    //af.print_all_clusters();
-    af
+#[derive(Args)]
+struct EvaluateArgs {
+    #[command(flatten)]
+    graph: GraphInput,
+    /// vertex,part CSV file, as written by `partition --format csv`
+    #[arg(long)]
+    parts: PathBuf,
+    /// vertex,community CSV file with the ground truth, as written by `generate --communities`;
+    /// NMI, ARI, purity and F1 against it are added to the JSON output
+    #[arg(long)]
+    truth: Option<PathBuf>,
+    #[command(flatten)]
+    out: ResultOutput,
 }
 
-
-
-
-
-
-#[derive(Debug, Copy, Clone, Hash)]
-struct Node {
-    name: usize,
-    weight: usize,
+fn format_of(path: &Path) -> Result<GraphFormat, Box<dyn Error>> {
+    GraphFormat::from_path(path).ok_or_else(|| format!("cannot tell the graph format of {}", path.display()).into())
 }
 
-impl PartialEq for Node {
-    fn eq(&self, others: &Self) -> bool {
-        self.name == others.name && self.weight == others.weight
-    }
+struct InputGraph {
+    edges: Vec<Edge<usize>>,
+    weights: VertexWeights,
 }
 
-impl Eq for Node { }
-impl Display for Node { 
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "({}, {})", self.name, self.weight)
-    }
- }
-
-impl Node {
-    fn new(Name: usize, random_weight: usize) -> Self {
-        Node {
-            name: Name,
-            weight: random_weight,
-        }
-    }
+fn read_input(input: &GraphInput) -> Result<InputGraph, Box<dyn Error>> {
+    let edges = read_graph_file(&input.input, format_of(&input.input)?).map_err(|e| format!("{}: {}", input.input.display(), e))?.edges;
+    let weights = match &input.weights {
+        Some(path) => table::read_vertex_weights(path)?,
+        None => HashMap::new(),
+    };
+    Ok(InputGraph { edges, weights })
 }
 
-fn make_fat_tree(k: usize, low: usize, high: usize, noise_weight: usize, noise_rate: u32)
-    -> Vec::<Edge_py<Node>> {
-    let mut edges = Vec::<Edge_py<Node>>::new();
-    let mut kernel_node = Vec::<Node>::new();
-    let mut pods = Vec::<(Vec::<Node>, Vec::<Node>)>::new();
-    let mut server_node = Vec::<Vec::<Vec<Node>>>::new();
-    let mut count = 0;
-    //生成节点
-    for _ in 0..k*k/4 {
-        let r_w: usize;
-        if rand::thread_rng().gen_ratio(noise_rate, 100) {
-            r_w = noise_weight;
-        } else {
-            r_w = rand::thread_rng().gen_range(low, high);
+fn generate(kind: GraphKind) -> Result<(), Box<dyn Error>> {
+    let rng = |out: &GraphOutput| StdRng::seed_from_u64(out.seed);
+    let (topology, out) = match kind {
+        GraphKind::FatTree { k, weights, out } => (generators::fat_tree(k, &weights.weights(), &mut rng(&out)), out),
+        GraphKind::LeafSpine { spines, leaves, hosts, weights, out } => {
+            (generators::leaf_spine(spines, leaves, hosts, &weights.weights(), &mut rng(&out)), out)
         }
-        let node = Node::new(count, r_w);
-        kernel_node.push(node);
-        count += 1;
-    }
-    for _ in 0..k {
-        let mut pod = (Vec::<Node>::new(), Vec::<Node>::new());
-        for _ in 0..k/2 {
-            let r_w: usize;
-            if rand::thread_rng().gen_ratio(noise_rate, 100) {
-                r_w = noise_weight;
-            } else {
-                r_w = rand::thread_rng().gen_range(low, high);
-            }
-            let node = Node::new(count, r_w);
-            pod.0.push(node);
-            count += 1;
+        GraphKind::Torus { dims, weights, out } => (generators::torus(&dims, &weights.weights(), &mut rng(&out)), out),
+        GraphKind::Dragonfly { groups, routers, hosts, weights, out } => {
+            (generators::dragonfly(groups, routers, hosts, &weights.weights(), &mut rng(&out)), out)
         }
-        for _ in 0..k/2 {
-            let r_w: usize;
-            if rand::thread_rng().gen_ratio(noise_rate, 100) {
-                r_w = noise_weight;
-            } else {
-                r_w = rand::thread_rng().gen_range(low, high);
-            }
-            let node = Node::new(count, r_w);
-            pod.1.push(node);
-            count += 1;
+        GraphKind::ErdosRenyi { vertices, p, weights, out } => {
+            (generators::erdos_renyi(vertices, p, &weights.weights(), &mut rng(&out)), out)
         }
-        pods.push(pod);
-    }
-    for _ in 0..k {
-        let mut pod_servers = Vec::<Vec::<Node>>::new();
-        for _ in 0..k/2 {
-            let mut access_servers = Vec::<Node>::new();
-            for _ in 0..k/2 {
-                let r_w: usize;
-            if rand::thread_rng().gen_ratio(noise_rate, 100) {
-                r_w = noise_weight;
-            } else {
-                r_w = rand::thread_rng().gen_range(low, high);
-            }
-                let node = Node::new(count, r_w);
-                access_servers.push(node);
-                count += 1;
-            }
-            pod_servers.push(access_servers);
+        GraphKind::BarabasiAlbert { vertices, m, weights, out } => {
+            (generators::barabasi_albert(vertices, m, &weights.weights(), &mut rng(&out)), out)
         }
-        server_node.push(pod_servers);
-    }
-    //生成节点完毕
-    //开始生成edge
-    for i in 0..k*k/4{
-        for j in 0..k {
-            let edge = Edge_py {
-                start: kernel_node[i],
-                end: pods[j].0[i*2/k],
-                weight: 1,
-            };
-            edges.push(edge);
-            let edge = Edge_py {
-                start: pods[j].0[i*2/k],
-                end: kernel_node[i],
-                weight: 1,
-            };
-            edges.push(edge);
+        GraphKind::Sbm { sizes, p_in, p_out, weights, out } => {
+            (generators::stochastic_block_model(&sizes, p_in, p_out, &weights.weights(), &mut rng(&out)), out)
         }
-    }
-    for i in 0..k {
-        for j in 0..k/2 {
-            for m in 0..k/2 {
-                let edge = Edge_py {
-                    start: pods[i].0[j],
-                    end: pods[i].1[m],
-                    weight: 1,
-                };
-                edges.push(edge);
-                let edge = Edge_py {
-                    start: pods[i].1[m],
-                    end: pods[i].0[j],
-                    weight: 1,
-                };
-                edges.push(edge);
-            }
+        GraphKind::Random { vertices, out } => {
+            let (edges, vertex_weights) = generate::make_random_graph(vertices, &mut rng(&out));
+            (Topology { edges, vertex_weights, communities: None }, out)
         }
-    }
-    for i in 0..k {
-        for j in 0..k/2 {
-            for m in 0..k/2 {
-                let edge = Edge_py {
-                    start: pods[i].1[j],
-                    end: server_node[i][j][m],
-                    weight: 1,
-                };
-                edges.push(edge);
-                let edge = Edge_py {
-                    start: server_node[i][j][m],
-                    end: pods[i].1[j],
-                    weight: 1,
-                };
-                edges.push(edge);
-            }
+        GraphKind::File { input, output } => {
+            let file = read_graph_file(&input, format_of(&input)?)?;
+            write_graph_file(&output, file.vertices, &file.edges, format_of(&output)?)?;
+            return Ok(());
         }
+    };
+    write_graph_file(&out.output, topology.vertex_count(), &topology.edges, format_of(&out.output)?)?;
+    if let Some(path) = &out.weights {
+        table::write_vertex_weights(path, &topology.vertex_weights)?;
+    }
+    if let Some(path) = &out.communities {
+        let communities = topology.communities.as_ref().ok_or("this generator has no planted communities")?;
+        table::write_column(path, "community", communities)?;
     }
-    println!("The count is: {}", count);
-    edges
+    Ok(())
 }
 
-fn make_random_graph (verticle: usize) ->  Vec::<Edge_py<Node>> {//随机生成一个图,矩阵中1表示存在
-    //let mut data = Array2::<Node>::zeros((verticle, verticle));
-    let mut i = 0;
-    let mut j = 0;
-    let mut edges = Vec::<Edge_py<Node>>::new();
-    let mut random_weight = Vec::<usize>::new();
-    for _ in 0..verticle/10 {
-        let r_w = rand::thread_rng().gen_range(30, 51);
-        random_weight.push(r_w);
-    }
-    for _ in verticle/10..verticle {
-        let r_w = rand::thread_rng().gen_range(1, 6);
-        random_weight.push(r_w);
-    }
-    while i < verticle {
-        j = 0;
-        while j < verticle {
-            if i == j {
-                //data.row_mut(i)[j] = 0;
-                j += 1;
-            } else if j < i {
-                //data.row_mut(i)[j] = data.row(j)[i];
-                j += 1;
-            } else {
-                let rand_number1 = rand::thread_rng().gen_range(1, j-i+1);
-                if rand_number1 != 1 {
-                    //data.row_mut(i)[j] = 0;
-                } else {
-                    edges.push(Edge_py {
-                        start: Node::new(i, random_weight[i]),
-                        end: Node::new(j, random_weight[j]),
-                        weight: 1
-                    });
-                    edges.push(Edge_py {
-                        start: Node::new(j, random_weight[j]),
-                        end: Node::new(i, random_weight[i]),
-                        weight: 1
-                    })
-                    }
-                j += 1;
-            }
-        }
-        i += 1;
-    }
-    //println!("the origional edges is {}", edges.len());
-    //affinity_clustering::print_edges(&edges);
-    edges
+#[derive(Serialize)]
+struct ClusterResult {
+    clusters: Vec<Vec<usize>>,
+    rounds: usize,
+    round_stats: Vec<RoundStats>,
+    stop: StopReason,
+    converged: bool,//没有收敛时为最后一轮的cluster
+    final_merges: usize,
+    #[serde(skip_serializing_if = "ConstraintReport::is_satisfied")]
+    unsatisfied: ConstraintReport<usize>,
+    elapsed_ms: u128,
 }
 
-fn get_hash_edges(edges: &Vec::<Edge_py<Node>>) -> HashMap::<(Node, Node), usize> {
-    let mut hash_edges = HashMap::<(Node, Node), usize>::new();
-    for edge in edges {
-        hash_edges.insert((edge.start, edge.end), edge.weight);
-    }
-    hash_edges
+//没有收敛到threshold时沿用已经形成的cluster
+fn run_linkage<L: Linkage<usize>>(
+    graph: &Graph<usize>,
+    args: &ClusterArgs,
+    constraints: &Constraints<usize>,
+    linkage: &L,
+    stats: &mut Vec<RoundStats>,
+) -> Result<(Affinity<usize>, ClusteringReport), ClusteringError> {
+    //输入没有边或稀疏化之后没有边时图为空，clamp的上界会小于下界
+    if graph.vertex_count() == 0 {
+        return Err(ClusteringError::EmptyGraph);
+    }
+    let mut af = Affinity::new_and_init(graph, args.threshold.clamp(1, graph.vertex_count()))?;
+    af.set_constraints(constraints)?;
+    let config = ClusteringConfig {
+        fragment_process: args.fragment_process,
+        max_rounds: args.max_rounds,
+        min_progress: args.min_progress,
+        time_budget: args.time_budget_ms.map(Duration::from_millis),
+        exact_k: args.exact_k,
+    };
+    let report = af.clustering_with_config(&config, linkage, |s| stats.push(*s))?;
+    Ok((af, report))
 }
 
-fn find_common_neighbors<T: Eq + Hash + Copy + Debug + Display>(edges: &Vec<Edge_py<T>>) -> Vec::<Edge_py<T>>{//转换为以common neighbor为边权的图
-    let mut neighbors_of_vertex = HashMap::<T, Vec::<T>>::new();
-    let mut common_neighbor_reverse = HashMap::<T, Vec::<T>>::new();
-    let mut edges_of_common_neighbors = HashMap::<(T, T), usize>::new();
-    let mut new_edges = Vec::<Edge_py<T>>::new();
-    let mut hash_edges = HashSet::<(T, T)>::new();
-    for e in edges {
-        if neighbors_of_vertex.contains_key(&e.start) {
-            neighbors_of_vertex.get_mut(&e.start).unwrap().push(e.end);
-        } else {
-            neighbors_of_vertex.insert(e.start, vec!(e.end));
-        }
-    }
-    for (vertex, neighbors) in neighbors_of_vertex {
-        for neighbor in neighbors {
-            if common_neighbor_reverse.contains_key(&neighbor) {
-                common_neighbor_reverse.get_mut(&neighbor).unwrap().push(vertex);
-            } else {
-                common_neighbor_reverse.insert(neighbor, vec!(vertex));
-            }
-        }
-    }
-    for (_, vertexs) in common_neighbor_reverse {
-        for vertex1 in vertexs.iter() {
-            for vertex2 in vertexs.iter() {
-                if vertex1 != vertex2 {
-                    if edges_of_common_neighbors.contains_key(&(*vertex1, *vertex2)) {
-                        *edges_of_common_neighbors.get_mut(&(*vertex1, *vertex2)).unwrap() += 1;
-                    } else {
-                        edges_of_common_neighbors.insert((*vertex1, *vertex2), 1);
-                    }
-                }
-            }
-        }
-    }
-    for (pair, weight) in edges_of_common_neighbors {
-        new_edges.push(Edge_py {
-            start: pair.0,
-            end: pair.1,
-            weight: weight,
-        });
-        hash_edges.insert(pair);
-    }
-    //println!("the common neighbor edges is {}", new_edges.len());
-    //affinity_clustering::print_edges(&new_edges);
-    new_edges
+fn cluster(args: ClusterArgs) -> Result<(), Box<dyn Error>> {
+    let InputGraph { edges, weights } = read_input(&args.graph)?;
+    let start = Instant::now();
+    let edges = if args.common_neighbors {
+        let hubs = match args.hubs {
+            HubKind::Skip => HubPolicy::Skip,
+            HubKind::Sample => HubPolicy::Sample,
+        };
+        neighborhood::common_neighbors(&edges, &NeighborhoodOptions { max_shared: args.max_shared, hubs, seed: args.seed })
+    } else {
+        edges
+    };
+    let (edges, _) = sparsify(edges, args.epsilon, &mut StdRng::seed_from_u64(args.seed));
+    let graph = Graph::from_edges(&edges)?.with_vertex_weights(|v| weights.get(v).copied().unwrap_or(1))?;
+    let pairs = |path: &Option<PathBuf>| path.as_deref().map_or(Ok(Vec::new()), table::read_pairs);
+    let constraints = Constraints { must_link: pairs(&args.must_link)?, cannot_link: pairs(&args.cannot_link)?, max_cluster_weight: args.max_cluster_weight };
+    let mut round_stats = Vec::new();
+    let (mut af, report) = match args.linkage {
+        LinkageKind::Min => run_linkage(&graph, &args, &constraints, &Min, &mut round_stats)?,
+        LinkageKind::Max => run_linkage(&graph, &args, &constraints, &Max, &mut round_stats)?,
+        LinkageKind::Average => run_linkage(&graph, &args, &constraints, &Average, &mut round_stats)?,
+        LinkageKind::Jaccard => run_linkage(&graph, &args, &constraints, &Jaccard, &mut round_stats)?,
+        LinkageKind::Cosine => run_linkage(&graph, &args, &constraints, &Cosine, &mut round_stats)?,
+    };
+    let result = ClusterResult {
+        clusters: af.clusters(),
+        rounds: af.hierarchy().depth(),
+        round_stats,
+        stop: report.stop,
+        converged: report.clusters <= args.threshold,
+        final_merges: report.final_merges,
+        unsatisfied: af.constraint_report(),
+        elapsed_ms: start.elapsed().as_millis(),
+    };
+
+    let mut writer = table::open_output(args.out.output.as_deref())?;
+    match args.out.format {
+        OutputFormat::Json => table::write_json(&mut *writer, &result)?,
+        OutputFormat::Csv => {
+            let rows: Vec<Vec<String>> = result
+                .clusters
+                .iter()
+                .enumerate()
+                .flat_map(|(c, cluster)| cluster.iter().map(move |v| vec![v.to_string(), c.to_string()]))
+                .collect();
+            table::write_csv(&mut *writer, &["vertex", "cluster"], &rows)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
 }
 
-fn RankSwap(line: Vec::<Node>, k: usize, r: usize, q: Vec::<usize>) -> Vec::<Node> {
-    let mut divided_line = Vec::<Vec::<Vec::<Node>>>::new();
-    let mut cut_size = Vec::<usize>::new();
-    //line划分为k个partitions， partition划分为r个intervals，并计算每个partition的cut size
-    for i in 0..q.len()-1 {
-        let mut partition_size = 0;
-        let mut partition = Vec::<Vec::<Node>>::new();
-        let mut interval_index = Vec::<usize>::new();
-        for j in 0..r+1 {
-            interval_index.push(q[i] + ((j*(q[i+1] - q[i])) as f32 / r as f32).floor() as usize);
-        }
-        for j in 0..r {
-            let mut interval = Vec::<Node>::new();
-            for k in interval_index[j]..interval_index[j+1] {
-                interval.push(line[k].clone());
-                partition_size += line[k].weight;
-            }
-            interval.sort_by_key(|x| x.weight);
-            interval.reverse();
-            partition.push(interval);
-        }
-        divided_line.push(partition);
-        cut_size.push(partition_size);
-    }
-    //println!("Present max cut size is: {}", find_max_size(&cut_size));
-    println!("Begin to optimize the cut size");
-    //划分完毕，开始配对
-    /*let mut pairs = Vec::<HashMap::<usize, usize>>::new();
-    for i in 0..divided_line.len()/2 {
-        //随机配对相邻partition的intervals
-        let mut hash_pair = HashMap::<usize, usize>::new();//intervals的配对表
-        for j in 0..r {
-            loop {
-                let random_pair = rand::thread_rng().gen_range(0, r);
-                if !hash_pair.contains_key(&random_pair) {
-                    hash_pair.insert(random_pair, j);
-                    break;
-                }
-            }
-        }
-        pairs.push(hash_pair);
-    }*/
-    let mut hash_pair = HashMap::<usize, usize>::new();//intervals的配对表
-        for j in 0..r {
-            loop {
-                let random_pair = rand::thread_rng().gen_range(0, r);
-                if !hash_pair.contains_key(&random_pair) {
-                    hash_pair.insert(random_pair, j);
-                    break;
-                }
-            }
-        }
-    println!("randomly pair intervals completed!");
-    //配对interval完毕
-    //配对partition开始
-    let mut cut_size_copy = cut_size.clone();
-    let mut partition_size_rank = Vec::<usize>::new();
-    for i in 0..k {
-        let mut max = 0;
-        let mut max_position = 0;
-        for j in 0..k {
-            if cut_size_copy[j] > max {
-                max = cut_size_copy[j];
-                max_position = j;
-            }
-        }
-        partition_size_rank.push(max_position);
-        cut_size_copy[max_position] = 0;
-    }
-    let mut partition_pairs = HashMap::<usize, usize>::new();
-    for i in 0..k/2 {
-        partition_pairs.insert(partition_size_rank[i], partition_size_rank[k-i-1]);
-    }
-    //目前对partition进行排序，最大与最小配对
-    //如对partition进行相邻配对，使用下段
-    /*let mut partition_pairs = HashMap::<usize, usize>::new();
-    for i in 0..k/2 {
-        partition_pairs.insert(i*2, i*2+1);
-    }*/
-    
+#[derive(Serialize)]
+struct PartitionResult {
+    bounds: Vec<usize>,
+    round_stats: Vec<RoundStats>,
+    costs: Vec<usize>,
+    max_cost: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rank_swap: Option<RankSwapStats>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    refinement: Vec<RefinePass>,
+    parts: Vec<Vec<usize>>,
+    metrics: PartitionMetrics,
+    elapsed_ms: u128,
+}
 
-    let mut count = 0;
-    loop {
-        count += 1;
-        println!("After {} rounds swap ", count);
-        let mut flag = 0;//标志变量，为0表示此轮没有进行交换，终止循环
-        for (partition1, partition2) in &partition_pairs {
-            for (interval1, interval2) in &hash_pair {
-                for j in 0..divided_line[*partition1][*interval1].len() {
-                    let mut best_pair = Option::<usize>::None;
-                    let mut present_small_max_size = max(cut_size[*partition1], cut_size[*partition2]);
-                    for k in 0..divided_line[*partition2][*interval2].len() {                                                                               
-                        let imaginaty_max_size = max(cut_size[*partition1] - divided_line[*partition1][*interval1][j].weight + divided_line[*partition2][*interval2][k].weight, 
-                            cut_size[*partition2] - divided_line[*partition2][*interval2][k].weight + divided_line[*partition1][*interval1][j].weight);
-                        if imaginaty_max_size < present_small_max_size {//交换后能获得更小的maxsize
-                            best_pair = Some(k);
-                            present_small_max_size = imaginaty_max_size;
-                        }
-                    }
-                    if let Some(real_best_pair) = best_pair {//有可供交换的best pair, 交换两点
-                        flag = 1;
-                        let pre_weight = divided_line[*partition2][*interval2][real_best_pair].weight as i32;
-                        let post_weight = divided_line[*partition1][*interval1][j].weight as i32;
-                        let difference:usize = (pre_weight - post_weight).abs() as usize;
-                        if pre_weight > post_weight {//更新两个partition的cutsize
-                            cut_size[*partition1] += difference;
-                            cut_size[*partition2] -= difference;
-                        } else {
-                            cut_size[*partition1] -= difference;
-                            cut_size[*partition2] += difference;
-                        }
-                        let temp = divided_line[*partition1][*interval1][j];
-                        divided_line[*partition1][*interval1][j] = divided_line[*partition2][*interval2][real_best_pair];
-                        divided_line[*partition2][*interval2][real_best_pair] = temp;
-                    }
-                }
-            }
-        }
-        if flag == 0 {
-            break;
-        }
-    }
-    //输出RankSwap处理后的序列
-    let mut adjusted_line = Vec::<Node>::new();
-    for partition in divided_line {
-        for interval in partition {
-            adjusted_line.append(&mut interval.clone());
-        }
-    }
-    adjusted_line
+fn partition_graph(args: PartitionArgs) -> Result<(), Box<dyn Error>> {
+    let InputGraph { edges, weights } = read_input(&args.graph)?;
+    let node_weight = |v: &usize| weights.get(v).copied().unwrap_or(1);
+    let start = Instant::now();
+    let graph = Graph::from_edges(&edges)?.with_vertex_weights(node_weight)?;
+    let intervals = args.intervals.unwrap_or_else(|| ((graph.vertex_count() / args.k.max(1)) as f64).sqrt() as usize).max(1);
+    let options = PartitionOptions {
+        epsilon: args.epsilon,
+        cluster_threshold: args.threshold,
+        fragment_process: !args.no_fragment_process,
+        common_neighbors: !args.no_common_neighbors,
+        rank_swap: match args.rank_swap {
+            RankSwapKind::None => None,
+            RankSwapKind::Near => Some(RankSwapOptions::Near { intervals }),
+            RankSwapKind::Rank => Some(RankSwapOptions::Rank { intervals }),
+            RankSwapKind::Greedy => Some(RankSwapOptions::Greedy { intervals, partners: args.partners }),
+        },
+        refine: args.refine.map(|balance_tolerance| RefineOptions { balance_tolerance, max_passes: args.refine_passes }),
+        seed: args.seed,
+    };
+    let mut round_stats = Vec::new();
+    let partitioning = partition_with_progress(&graph, args.k, &options, |s| round_stats.push(*s))?;
+    let elapsed_ms = start.elapsed().as_millis();
+    let parts = partitioning.parts();
+    let result = PartitionResult {
+        bounds: partitioning.bounds().to_vec(),
+        round_stats,
+        costs: partitioning.costs().to_vec(),
+        max_cost: partitioning.max_cost(),
+        rank_swap: partitioning.rank_swap().cloned(),
+        refinement: partitioning.refinement().to_vec(),
+        metrics: evaluate_weighted(&edges, &parts, node_weight)?,
+        parts,
+        elapsed_ms,
+    };
+
+    let mut writer = table::open_output(args.out.output.as_deref())?;
+    match args.out.format {
+        OutputFormat::Json => table::write_json(&mut *writer, &result)?,
+        OutputFormat::Csv => {
+            let rows: Vec<Vec<String>> = graph
+                .vertices()
+                .iter()
+                .map(|v| vec![v.to_string(), partitioning.part_of(v).unwrap().to_string()])
+                .collect();
+            table::write_csv(&mut *writer, &["vertex", "part"], &rows)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
 }
 
-fn q_list(k: usize) -> Vec::<usize>{
-    let mut hash_qs = HashSet::<usize>::new();
-    let mut present_k1 = k;
-    let mut present_k2 = k;
-    hash_qs.insert(k);
-    while present_k1 != 1 && present_k2 != 1 {
-        let mut hash_number = HashSet::<usize>::new();
-        hash_number.insert(present_k1/2);
-        hash_number.insert(present_k1 - present_k1/2);
-        hash_number.insert(present_k2/2);
-        hash_number.insert(present_k2 - present_k2/2);
-        if hash_number.len() == 1 {
-            present_k1 = *hash_number.iter().next().unwrap();
-            present_k2 = present_k1;
-            hash_qs.insert(present_k1);
-        } else {
-            let mut iters = hash_number.iter();
-            present_k1 = *iters.next().unwrap();
-            present_k2 = *iters.next().unwrap();
-            hash_qs.insert(present_k1);
-            hash_qs.insert(present_k2);
-        }
-    }
-    let mut qs:Vec::<usize> = hash_qs.into_iter().collect();
-    qs.sort();
-    println!("The q_list is {:?}", qs);
-    qs
+#[derive(Serialize)]
+struct EvaluateResult {
+    #[serde(flatten)]
+    metrics: PartitionMetrics,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    agreement: Option<Agreement>,
 }
 
-fn DynamicProgram(line: Vec::<Node>, k: usize, edges: HashMap::<(Node, Node), usize>) {
-    let mut node_position = HashMap::<Node, usize>::new();
-    for i in 0..line.len() {
-        node_position.insert(line[i], i);
-    }
-    //println!("the length of line is: {}", line.len());
-    //rintln!("the length oSf node_position is: {}", node_position.len());
-    let vertex_num = line.len();
-    let mut total_node_weight = 0;
-    let mut total_edge_weight = 0;
-    let mut J = Array3::<usize>::zeros((vertex_num, vertex_num, vertex_num));
-    //J存储从区间[i, j]到点k的边长度总和,以便后续计算使用
-    //初始化J
-    for i in 0..vertex_num {
-        total_node_weight += line[i].weight;
-    }
-    for ((start, end), edge_weight) in &edges {
-        total_edge_weight += edge_weight;
-        J[[node_position[start], node_position[start], node_position[end]]] = 1;
-    }
-    //J计算公式为J[i, j, k] = J[i, j-1, k] + J[j, j, k]
-    for length in 2..vertex_num {//length为该区间包括的点个数
-        for i in 0..vertex_num-length {
-            let j = i + length - 1;
-            for k in i+length..vertex_num {
-                J[[i, j, k]] = J[[i, j-1, k]] + J[[j, j, k]];
-            }
+//vertex,part形式的CSV按part分组
+fn read_groups(path: &Path) -> Result<Vec<Vec<usize>>, Box<dyn Error>> {
+    let mut groups = Vec::<Vec<usize>>::new();
+    for (v, p) in table::read_pairs(path)? {
+        if groups.len() <= p {
+            groups.resize(p + 1, Vec::new());
         }
+        groups[p].push(v);
     }
-    println!("table J has been completed");
+    Ok(groups)
+}
 
-    let mut D = Array2::<usize>::zeros((vertex_num, vertex_num));
-    //D存储在区间[i, j]内的边权总和，计算公式为D(i, j+1) = D(i, j) + J(i, j, j+1)
-    for i in 0..vertex_num-1 {
-        for j in i+1..vertex_num {
-            D[[i, j]] = D[[i, j-1]] + J[[i, j-1, j]];
-        }
-    }
-    println!("table D has been completed");
-    let mut B = Array2::<usize>::zeros((vertex_num, vertex_num));
-    //B存储在区间[i, j]内的点权总和，计算公式为B(i, j+1) = B(i, j) + w[j+1]
-    for i in 0..vertex_num {
-        B[[i, i]] = line[i].weight;
-    }//初始化B
-    for i in 0..vertex_num-1 {
-        for j in i+1..vertex_num {
-            B[[i, j]] = B[[i, j-1]] + line[j].weight;
-        }
-    }
-    println!("table B has been completed");
+fn evaluate(args: EvaluateArgs) -> Result<(), Box<dyn Error>> {
+    let InputGraph { edges, weights } = read_input(&args.graph)?;
+    let parts = read_groups(&args.parts)?;
+    let metrics = evaluate_weighted(&edges, &parts, |v| weights.get(v).copied().unwrap_or(1))?;
+    let agreement = match &args.truth {
+        Some(path) => Some(compare(&parts, &read_groups(path)?)?),
+        None => None,
+    };
+
+    let mut writer = table::open_output(args.out.output.as_deref())?;
+    match args.out.format {
+        OutputFormat::Json => table::write_json(&mut *writer, &EvaluateResult { metrics, agreement })?,
+        OutputFormat::Csv => {
+            let rows: Vec<Vec<String>> = metrics
+                .parts
+                .iter()
+                .enumerate()
+                .map(|(p, m)| {
+                    vec![
+                        p.to_string(),
+                        m.vertices.to_string(),
+                        m.node_weight.to_string(),
+                        m.internal_weight.to_string(),
+                        m.cut.to_string(),
+                        m.volume.to_string(),
+                        m.conductance.to_string(),
+                    ]
+                })
+                .collect();
+            table::write_csv(&mut *writer, &["part", "vertices", "node_weight", "internal_weight", "cut", "volume", "conductance"], &rows)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
 
-    let mut C = Array3::<usize>::zeros((vertex_num, vertex_num, vertex_num));
-    //C[i, j, k]存储从区间[i, k]到区间[k, j]的边长度总和
-    //初始化C
-    for i in 0..vertex_num-1 {
-        C[[i, i+1, i]] = J[[i, i, i+1]];
-    }
-    for i in 0..vertex_num-2 {
-        for j in i+2..vertex_num {
-            for cut_point in i..j {
-                C[[i, j, cut_point]] = C[[i, j-1, cut_point]] + J[[i, cut_point, j]];
-            }
-        }
-    }
-    println!("table C has been completed");
-    let mut A = Array3::<usize>::zeros((vertex_num, vertex_num, k+1));
-    let mut Ap = ArrayBase::<ndarray::OwnedRepr<Vec::<usize>>, ndarray::Dim<[usize; 3]>>::default((vertex_num, vertex_num, k+1));
-    //存储A(i, j, q)，即中间解, Ap存储切割位置
-    //初始化A
-    println!("The average weight of cluster is: {}", total_node_weight as f32/k as f32 + total_edge_weight as f32/k as f32);
-    for i in 0..vertex_num {
-        for j in i.. vertex_num {
-            A[[i, j, 1]] = B[[i, j]] + D[[i, j]] + C[[0, j, i]] + C[[i, vertex_num-1, j]];
-        }
-    }
-    println!("begin to dynamic programing");
-    //动态规划序列准备
-    let mut qs = q_list(k);
-    qs.remove(0);
-    //开始动态规划
-    //for q in 2..k+1 {
-    for q in qs {
-        println!("now running in q = {}", q);
-        let left = q/2;
-        let right = q-q/2;
-        for i in 0..vertex_num-1 {
-            for j in i+1..vertex_num {
-                if q <= j-i+1 {
-                    let mut min_cut_point = i;
-                    let mut min_cut_size = 10000000;
-                    for cut_point in i..j {
-                        if cut_point-i+1 >= left && j-cut_point+1 >= right {
-                            let cut_size_temp = max(A[[i, cut_point, left]], A[[cut_point+1, j, right]]);
-                            if cut_size_temp < min_cut_size {
-                                min_cut_size = cut_size_temp;
-                                min_cut_point = cut_point;
-                            }
-                        }
-                    }
-                    A[[i, j, q]] = min_cut_size;
-                    Ap[[i, j, q]] = Ap[[i, min_cut_point, left]].clone();
-                    Ap[[i, j, q]].push(min_cut_point);
-                    let mut right_part = Ap[[min_cut_point+1, j, right]].clone();
-                    Ap[[i, j, q]].append(&mut right_part);
-                }
-            }
-        }
-    }
-    println!("dynamic programing finished!");
-    //输出动态规划结果
-    println!("These cut point is: {:?}", Ap[[0, vertex_num-1, k]]);
-    let mut cluster_size = Vec::<usize>::new();
-    let mut cut_index = Vec::<usize>::new();
-    cut_index.push(0);
-    let mut temp = Ap[[0, vertex_num-1, k]].clone();
-    cut_index.append(&mut temp);
-    cut_index.push(vertex_num-1);
-    println!("The cut_index is: {:?}", cut_index);
-    let mut max_cluster_size = 0;
-    for i in 0..cut_index.len()-1 {
-        let mut present_cluster_size = 0;
-        if i == 0 {
-            present_cluster_size += C[[0, vertex_num-1, cut_index[1]]];
-        } else if i==cut_index.len()-2 {
-            present_cluster_size += C[[0, vertex_num-1, cut_index[i]]];
-        } else {
-            present_cluster_size += C[[0, cut_index[i+1], cut_index[i]]];
-            present_cluster_size += C[[cut_index[i], vertex_num-1, cut_index[i+1]]];
-        }
-        present_cluster_size += D[[cut_index[i], cut_index[i+1]]];
-        present_cluster_size += B[[cut_index[i], cut_index[i+1]]];
-        cluster_size.push(present_cluster_size);
-        if present_cluster_size > max_cluster_size {
-            max_cluster_size = present_cluster_size;
-        }
-    }
-    let mut mean:f32 = 0.0;
-    let mut variance:f32= 0.0;
-    for i in 0..cluster_size.len() {
-        mean += cluster_size[i] as f32 / cluster_size.len() as f32;
-    }
-    for i in 0..cluster_size.len() {
-        variance += (cluster_size[i] as f32 - mean).powf(2.0);
-    }
-    variance /= cluster_size.len() as f32;
-    let standard_deviation = variance.sqrt();
-    let coefficient_of_variation = standard_deviation / mean;
-    println!("The cut sizes is: {:?}", cluster_size);
-    println!("The mean cut size is: {}", mean);
-    println!("The max cut size is: {}", max_cluster_size);
-    println!("The coefficient of variance is: {}", coefficient_of_variation);
+//日志写到标准错误，标准输出只有结果
+fn init_logging(verbose: u8) {
+    let level = match verbose {
+        0 => return,
+        1 => Level::INFO,
+        2 => Level::DEBUG,
+        _ => Level::TRACE,
+    };
+    tracing_subscriber::fmt().with_max_level(level).with_writer(std::io::stderr).init();
 }
 
-fn Combination(af: Affinity::<Node>, partition_number: usize, interval_len: usize, rank_swap: bool) -> Vec::<Node> {
-/*
-    //partition_number: finally clusters number predetermined
-    //interval_len: length of every interval of a partition
-    //rank_swap: weather to implement rank_swap algorithm
-*/
-    let mut q = Vec::<usize>::new();
-    let line = af.linear_embed();
-    for i in 0..partition_number+1 {
-        q.push(((i*line.len()) as f32 / partition_number as f32).floor() as usize);
-    }
-    if rank_swap {
-        let adjusted_line = RankSwap(line, partition_number, interval_len, q);
-        adjusted_line
-    } else {
-        line
+fn run(command: Command) -> Result<(), Box<dyn Error>> {
+    match command {
+        Command::Generate(kind) => generate(kind),
+        Command::Cluster(args) => cluster(args),
+        Command::Partition(args) => partition_graph(args),
+        Command::Evaluate(args) => evaluate(args),
     }
 }
 
-fn random_mock(graph_scale: usize, partition_number: usize, rank_swap: bool, rank_swap_mode: String,  cluster_threshold: usize) {
-/*
-    // cluster_threshold: usize, the threshold that stop affinity clustering
-    // rank_swap_mode: String ("near" or "rank"). "near" mode pair two intervals nearby, can approximately minimize edges cut off,
-       while "rank" mode pair the largest interval with the smallest one, may raise edges cut off.
-    // rank_swap: weather to implement rank_swap algorithm
-*/
-    let edges = make_fat_tree(graph_scale, 3, 6, 100, 10);
-    //let edges = make_random_graph(600);
-
-    let mut vertex_set = HashSet::<Node>::new();
-    for i in 0..edges.len() {
-        vertex_set.insert(edges[i].start);
-        vertex_set.insert(edges[i].end);
+fn main() {
+    let cli = Cli::parse();
+    init_logging(cli.verbose);
+    if let Err(e) = run(cli.command) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
     }
-    println!("The vertex number is: {}", vertex_set.len());
-
-    let sw = Stopwatch::start_new();
-    let hash_edges = get_hash_edges(&edges);    
-    let new_edges = find_common_neighbors(&edges);
-    let af = make_cluster(0.4,  new_edges, cluster_threshold, true, true);
-    let line_after_swap = Combination(af, partition_number, ((graph_scale.pow(3)/(4*partition_number))as f32).sqrt() as usize, rank_swap);
-    DynamicProgram(line_after_swap, partition_number, hash_edges);
-    println!("The running time is:{}", sw.elapsed_ms());
 }
 
-fn main() {
-    random_mock(12, 16, true, "rank".to_string(), 10);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn run_args(args: &[&str]) -> Result<(), Box<dyn Error>> {
+        let cli = Cli::try_parse_from(std::iter::once("affinity_test").chain(args.iter().copied()))?;
+        run(cli.command)
+    }
+
+    #[test]
+    fn generate_partition_and_evaluate_round_trip() {
+        let dir = std::env::temp_dir().join(format!("affinity_test_round_trip_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        let (graph, weights, truth, parts, metrics) = (path("sbm.graph"), path("weights.csv"), path("truth.csv"), path("parts.csv"), path("metrics.json"));
+
+        run_args(&["generate", "sbm", "--sizes", "30,30,30", "--p-in", "0.5", "--p-out", "0.01", "-o", &graph, "--weights", &weights, "--communities", &truth]).unwrap();
+        run_args(&["partition", "-i", &graph, "--weights", &weights, "-k", "3", "--refine", "0.1", "--format", "csv", "-o", &parts]).unwrap();
+        let assigned = table::read_pairs(Path::new(&parts)).unwrap();
+        let mut vertices: Vec<usize> = assigned.iter().map(|&(v, _)| v).collect();
+        vertices.sort_unstable();
+        assert_eq!(vertices, (0..90).collect::<Vec<_>>());
+        assert!(assigned.iter().all(|&(_, p)| p < 3));
+
+        run_args(&["evaluate", "-i", &graph, "--weights", &weights, "--parts", &parts, "--truth", &truth, "-o", &metrics]).unwrap();
+        let result: serde_json::Value = serde_json::from_str(&fs::read_to_string(&metrics).unwrap()).unwrap();
+        //点和点权经过三个命令后都没有丢失
+        let parts = result["parts"].as_array().unwrap();
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts.iter().map(|p| p["vertices"].as_u64().unwrap()).sum::<u64>(), 90);
+        let total: usize = table::read_vertex_weights(Path::new(&weights)).unwrap().values().sum();
+        assert_eq!(parts.iter().map(|p| p["node_weight"].as_u64().unwrap()).sum::<u64>(), total as u64);
+        assert_eq!(result["agreement"]["vertices"], 90);
+        let nmi = result["agreement"]["nmi"].as_f64().unwrap();
+        assert!(nmi > 0.0 && nmi <= 1.0, "{}", result);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn malformed_tables_fail_the_command() {
+        let dir = std::env::temp_dir().join(format!("affinity_test_malformed_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        let (graph, weights) = (path("torus.csv"), path("weights.csv"));
+        run_args(&["generate", "torus", "--dims", "4,4", "-o", &graph]).unwrap();
+        fs::write(&weights, "vertex,weight\n0,2\n1;3\n").unwrap();
+        let err = run_args(&["partition", "-i", &graph, "--weights", &weights, "-k", "2"]).unwrap_err();
+        assert!(err.to_string().contains("weights.csv:3:"), "{}", err);
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
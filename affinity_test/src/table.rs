@@ -0,0 +1,111 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::mem;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    Json,
+    Csv,
+}
+
+//没有指定文件时写到标准输出
+pub fn open_output(path: Option<&Path>) -> io::Result<Box<dyn Write>> {
+    Ok(match path {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    })
+}
+
+pub fn write_json<T: Serialize>(writer: &mut dyn Write, value: &T) -> Result<(), Box<dyn Error>> {
+    serde_json::to_writer_pretty(&mut *writer, value)?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+pub fn write_csv(writer: &mut dyn Write, header: &[&str], rows: &[Vec<String>]) -> io::Result<()> {
+    writeln!(writer, "{}", header.join(","))?;
+    for row in rows {
+        writeln!(writer, "{}", row.join(","))?;
+    }
+    Ok(())
+}
+
+//本程序写出的两列CSV的表头，只能作为第一个非空、非注释的行出现
+const HEADERS: &[&str] = &["vertex,weight", "vertex,community", "vertex,part", "vertex,cluster", "vertex,vertex"];
+
+//读取两列整数的CSV，例如vertex,weight或vertex,part
+pub fn read_pairs(path: &Path) -> Result<Vec<(usize, usize)>, Box<dyn Error>> {
+    parse_pairs(BufReader::new(File::open(path)?), &path.display().to_string())
+}
+
+//跳过空行、#开头的行和开头的一行已知表头，其余不是两个整数的行都带行号报错
+fn parse_pairs<R: BufRead>(reader: R, name: &str) -> Result<Vec<(usize, usize)>, Box<dyn Error>> {
+    let mut pairs = Vec::new();
+    let mut first = true;
+    for (no, line) in reader.lines().enumerate() {
+        let line = line?;
+        let text = line.trim();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = text.split(',').map(str::trim).collect();
+        if mem::replace(&mut first, false) && HEADERS.contains(&fields.join(",").as_str()) {
+            continue;
+        }
+        if fields.len() != 2 {
+            return Err(format!("{}:{}: expected two columns, found {:?}", name, no + 1, text).into());
+        }
+        let parse = |s: &str| s.parse::<usize>().map_err(|e| format!("{}:{}: {:?}: {}", name, no + 1, s, e));
+        pairs.push((parse(fields[0])?, parse(fields[1])?));
+    }
+    Ok(pairs)
+}
+
+//点到点权的映射，没有列出的点权重为1
+pub type VertexWeights = HashMap<usize, usize>;
+
+pub fn read_vertex_weights(path: &Path) -> Result<VertexWeights, Box<dyn Error>> {
+    Ok(read_pairs(path)?.into_iter().collect())
+}
+
+pub fn write_vertex_weights(path: &Path, weights: &[usize]) -> Result<(), Box<dyn Error>> {
+    write_column(path, "weight", weights)
+}
+
+//写出vertex,<name>两列，values[v]为点v的值
+pub fn write_column(path: &Path, name: &str, values: &[usize]) -> Result<(), Box<dyn Error>> {
+    let mut writer = open_output(Some(path))?;
+    let rows: Vec<Vec<String>> = values.iter().enumerate().map(|(v, x)| vec![v.to_string(), x.to_string()]).collect();
+    write_csv(&mut *writer, &["vertex", name], &rows)?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Vec<(usize, usize)>, String> {
+        parse_pairs(text.as_bytes(), "pairs.csv").map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn headers_comments_and_blank_lines_are_skipped() {
+        assert_eq!(parse("# planted\n\nvertex, part\n0,1\n 1 , 0 \n\n# done\n"), Ok(vec![(0, 1), (1, 0)]));
+        assert_eq!(parse("3,4"), Ok(vec![(3, 4)]));
+    }
+
+    #[test]
+    fn malformed_rows_are_reported_with_their_line() {
+        //表头拼错时不再被当作表头跳过
+        assert_eq!(parse("vertx,part\n0,1\n"), Err("pairs.csv:1: \"vertx\": invalid digit found in string".to_string()));
+        assert!(parse("vertex,part\n0,1\nvertex,part\n").unwrap_err().starts_with("pairs.csv:3:"));
+        assert!(parse("0,1\nx1,2\n").unwrap_err().starts_with("pairs.csv:2: \"x1\""));
+        assert!(parse("0,1\n\n2,3,4\n").unwrap_err().starts_with("pairs.csv:3: expected two columns"));
+        assert!(parse("0,1\n-2,3\n").unwrap_err().starts_with("pairs.csv:2:"));
+    }
+}